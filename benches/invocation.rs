@@ -0,0 +1,80 @@
+//! Counts the heap allocations made by a guest call, to keep the cost of passing the invocation
+//! to the guest in check. Run it with `cargo bench --bench invocation`.
+//!
+//! The engine stands in for a real provider: like the `__guest_request` import of one, it
+//! retrieves the invocation once per call, and it answers with an empty response so that the
+//! count covers only the host's own work.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use wapc::{ModuleState, WapcHost, WebAssemblyEngineProvider};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Default)]
+struct RequestReadingEngine {
+    state: Option<Arc<ModuleState>>,
+}
+
+impl WebAssemblyEngineProvider for RequestReadingEngine {
+    fn init(&mut self, host: Arc<ModuleState>) -> Result<(), Box<dyn Error>> {
+        self.state = Some(host);
+        Ok(())
+    }
+
+    fn call(&mut self, _op_length: i32, _msg_length: i32) -> Result<i32, Box<dyn Error>> {
+        let state = self.state.as_ref().ok_or("not initialized")?;
+        let invocation = state.get_guest_request().ok_or("no guest request")?;
+        state.set_guest_response(Vec::with_capacity(0));
+        Ok(invocation.msg.len().min(1) as i32)
+    }
+
+    fn replace(&mut self, _module: &[u8]) -> Result<(), Box<dyn Error>> {
+        Err("not supported".into())
+    }
+}
+
+const CALLS: usize = 100_000;
+
+fn main() {
+    let host = WapcHost::new(Box::new(RequestReadingEngine::default()), |_, _, _, _, _| {
+        Ok(vec![])
+    })
+    .unwrap();
+    let payload = vec![7; 1024];
+    host.call("warmup", &payload).unwrap();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for _ in 0..CALLS {
+        host.call("echo", &payload).unwrap();
+    }
+    let elapsed = started.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{} calls: {:.2} allocations per call, {:?} per call",
+        CALLS,
+        allocations as f64 / CALLS as f64,
+        elapsed / CALLS as u32
+    );
+}
@@ -0,0 +1,49 @@
+//! Compares the throughput of copying large responses into guest memory with
+//! `write_bytes_to_memory` against the byte-by-byte loop it replaced. Run it with
+//! `cargo bench --bench memory`.
+
+use std::time::{Duration, Instant};
+
+use wapc::memory::write_bytes_to_memory;
+
+const RESPONSE_SIZES: [usize; 3] = [64 * 1024, 1024 * 1024, 16 * 1024 * 1024];
+const BYTES_PER_SIZE: usize = 256 * 1024 * 1024;
+
+/// The copy as providers used to make it, one byte at a time
+fn write_byte_by_byte(memory: &mut [u8], offset: u32, data: &[u8]) {
+    for (i, b) in data.iter().enumerate() {
+        memory[offset as usize + i] = *b;
+    }
+}
+
+/// MiB copied per second
+fn throughput(elapsed: Duration) -> f64 {
+    BYTES_PER_SIZE as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64()
+}
+
+fn main() {
+    for size in RESPONSE_SIZES.iter().copied() {
+        let response = vec![0x5a; size];
+        let mut memory = vec![0; size + 8];
+        let copies = BYTES_PER_SIZE / size;
+
+        let started = Instant::now();
+        for _ in 0..copies {
+            write_byte_by_byte(std::hint::black_box(&mut memory), 8, &response);
+        }
+        let looped = started.elapsed();
+
+        let started = Instant::now();
+        for _ in 0..copies {
+            write_bytes_to_memory(std::hint::black_box(&mut memory), 8, &response).unwrap();
+        }
+        let copied = started.elapsed();
+
+        println!(
+            "{:>8} KiB responses: byte loop {:>8.0} MiB/s, write_bytes_to_memory {:>8.0} MiB/s",
+            size / 1024,
+            throughput(looped),
+            throughput(copied)
+        );
+    }
+}
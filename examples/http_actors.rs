@@ -0,0 +1,153 @@
+//! Running waPC guests behind a multi-threaded web framework, here actix-web.
+//!
+//! A `WapcHost` is not `Send`: it must stay on the thread that created it. Sharing one between
+//! request handlers behind a `Mutex` (or forcing it across threads with an `unsafe impl Send`)
+//! serializes every request and is unsound. Instead, let waPC own the threads:
+//!
+//! * Stateless operations go to a [WapcHostPool], whose hosts each live on their own worker
+//!   thread. Any host can serve any request.
+//! * A guest that keeps state between calls lives behind a [WapcHostHandle], which queues calls
+//!   for the single thread that owns it and bounds the queue, so an overloaded guest sheds load
+//!   with `503 Service Unavailable` instead of piling up requests.
+//!
+//! Both block the calling thread until the guest responds, so async handlers make the call
+//! inside `web::block`, keeping the framework's async workers free to accept requests.
+//!
+//! Run it with `cargo run --example http_actors`, then:
+//!
+//! ```text
+//! curl -d hello localhost:8080/echo
+//! curl -X POST localhost:8080/count
+//! ```
+
+use std::error::Error;
+use std::sync::Arc;
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+use wapc::errors::ErrorKind;
+use wapc::{ModuleState, WapcHost, WapcHostBuilder, WapcHostHandle, WapcHostPool};
+
+/// Stands in for a real engine provider, such as wasmtime-provider, so the example runs without
+/// one. It answers `echo` with the payload and `count` with the number of calls so far.
+#[derive(Default)]
+struct NativeGuest {
+    state: Option<Arc<ModuleState>>,
+    count: u64,
+}
+
+impl wapc::WebAssemblyEngineProvider for NativeGuest {
+    fn init(&mut self, host: Arc<ModuleState>) -> Result<(), Box<dyn Error>> {
+        self.state = Some(host);
+        Ok(())
+    }
+
+    fn call(&mut self, _op_length: i32, _msg_length: i32) -> Result<i32, Box<dyn Error>> {
+        let state = self.state.as_ref().ok_or("guest is not initialized")?;
+        let inv = state.get_guest_request().ok_or("no guest request")?;
+        match &*inv.operation {
+            "echo" => state.set_guest_response(inv.msg.to_vec()),
+            "count" => {
+                self.count += 1;
+                state.set_guest_response(self.count.to_string().into_bytes());
+            }
+            op => {
+                state.set_guest_error(format!("[unsupported_operation] {}", op));
+                return Ok(0);
+            }
+        }
+        Ok(1)
+    }
+
+    fn replace(&mut self, _bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        Err("the native guest cannot be replaced".into())
+    }
+}
+
+fn new_host() -> wapc::Result<WapcHost> {
+    WapcHostBuilder::new().build(Box::new(NativeGuest::default()))
+}
+
+/// Maps a waPC error to a response: guests that classify their errors choose the status, and
+/// a full queue asks the client to come back later
+fn error_response(e: wapc::errors::Error) -> HttpResponse {
+    if let ErrorKind::QueueFull = e.kind() {
+        return HttpResponse::ServiceUnavailable().finish();
+    }
+    let status = e
+        .guest_error()
+        .map(|guest| guest.class.http_status())
+        .unwrap_or(500);
+    let status = actix_web::http::StatusCode::from_u16(status)
+        .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR);
+    HttpResponse::build(status).body(e.to_string())
+}
+
+async fn echo(pool: web::Data<WapcHostPool>, body: web::Bytes) -> actix_web::Result<HttpResponse> {
+    let result = web::block(move || pool.call("echo", &body)).await?;
+    Ok(match result {
+        Ok(response) => HttpResponse::Ok().body(response),
+        Err(e) => error_response(e),
+    })
+}
+
+async fn count(counter: web::Data<WapcHostHandle>) -> actix_web::Result<HttpResponse> {
+    let result = web::block(move || counter.try_call("count", b"")).await?;
+    Ok(match result {
+        Ok(response) => HttpResponse::Ok().body(response),
+        Err(e) => error_response(e),
+    })
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    // The pool and the handle are created once and shared by every server worker; `web::Data`
+    // wraps them in an `Arc`
+    let pool = WapcHostPool::new(workers, |_| new_host()).map_err(to_io)?;
+    let pool = web::Data::new(pool);
+    let counter = web::Data::new(WapcHostHandle::spawn(64, new_host).map_err(to_io)?);
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(pool.clone())
+            .app_data(counter.clone())
+            .route("/echo", web::post().to(echo))
+            .route("/count", web::post().to(count))
+    })
+    .workers(workers)
+    .bind(("127.0.0.1", 8080))?
+    .run()
+    .await
+}
+
+fn to_io(e: wapc::errors::Error) -> std::io::Error {
+    std::io::Error::other(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+
+    #[actix_web::test]
+    async fn requests_reach_the_guests() {
+        let pool = web::Data::new(WapcHostPool::new(2, |_| new_host()).unwrap());
+        let counter = web::Data::new(WapcHostHandle::spawn(1, new_host).unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(pool)
+                .app_data(counter)
+                .route("/echo", web::post().to(echo))
+                .route("/count", web::post().to(count)),
+        )
+        .await;
+
+        let request = test::TestRequest::post().uri("/echo").set_payload("hello");
+        let response = test::call_and_read_body(&app, request.to_request()).await;
+        assert_eq!(response, "hello");
+        for expected in ["1", "2"] {
+            let request = test::TestRequest::post().uri("/count").to_request();
+            assert_eq!(test::call_and_read_body(&app, request).await, expected);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! Feeds arbitrary bytes to every decoder that parses guest-supplied data
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wapc::guest_error::GuestError;
+use wapc::route::Route;
+use wapc::{batch, events};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = batch::decode_requests(data);
+    let _ = batch::decode_responses(data);
+    if let Ok(decoded) = events::decode(data) {
+        assert_eq!(events::decode(&events::encode(&decoded)).unwrap(), decoded);
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let _ = GuestError::decode(&text).to_string();
+    let _ = Route::parse(&text).to_string();
+});
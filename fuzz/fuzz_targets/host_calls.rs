@@ -0,0 +1,112 @@
+//! Drives the host imports with adversarial arguments through a synthetic guest. The input is
+//! split into commands, each invoking one import with fuzzer-chosen IDs, pointers and payloads.
+#![no_main]
+
+use std::sync::Arc;
+
+use libfuzzer_sys::fuzz_target;
+use wapc::host_function::{HostFunctionContext, Val, ValType};
+use wapc::{ModuleState, WapcHostBuilder, WebAssemblyEngineProvider};
+
+type EngineResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+struct SyntheticGuest {
+    state: Option<Arc<ModuleState>>,
+    script: Vec<u8>,
+    memory: Vec<u8>,
+}
+
+impl SyntheticGuest {
+    fn run(&mut self) -> i32 {
+        let state = self.state.clone().unwrap();
+        let mut script = &self.script[..];
+        let mut result = 1;
+        while let Some((&cmd, rest)) = script.split_first() {
+            let len = rest.first().map(|&l| l as usize).unwrap_or(0).min(rest.len().saturating_sub(1));
+            let arg = &rest[1.min(rest.len())..1.min(rest.len()) + len];
+            script = &rest[(1 + len).min(rest.len())..];
+            let text = String::from_utf8_lossy(arg);
+            let word = arg.iter().fold(0i32, |acc, &b| acc.rotate_left(8) ^ b as i32);
+            match cmd % 8 {
+                0 => {
+                    let _ = state.do_host_call(&text, &text, &text, arg);
+                    let _ = (state.get_host_response(), state.get_host_error());
+                }
+                1 => state.set_guest_error(text.into_owned()),
+                2 => state.set_guest_response(arg.to_vec()),
+                3 => {
+                    state.do_stream_open(&text);
+                }
+                4 => {
+                    state.do_stream_write(word, arg);
+                }
+                5 => {
+                    state.do_stream_close(word);
+                }
+                6 => state.do_emit_event(&text, arg),
+                _ => {
+                    // AssemblyScript shims decode strings from guest memory at arbitrary pointers
+                    for (i, b) in arg.iter().enumerate() {
+                        let len = self.memory.len();
+                        self.memory[(word as usize).wrapping_add(i) % len] = *b;
+                    }
+                    for f in state.host_functions() {
+                        let params: Vec<Val> = (0..)
+                            .zip(&f.ty().params)
+                            .map(|(i, ty)| match ty {
+                                ValType::I32 => Val::I32(word.wrapping_add(i)),
+                                ValType::I64 => Val::I64(word as i64),
+                                ValType::F32 => Val::F32(word as f32),
+                                ValType::F64 => Val::F64(word as f64),
+                            })
+                            .collect();
+                        let mut ctx = HostFunctionContext {
+                            state: &state,
+                            memory: Some(&mut self.memory),
+                        };
+                        let _ = f.call(&mut ctx, &params);
+                    }
+                    result = 0;
+                }
+            }
+        }
+        result
+    }
+}
+
+impl WebAssemblyEngineProvider for SyntheticGuest {
+    fn init(&mut self, host: Arc<ModuleState>) -> EngineResult<()> {
+        self.state = Some(host);
+        Ok(())
+    }
+
+    fn call(&mut self, _op_length: i32, _msg_length: i32) -> EngineResult<i32> {
+        Ok(self.run())
+    }
+
+    fn replace(&mut self, bytes: &[u8]) -> EngineResult<()> {
+        self.script = bytes.to_vec();
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let engine = SyntheticGuest {
+        state: None,
+        script: data.to_vec(),
+        memory: vec![0; 256],
+    };
+    let host = WapcHostBuilder::new()
+        .host_callback(|_, _, _, op, payload| {
+            if op.len() % 2 == 0 {
+                Ok(payload.to_vec())
+            } else {
+                Err(op.to_string().into())
+            }
+        })
+        .assemblyscript_shims()
+        .build(Box::new(engine))
+        .unwrap();
+    let _ = host.call("fuzz", data);
+    let _ = host.call("wapc:events!__wapc_poll_events", &[]);
+});
@@ -0,0 +1,128 @@
+//! Shims for the `env.abort` and `env.trace` imports that AssemblyScript guests declare.
+//!
+//! Enable them with
+//! [WapcHostBuilder::assemblyscript_shims](../struct.WapcHostBuilder.html#method.assemblyscript_shims).
+//! `abort` traps the guest and fails the current call with a
+//! [GuestCallFailure](../errors/enum.ErrorKind.html#variant.GuestCallFailure) carrying the
+//! message and source location; `trace` is routed to the module's loggers. Both decode their
+//! string arguments from guest memory, so the engine provider must supply it in the
+//! [HostFunctionContext](../host_function/struct.HostFunctionContext.html).
+
+use crate::host_function::{FuncType, HostFunction, HostFunctionContext, Val, ValType};
+
+/// The import module used by AssemblyScript's runtime
+pub const ASSEMBLYSCRIPT_MODULE: &str = "env";
+
+/// Returns the `env.abort` and `env.trace` host functions
+pub fn shims() -> Vec<HostFunction> {
+    use ValType::*;
+    vec![
+        HostFunction::new(
+            ASSEMBLYSCRIPT_MODULE,
+            "abort",
+            FuncType::new(&[I32, I32, I32, I32], &[]),
+            |ctx, params| {
+                let (msg, file, line, col) = match params {
+                    [Val::I32(m), Val::I32(f), Val::I32(l), Val::I32(c)] => (*m, *f, *l, *c),
+                    _ => unreachable!(),
+                };
+                let reason = format!(
+                    "abort: {} at {}:{}:{}",
+                    read_string(ctx, msg),
+                    read_string(ctx, file),
+                    line,
+                    col
+                );
+                ctx.state.set_guest_error(reason.clone());
+                Err(reason.into())
+            },
+        ),
+        HostFunction::new(
+            ASSEMBLYSCRIPT_MODULE,
+            "trace",
+            FuncType::new(&[I32, I32, F64, F64, F64, F64, F64], &[]),
+            |ctx, params| {
+                let (msg, n) = match params {
+                    [Val::I32(m), Val::I32(n), ..] => (*m, *n),
+                    _ => unreachable!(),
+                };
+                let args: Vec<String> = params[2..]
+                    .iter()
+                    .take(n.max(0) as usize)
+                    .map(|v| match v {
+                        Val::F64(f) => f.to_string(),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                let mut line = format!("trace: {}", read_string(ctx, msg));
+                if !args.is_empty() {
+                    line.push(' ');
+                    line.push_str(&args.join(", "));
+                }
+                ctx.state.do_console_log(&line);
+                Ok(vec![])
+            },
+        ),
+    ]
+}
+
+/// Decodes an AssemblyScript string: UTF-16LE data at `ptr`, with its length in bytes stored
+/// in the four bytes preceding it. Null or unreadable pointers decode to a placeholder.
+fn read_string(ctx: &HostFunctionContext, ptr: i32) -> String {
+    const UNKNOWN: &str = "<unknown>";
+    let memory = match ctx.memory {
+        Some(ref m) => m,
+        None => return UNKNOWN.to_string(),
+    };
+    let ptr = ptr as u32 as usize;
+    if ptr < 4 || ptr > memory.len() {
+        return UNKNOWN.to_string();
+    }
+    let mut len = [0u8; 4];
+    len.copy_from_slice(&memory[ptr - 4..ptr]);
+    let len = u32::from_le_bytes(len) as usize;
+    match memory.get(ptr..ptr.saturating_add(len)) {
+        Some(bytes) => {
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => UNKNOWN.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ModuleState;
+
+    fn write_string(memory: &mut [u8], ptr: usize, s: &str) {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        memory[ptr - 4..ptr].copy_from_slice(&((units.len() * 2) as u32).to_le_bytes());
+        for (i, u) in units.iter().enumerate() {
+            memory[ptr + i * 2..ptr + i * 2 + 2].copy_from_slice(&u.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn abort_records_message_and_location() {
+        let mut memory = vec![0u8; 128];
+        write_string(&mut memory, 8, "boom");
+        write_string(&mut memory, 40, "index.ts");
+        let state = ModuleState::default();
+        let abort = shims().remove(0);
+        let mut ctx = HostFunctionContext {
+            state: &state,
+            memory: Some(&mut memory),
+        };
+
+        let params = [Val::I32(8), Val::I32(40), Val::I32(3), Val::I32(7)];
+        assert!(abort.call(&mut ctx, &params).is_err());
+        assert_eq!(
+            state.guest_error.read().unwrap().as_deref(),
+            Some("abort: boom at index.ts:3:7")
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! An audit trail of host calls, for multi-tenant deployments that must show which module
+//! called what. Requires the `audit` feature.
+//!
+//! A host configured with
+//! [WapcHostBuilder::audit_sink](../struct.WapcHostBuilder.html#method.audit_sink) hands an
+//! [AuditRecord](struct.AuditRecord.html) to the sink for every host call its guest makes,
+//! after the call completes. Payloads are never recorded, only their SHA-256 hash. A
+//! [HostCallPolicy](trait.HostCallPolicy.html) set with
+//! [WapcHostBuilder::host_call_policy](../struct.WapcHostBuilder.html#method.host_call_policy)
+//! decides whether each host call may proceed, and its decision is recorded with the call.
+//!
+//! Sinks are provided for JSON-lines files ([FileSink](struct.FileSink.html)) and bounded
+//! in-memory buffers ([MemorySink](struct.MemorySink.html)), and any
+//! `Fn(&AuditRecord) + Send + Sync` closure is a sink as well.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::errors::{self, ErrorKind};
+use crate::{HostCallContext, Result};
+
+/// Whether a host call was allowed to proceed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Allowed,
+    /// Denied by the host call policy, for the given reason
+    Denied(String),
+}
+
+/// A single audited host call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the host call was made, in milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub module_id: u64,
+    pub binding: String,
+    pub namespace: String,
+    pub operation: String,
+    /// The SHA-256 hash of the payload, hex encoded
+    pub payload_sha256: String,
+    pub decision: Decision,
+    /// The error the host call failed with, if it did
+    pub error: Option<String>,
+    pub duration: Duration,
+}
+
+/// Decides whether a guest may make a host call. Denied calls fail with an error naming the
+/// reason, and are never seen by capability providers or the host callback.
+pub trait HostCallPolicy: Send + Sync {
+    /// Returns `Err` with a reason to deny the call
+    fn check(&self, ctx: &HostCallContext, operation: &str) -> std::result::Result<(), String>;
+}
+
+impl<F> HostCallPolicy for F
+where
+    F: Fn(&HostCallContext, &str) -> std::result::Result<(), String> + Send + Sync,
+{
+    fn check(&self, ctx: &HostCallContext, operation: &str) -> std::result::Result<(), String> {
+        self(ctx, operation)
+    }
+}
+
+/// Receives an audit record for every host call
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord) + Send + Sync,
+{
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// A sink that appends each record to a file as a line of JSON
+pub struct FileSink {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl FileSink {
+    /// Opens the file at `path` for appending, creating it if necessary
+    pub fn create(path: impl AsRef<Path>) -> Result<FileSink> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| errors::new(ErrorKind::IO(e)))?;
+        Ok(FileSink {
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+}
+
+impl AuditSink for FileSink {
+    fn record(&self, record: &AuditRecord) {
+        let mut file = self.file.lock().unwrap();
+        let written = serde_json::to_writer(&mut *file, record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| file.write_all(b"\n"));
+        if let Err(e) = written {
+            error!("Failed to write audit record: {}", e);
+        }
+    }
+}
+
+/// A sink that keeps the most recent records in memory
+pub struct MemorySink {
+    capacity: usize,
+    records: Mutex<VecDeque<AuditRecord>>,
+}
+
+impl MemorySink {
+    /// Creates a sink that keeps at most `capacity` records, discarding the oldest
+    pub fn new(capacity: usize) -> MemorySink {
+        MemorySink {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns the records held, oldest first
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl AuditSink for MemorySink {
+    fn record(&self, record: &AuditRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        if self.capacity > 0 {
+            records.push_back(record.clone());
+        }
+    }
+}
+
+pub(crate) fn payload_hash(payload: &[u8]) -> String {
+    Sha256::digest(payload)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub(crate) fn timestamp_ms(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
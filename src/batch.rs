@@ -0,0 +1,135 @@
+//! Framing for batching several invocations into a single `__guest_call`, used by
+//! [WapcHost::call_batch](../struct.WapcHost.html#method.call_batch).
+//!
+//! A batch is sent to the guest as the `__wapc_batch` operation. Its payload is the
+//! concatenation of one frame per invocation, each made up of:
+//!
+//! | Field       | Encoding                        |
+//! |-------------|---------------------------------|
+//! | op_len      | u32, big-endian                 |
+//! | operation   | `op_len` bytes of UTF-8         |
+//! | payload_len | u32, big-endian                 |
+//! | payload     | `payload_len` bytes             |
+//!
+//! A guest that supports batching responds with exactly one frame per invocation, in the same
+//! order, each made up of:
+//!
+//! | Field       | Encoding                                    |
+//! |-------------|---------------------------------------------|
+//! | status      | u8: 1 for success, 0 for failure            |
+//! | len         | u32, big-endian                             |
+//! | body        | `len` bytes: the response, or a UTF-8 error |
+//!
+//! Guests that do not support batching simply fail the `__wapc_batch` operation, and the host
+//! falls back to making the calls one at a time.
+
+/// The operation name used to deliver a batch to the guest
+pub const BATCH_OPERATION: &str = "__wapc_batch";
+
+/// Encodes a batch of invocations into a `__wapc_batch` payload
+pub fn encode_requests(requests: &[(&str, &[u8])]) -> Vec<u8> {
+    let len = requests
+        .iter()
+        .map(|(op, payload)| 8 + op.len() + payload.len())
+        .sum();
+    let mut buf = Vec::with_capacity(len);
+    for (op, payload) in requests {
+        write_frame(&mut buf, op.as_bytes());
+        write_frame(&mut buf, payload);
+    }
+    buf
+}
+
+/// Decodes a `__wapc_batch` payload into its invocations
+pub fn decode_requests(mut buf: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut requests = Vec::new();
+    while !buf.is_empty() {
+        let op = read_frame(&mut buf)?;
+        let op = String::from_utf8(op.to_vec())
+            .map_err(|_| "Batch operation name is not valid UTF-8".to_string())?;
+        let payload = read_frame(&mut buf)?;
+        requests.push((op, payload.to_vec()));
+    }
+    Ok(requests)
+}
+
+/// Encodes the results of a batch into a `__wapc_batch` response
+pub fn encode_responses(responses: &[Result<Vec<u8>, String>]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for response in responses {
+        match response {
+            Ok(r) => {
+                buf.push(1);
+                write_frame(&mut buf, r);
+            }
+            Err(e) => {
+                buf.push(0);
+                write_frame(&mut buf, e.as_bytes());
+            }
+        }
+    }
+    buf
+}
+
+/// Decodes a `__wapc_batch` response into the result of each invocation
+pub fn decode_responses(mut buf: &[u8]) -> Result<Vec<Result<Vec<u8>, String>>, String> {
+    let mut responses = Vec::new();
+    while let Some((&status, rest)) = buf.split_first() {
+        buf = rest;
+        let body = read_frame(&mut buf)?;
+        responses.push(match status {
+            1 => Ok(body.to_vec()),
+            0 => Err(String::from_utf8_lossy(body).into_owned()),
+            other => return Err(format!("Invalid batch response status {}", other)),
+        });
+    }
+    Ok(responses)
+}
+
+pub(crate) fn write_frame(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+pub(crate) fn read_frame<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], String> {
+    if buf.len() < 4 {
+        return Err("Truncated batch frame length".to_string());
+    }
+    let (len, rest) = buf.split_at(4);
+    let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    if rest.len() < len {
+        return Err("Truncated batch frame".to_string());
+    }
+    let (frame, rest) = rest.split_at(len);
+    *buf = rest;
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let requests: Vec<(&str, &[u8])> = vec![("a", b"1"), ("bb", b""), ("", b"333")];
+        let decoded = decode_requests(&encode_requests(&requests)).unwrap();
+        let decoded: Vec<(&str, &[u8])> = decoded
+            .iter()
+            .map(|(op, p)| (op.as_str(), p.as_slice()))
+            .collect();
+        assert_eq!(decoded, requests);
+
+        let responses = vec![Ok(b"ok".to_vec()), Err("nope".to_string())];
+        assert_eq!(decode_responses(&encode_responses(&responses)).unwrap(), responses);
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let buf = encode_requests(&[("op", b"payload")]);
+        for len in 1..buf.len() {
+            assert!(decode_requests(&buf[..len]).is_err());
+        }
+        assert!(decode_responses(&[1, 0, 0, 0, 5, b'x']).is_err());
+        assert!(decode_responses(&[7, 0, 0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! Serves the operations of a waPC module over stdin and stdout, as line-delimited JSON-RPC, so
+//! scripts and CI jobs can exercise a guest without writing any Rust:
+//!
+//! ```text
+//! echo '{"jsonrpc":"2.0","id":1,"method":"call","params":{"operation":"echo","payload":"aGk="}}' \
+//!     | wapc-run guest.wasm
+//! ```
+//!
+//! See [wapc::server::jsonrpc](../wapc/server/jsonrpc/index.html) for the protocol. Guests run
+//! in the wasmi interpreter, which needs no native code generation and starts instantly; the
+//! runner only links the waPC imports, so guests that import WASI are refused. Embedders who
+//! want another engine call `jsonrpc::run_file` with it instead.
+//!
+//! `wapc-run doctor` runs the [self-test](../wapc/doctor/index.html) with the interpreter and
+//! prints the report, exiting with an error if a check failed.
+
+use std::error::Error;
+use std::sync::Arc;
+
+use wapc::memory::write_bytes_to_memory;
+use wapc::server::jsonrpc;
+use wapc::{EngineInfo, ModuleState, WapcFunctions, WebAssemblyEngineProvider, HOST_NAMESPACE};
+use wasmi::{Caller, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+type State = Arc<ModuleState>;
+
+/// A waPC engine provider backed by the wasmi interpreter
+struct WasmiEngine {
+    module: Vec<u8>,
+    host: Option<State>,
+    store: Option<Store<State>>,
+    guest_call: Option<TypedFunc<(i32, i32), i32>>,
+}
+
+impl WasmiEngine {
+    fn new(module: &[u8]) -> WasmiEngine {
+        WasmiEngine {
+            module: module.to_vec(),
+            host: None,
+            store: None,
+            guest_call: None,
+        }
+    }
+}
+
+impl WebAssemblyEngineProvider for WasmiEngine {
+    fn init(&mut self, host: Arc<ModuleState>) -> Result<(), Box<dyn Error>> {
+        let engine = Engine::default();
+        let module = Module::new(&engine, &self.module[..])?;
+        let mut store = Store::new(&engine, host.clone());
+        let instance = linker(&engine)?
+            .instantiate(&mut store, &module)?
+            .start(&mut store)?;
+        for start in WapcFunctions::REQUIRED_STARTS.iter() {
+            if let Ok(start) = instance.get_typed_func::<(), ()>(&store, start) {
+                start.call(&mut store, ())?;
+            }
+        }
+        self.guest_call = Some(instance.get_typed_func(&store, WapcFunctions::GUEST_CALL)?);
+        self.store = Some(store);
+        self.host = Some(host);
+        Ok(())
+    }
+
+    fn call(&mut self, op_length: i32, msg_length: i32) -> Result<i32, Box<dyn Error>> {
+        let (store, guest_call) = match (self.store.as_mut(), self.guest_call.as_ref()) {
+            (Some(store), Some(guest_call)) => (store, guest_call),
+            _ => return Err("The guest module is not initialized".into()),
+        };
+        Ok(guest_call.call(store, (op_length, msg_length))?)
+    }
+
+    fn replace(&mut self, module: &[u8]) -> Result<(), Box<dyn Error>> {
+        let host = self.host.clone().ok_or("The guest module is not initialized")?;
+        self.module = module.to_vec();
+        self.init(host)
+    }
+
+    fn set_module(&mut self, module: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.module = module.to_vec();
+        Ok(())
+    }
+
+    fn engine_info(&self) -> Option<EngineInfo> {
+        Some(EngineInfo {
+            name: "wasmi".to_string(),
+            // The version requirement in Cargo.toml
+            version: "0.32".to_string(),
+            features: vec!["interpreter".to_string()],
+        })
+    }
+}
+
+/// Links the waPC imports, reading and writing payloads through the data memory chosen in the
+/// host's engine settings
+fn linker(engine: &Engine) -> Result<Linker<State>, wasmi::Error> {
+    let mut linker = Linker::new(engine);
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::GUEST_REQUEST_FN,
+        |mut caller: Caller<'_, State>, op_ptr: i32, ptr: i32| {
+            let host = caller.data().clone();
+            if let Some(invocation) = host.get_guest_request() {
+                write(&mut caller, op_ptr, invocation.operation.as_bytes())?;
+                write(&mut caller, ptr, &invocation.msg)?;
+            }
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::GUEST_RESPONSE_FN,
+        |caller: Caller<'_, State>, ptr: i32, len: i32| {
+            let response = read(&caller, ptr, len)?;
+            caller.data().set_guest_response(response);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::GUEST_ERROR_FN,
+        |caller: Caller<'_, State>, ptr: i32, len: i32| {
+            let error = String::from_utf8_lossy(&read(&caller, ptr, len)?).into_owned();
+            caller.data().set_guest_error(error);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::HOST_CALL,
+        |caller: Caller<'_, State>,
+         bd_ptr: i32,
+         bd_len: i32,
+         ns_ptr: i32,
+         ns_len: i32,
+         op_ptr: i32,
+         op_len: i32,
+         ptr: i32,
+         len: i32| {
+            let binding = read_string(&caller, bd_ptr, bd_len)?;
+            let namespace = read_string(&caller, ns_ptr, ns_len)?;
+            let operation = read_string(&caller, op_ptr, op_len)?;
+            let payload = read(&caller, ptr, len)?;
+            caller
+                .data()
+                .do_host_call(&binding, &namespace, &operation, &payload)
+                .map_err(|e| wasmi::Error::new(e.to_string()))
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::HOST_RESPONSE_LEN_FN,
+        |caller: Caller<'_, State>| -> i32 {
+            caller.data().get_host_response().map_or(0, |r| r.len() as i32)
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::HOST_RESPONSE_FN,
+        |mut caller: Caller<'_, State>, ptr: i32| {
+            match caller.data().get_host_response() {
+                Some(response) => write(&mut caller, ptr, &response),
+                None => Ok(()),
+            }
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::HOST_ERROR_LEN_FN,
+        |caller: Caller<'_, State>| -> i32 {
+            caller.data().get_host_error().map_or(0, |e| e.len() as i32)
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::HOST_ERROR_FN,
+        |mut caller: Caller<'_, State>, ptr: i32| match caller.data().get_host_error() {
+            Some(error) => write(&mut caller, ptr, error.as_bytes()),
+            None => Ok(()),
+        },
+    )?;
+    linker.func_wrap(
+        HOST_NAMESPACE,
+        WapcFunctions::HOST_CONSOLE_LOG,
+        |caller: Caller<'_, State>, ptr: i32, len: i32| {
+            let message = read_string(&caller, ptr, len)?;
+            caller.data().do_console_log(&message);
+            Ok(())
+        },
+    )?;
+    Ok(linker)
+}
+
+fn memory(caller: &Caller<'_, State>) -> Result<Memory, wasmi::Error> {
+    let name = &caller.data().engine_settings().memory_export;
+    caller
+        .get_export(name)
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| {
+            wasmi::Error::new(format!("The guest does not export a memory named {}", name))
+        })
+}
+
+fn read(caller: &Caller<'_, State>, ptr: i32, len: i32) -> Result<Vec<u8>, wasmi::Error> {
+    let mut buffer = vec![0; len as u32 as usize];
+    memory(caller)?
+        .read(caller, ptr as u32 as usize, &mut buffer)
+        .map_err(|e| wasmi::Error::new(e.to_string()))?;
+    Ok(buffer)
+}
+
+fn read_string(caller: &Caller<'_, State>, ptr: i32, len: i32) -> Result<String, wasmi::Error> {
+    Ok(String::from_utf8_lossy(&read(caller, ptr, len)?).into_owned())
+}
+
+fn write(caller: &mut Caller<'_, State>, ptr: i32, data: &[u8]) -> Result<(), wasmi::Error> {
+    let memory = memory(caller)?.data_mut(caller);
+    write_bytes_to_memory(memory, ptr as u32, data).map_err(|e| wasmi::Error::new(e.to_string()))
+}
+
+fn main() {
+    let path = match std::env::args_os().nth(1) {
+        Some(ref arg) if arg == "doctor" => {
+            let report = wapc::doctor(|module| Box::new(WasmiEngine::new(module)));
+            print!("{}", report);
+            std::process::exit(if report.healthy() { 0 } else { 1 });
+        }
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: wapc-run <module.wasm> | wapc-run doctor");
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = jsonrpc::run_file(path, |module| Box::new(WasmiEngine::new(module))) {
+        eprintln!("wapc-run: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(all(test, feature = "echo-guest"))]
+mod tests {
+    use super::*;
+    use wapc::WapcHostBuilder;
+
+    #[test]
+    fn serves_the_echo_guest() {
+        let host = WapcHostBuilder::new()
+            .build(Box::new(WasmiEngine::new(wapc::guests::ECHO)))
+            .unwrap();
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"call","#,
+            r#""params":{"operation":"echo","payload":"aGk="}}"#,
+            "\n",
+        );
+        let mut output = Vec::new();
+        jsonrpc::serve(&host, input.as_bytes(), &mut output).unwrap();
+        let response: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(response["result"]["payload"], "aGk=");
+    }
+
+    #[test]
+    fn doctor_passes_with_the_interpreter() {
+        let report = wapc::doctor(|module| Box::new(WasmiEngine::new(module)));
+        assert!(report.healthy(), "{}", report);
+    }
+}
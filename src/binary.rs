@@ -0,0 +1,98 @@
+//! Identification of WebAssembly binaries by their preamble.
+//!
+//! Core modules and components share the `\0asm` magic number but differ in the version and
+//! layer fields that follow it. Engine providers that can host both use
+//! [binary_kind](fn.binary_kind.html) to pick an instantiation path, so core modules and
+//! components are invoked through the same [WapcHost](../struct.WapcHost.html) API.
+//!
+//! [load_module_file](fn.load_module_file.html) reads a binary from disk, refusing files over a
+//! size limit before reading them.
+
+use std::io::Read;
+use std::path::Path;
+
+use crate::errors::{self, ErrorKind};
+use crate::Result;
+
+const MAGIC: &[u8; 4] = b"\0asm";
+
+/// The largest module [load_module_file](fn.load_module_file.html) reads unless told otherwise
+pub const DEFAULT_MAX_MODULE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// The kind of a WebAssembly binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryKind {
+    /// A core WebAssembly module
+    CoreModule,
+    /// A component model component
+    Component,
+}
+
+/// Identifies a WebAssembly binary from its 8-byte preamble, returning `None` if the bytes are
+/// not a WebAssembly binary (for example, the WebAssembly text format)
+pub fn binary_kind(bytes: &[u8]) -> Option<BinaryKind> {
+    if bytes.len() < 8 || &bytes[..4] != MAGIC {
+        return None;
+    }
+    // The version is a u16 followed by a u16 layer: core modules are layer 0, components layer 1
+    match (bytes[6], bytes[7]) {
+        (0, 0) => Some(BinaryKind::CoreModule),
+        (1, 0) => Some(BinaryKind::Component),
+        _ => None,
+    }
+}
+
+/// Reads a module from disk, returning a
+/// [ModuleTooLarge](../errors/enum.ErrorKind.html#variant.ModuleTooLarge) error without reading
+/// it if the file is larger than `max_size` bytes. The buffer is sized from the file up front,
+/// so loading never holds more than one copy of the module.
+pub fn load_module_file(path: impl AsRef<Path>, max_size: u64) -> Result<Vec<u8>> {
+    let file = std::fs::File::open(path.as_ref()).map_err(|e| errors::new(ErrorKind::IO(e)))?;
+    let size = file
+        .metadata()
+        .map_err(|e| errors::new(ErrorKind::IO(e)))?
+        .len();
+    if size > max_size {
+        return Err(errors::new(ErrorKind::ModuleTooLarge {
+            size,
+            limit: max_size,
+        }));
+    }
+    let mut bytes = Vec::with_capacity(size as usize);
+    // The file may grow after its size was checked, so never read past the limit
+    file.take(max_size + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| errors::new(ErrorKind::IO(e)))?;
+    if bytes.len() as u64 > max_size {
+        return Err(errors::new(ErrorKind::ModuleTooLarge {
+            size: bytes.len() as u64,
+            limit: max_size,
+        }));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifies_preambles() {
+        assert_eq!(binary_kind(b"\0asm\x01\0\0\0"), Some(BinaryKind::CoreModule));
+        assert_eq!(binary_kind(b"\0asm\x0d\0\x01\0"), Some(BinaryKind::Component));
+        assert_eq!(binary_kind(b"(module)"), None);
+    }
+
+    #[test]
+    fn oversized_module_files_are_refused() {
+        let path = std::env::temp_dir().join(format!("wapc-load-{}.wasm", std::process::id()));
+        std::fs::write(&path, b"\0asm\x01\0\0\0").unwrap();
+        assert_eq!(load_module_file(&path, 8).unwrap().len(), 8);
+        match load_module_file(&path, 7).unwrap_err().kind() {
+            ErrorKind::ModuleTooLarge { size, limit } => assert_eq!((*size, *limit), (8, 7)),
+            other => panic!("unexpected error {:?}", other),
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_module_file(&path, 8).is_err());
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+
+use crate::capability::{CapabilityConfig, CapabilityProvider};
+use crate::chrome_trace::TimingRecorder;
+use crate::extensions::Extensions;
+use crate::host_function::{FuncType, HostFunction, HostFunctionContext, Val};
+use crate::middleware::CallMiddleware;
+use crate::plugin::RuntimePlugin;
+use crate::stream::StreamSink;
+use crate::{
+    EngineSettings, HostHandler, HostOptions, LogCallback, MemoryScrub, ModuleState, PanicPolicy,
+    Result, WapcHost, WebAssemblyEngineProvider, GLOBAL_MODULE_COUNT,
+};
+
+/// A builder for [WapcHost](struct.WapcHost.html) instances, used when a host needs more
+/// configuration than the `WapcHost` constructors offer
+///
+/// ```ignore
+/// let host = WapcHostBuilder::new()
+///     .host_callback(|id, bd, ns, op, payload| Ok(vec![]))
+///     .logger(|id, msg| println!("[{}] {}", id, msg))
+///     .enable_time_imports()
+///     .build(Box::new(engine))?;
+/// ```
+#[derive(Default)]
+pub struct WapcHostBuilder {
+    handler: Option<Arc<dyn HostHandler>>,
+    loggers: Vec<Box<LogCallback>>,
+    time_imports: bool,
+    timings_capacity: Option<usize>,
+    id: Option<u64>,
+    string_id: Option<Arc<str>>,
+    stream_sink: Option<Arc<dyn StreamSink>>,
+    plugins: Vec<Arc<dyn RuntimePlugin>>,
+    extensions: Extensions,
+    host_functions: Vec<HostFunction>,
+    linked_modules: Vec<crate::linking::LinkedModule>,
+    engine_settings: EngineSettings,
+    capabilities: HashMap<String, Arc<dyn CapabilityProvider>>,
+    max_module_size: Option<u64>,
+    panic_policy: PanicPolicy,
+    advertised: Vec<crate::introspect::NamespaceInfo>,
+    retries: HashMap<String, crate::retry::RetryPolicy>,
+    breakers: HashMap<String, Arc<crate::circuit::CircuitBreaker>>,
+    max_response_size: Option<usize>,
+    #[cfg(feature = "audit")]
+    audit_sink: Option<Arc<dyn crate::audit::AuditSink>>,
+    #[cfg(feature = "audit")]
+    policy: Option<Arc<dyn crate::audit::HostCallPolicy>>,
+    #[cfg(feature = "validate")]
+    wasi_profile: Option<crate::wasi::WasiProfile>,
+    options: HostOptions,
+}
+
+impl WapcHostBuilder {
+    /// Creates a new builder with no host callback, no loggers, and no optional imports
+    pub fn new() -> WapcHostBuilder {
+        Self::default()
+    }
+
+    /// Sets the closure that will be invoked when the guest performs a host call
+    pub fn host_callback(
+        self,
+        host_callback: impl Fn(
+            u64,
+            &str,
+            &str,
+            &str,
+            &[u8],
+        )
+            -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+        + 'static
+        + Sync
+        + Send,
+    ) -> Self {
+        self.handler(Arc::new(host_callback))
+    }
+
+    /// Sets the [HostHandler](trait.HostHandler.html) that will be invoked when the guest
+    /// performs a host call
+    pub fn handler(mut self, handler: Arc<dyn HostHandler>) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    /// Attaches a logger that will receive all console log messages emitted by the guest,
+    /// including those emitted during initialization. May be called more than once.
+    pub fn logger(mut self, logger: impl Fn(u64, &str) + 'static + Sync + Send) -> Self {
+        self.loggers.push(Box::new(logger));
+        self
+    }
+
+    /// Instructs the engine provider to export the optional `__host_time_ms` and
+    /// `__host_monotonic_ms` functions, giving non-WASI guests a time source. Has no effect in
+    /// [deterministic](#method.deterministic) mode.
+    pub fn enable_time_imports(mut self) -> Self {
+        self.time_imports = true;
+        self
+    }
+
+    /// Collects per-operation call counts, error counts, latencies and payload sizes, readable
+    /// through [WapcHost::stats](struct.WapcHost.html#method.stats)
+    pub fn collect_stats(mut self) -> Self {
+        self.options.stats = Some(crate::stats::CallStats::new());
+        self
+    }
+
+    /// Resets the guest instance (see [WapcHost::reset](struct.WapcHost.html#method.reset)) after
+    /// the given number of consecutive failed calls
+    pub fn recycle_after_errors(mut self, errors: u32) -> Self {
+        self.options.recycle_after_errors = Some(errors);
+        self
+    }
+
+    /// Resets the guest instance (see [WapcHost::reset](struct.WapcHost.html#method.reset)) after
+    /// the given number of calls, preventing memory leaked by the guest from accumulating over
+    /// long uptimes
+    pub fn recycle_after_calls(mut self, calls: u64) -> Self {
+        self.options.recycle_after_calls = Some(calls);
+        self
+    }
+
+    /// Fails creation of the host with a
+    /// [GuestInitTimeout](errors/enum.ErrorKind.html#variant.GuestInitTimeout) error if the
+    /// guest's start functions do not complete within the given time. Engine providers that
+    /// supply an [InterruptHandle](trait.InterruptHandle.html) have the guest interrupted at the
+    /// deadline; with other engines a hung start function still blocks creation.
+    pub fn init_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.options.init_timeout = Some(timeout);
+        self
+    }
+
+    /// Defers instantiating the guest and running its start functions until its first call or
+    /// an explicit [ensure_initialized](struct.WapcHost.html#method.ensure_initialized), which
+    /// shortens startup for hosts that load many rarely used modules. Initialization errors are
+    /// then reported by that first call rather than by `build`.
+    pub fn lazy(mut self) -> Self {
+        self.options.lazy = true;
+        self
+    }
+
+    /// Warms the guest up once it is initialized, so the first real call does not pay for
+    /// compiling it. See [WapcHost::warmup](struct.WapcHost.html#method.warmup); a warmup
+    /// failure fails creation of the host.
+    pub fn warmup(mut self, ops: &[&str]) -> Self {
+        self.options.warmup = Some(ops.iter().map(|op| op.to_string()).collect());
+        self
+    }
+
+    /// Clears the guest's data after every call into it, for hosts processing secrets on behalf
+    /// of several tenants. This covers deferred calls and their resumption, warmup, health and
+    /// interface probes, and raw export calls, so guests cannot keep state between calls; a
+    /// deferred call can only be resumed if the guest does not rely on such state. A call whose
+    /// data cannot be cleared, e.g. because the engine provider cannot reset the guest, fails
+    /// instead of returning its response. See [MemoryScrub](enum.MemoryScrub.html).
+    pub fn scrub_memory(mut self, scrub: MemoryScrub) -> Self {
+        self.options.scrub = Some(scrub);
+        self
+    }
+
+    /// Answers repeated calls to the given operations from an LRU cache of up to `capacity`
+    /// successful responses, each kept for at most `ttl`, without invoking the guest. Only use
+    /// this for operations whose response depends solely on their payload. See the
+    /// [cache](cache/index.html) module.
+    pub fn cache(mut self, operations: &[&str], capacity: usize, ttl: std::time::Duration) -> Self {
+        let cache = Arc::new(crate::cache::ResponseCache::new(operations, capacity, ttl));
+        self.options.middleware.push(cache.clone());
+        self.options.cache = Some(cache);
+        self
+    }
+
+    /// Links the `env.abort` and `env.trace` imports declared by AssemblyScript guests. See the
+    /// [assemblyscript](assemblyscript/index.html) module.
+    pub fn assemblyscript_shims(mut self) -> Self {
+        self.host_functions.extend(crate::assemblyscript::shims());
+        self
+    }
+
+    /// Requires the engine provider to execute the guest deterministically. See
+    /// [EngineSettings::deterministic](struct.EngineSettings.html#structfield.deterministic).
+    pub fn deterministic(mut self) -> Self {
+        self.engine_settings.deterministic = true;
+        self
+    }
+
+    /// Enables wasm threads (shared memory and atomics) in the engine provider. See
+    /// [EngineSettings::threads](struct.EngineSettings.html#structfield.threads) for how
+    /// guest-spawned threads interact with waPC calls.
+    pub fn threads(mut self) -> Self {
+        self.engine_settings.threads = true;
+        self
+    }
+
+    /// Enables or disables SIMD in the engine provider; it is enabled by default. Disabling SIMD
+    /// also disables relaxed SIMD.
+    pub fn simd(mut self, enabled: bool) -> Self {
+        self.engine_settings.simd = enabled;
+        self.engine_settings.relaxed_simd &= enabled;
+        self
+    }
+
+    /// Enables relaxed SIMD, and SIMD with it, in the engine provider
+    pub fn relaxed_simd(mut self) -> Self {
+        self.engine_settings.simd = true;
+        self.engine_settings.relaxed_simd = true;
+        self
+    }
+
+    /// Limits the stack space guest code may use, in bytes. See
+    /// [EngineSettings::max_wasm_stack](struct.EngineSettings.html#structfield.max_wasm_stack).
+    pub fn max_wasm_stack(mut self, bytes: usize) -> Self {
+        self.engine_settings.max_wasm_stack = Some(bytes);
+        self
+    }
+
+    /// Sets the size of the native stacks used for asynchronous guest calls, in bytes. See
+    /// [EngineSettings::async_stack_size](struct.EngineSettings.html#structfield.async_stack_size).
+    pub fn async_stack_size(mut self, bytes: usize) -> Self {
+        self.engine_settings.async_stack_size = Some(bytes);
+        self
+    }
+
+    /// Writes a wasm coredump to `dir` whenever a guest call traps, so guest authors can debug
+    /// the failure offline with standard tooling. Requires an engine provider that implements
+    /// [take_coredump](trait.WebAssemblyEngineProvider.html#method.take_coredump). The path of
+    /// the latest coredump is reported by
+    /// [WapcHost::debug_state](struct.WapcHost.html#method.debug_state).
+    pub fn coredump_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.engine_settings.coredump_on_trap = true;
+        self.options.coredump_dir = Some(dir.into());
+        self
+    }
+
+    /// Reads the DWARF debug info of guests compiled with it, so that errors from trapping
+    /// calls carry file and line backtraces. See
+    /// [Error::backtrace](errors/struct.Error.html#method.backtrace).
+    pub fn debug_info(mut self) -> Self {
+        self.engine_settings.debug_info = true;
+        self
+    }
+
+    /// Strips DWARF custom sections from modules before their compiled form is cached. See
+    /// [EngineSettings::strip_debug_sections](struct.EngineSettings.html#structfield.strip_debug_sections).
+    pub fn strip_debug_sections(mut self) -> Self {
+        self.engine_settings.strip_debug_sections = true;
+        self
+    }
+
+    /// Enables the multi-memory proposal in the engine provider. See
+    /// [EngineSettings::multi_memory](struct.EngineSettings.html#structfield.multi_memory).
+    pub fn multi_memory(mut self) -> Self {
+        self.engine_settings.multi_memory = true;
+        self
+    }
+
+    /// Selects the exported memory waPC payloads are exchanged through, instead of `memory`.
+    /// See [EngineSettings::memory_export](struct.EngineSettings.html#structfield.memory_export).
+    pub fn memory_export(mut self, name: &str) -> Self {
+        self.engine_settings.memory_export = name.to_string();
+        self
+    }
+
+    /// Has the engine provider hand control back to the host every `epochs` epochs during a
+    /// guest call, so that a [fairly scheduled](struct.WapcHostPool.html#method.fair_scheduling)
+    /// pool can let other tenants run. See
+    /// [EngineSettings::time_slice_epochs](struct.EngineSettings.html#structfield.time_slice_epochs).
+    pub fn time_slice(mut self, epochs: u64) -> Self {
+        self.engine_settings.time_slice_epochs = Some(epochs.max(1));
+        self
+    }
+
+    /// Has the engine provider allocate instances from a pool with the given limits. See
+    /// [EngineSettings::pooling_allocation](struct.EngineSettings.html#structfield.pooling_allocation).
+    /// To also reuse the instances themselves, check hosts out of a
+    /// [WapcHostPool](struct.WapcHostPool.html) and drop them to return them.
+    pub fn pooling_allocation(mut self, limits: crate::PoolingAllocation) -> Self {
+        self.engine_settings.pooling_allocation = Some(limits);
+        self
+    }
+
+    /// Chooses what happens when the host callback or a capability provider panics. By default
+    /// the panic is converted into a host error delivered to the guest.
+    pub fn on_host_panic(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Advertises a namespace handled by the host callback, and the operations within it, to
+    /// guests that list the host's namespaces with the [introspect](introspect/index.html) host
+    /// call
+    pub fn advertise(mut self, namespace: &str, operations: &[&str]) -> Self {
+        self.advertised
+            .push(crate::introspect::NamespaceInfo::new(namespace, operations));
+        self
+    }
+
+    /// Fails calls whose guest response is larger than `bytes`, without copying the response
+    /// out of guest memory when the engine provider supports it. See the
+    /// [limits](limits/index.html) module.
+    pub fn max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// Retries failed host calls to `namespace`, whether handled by a capability provider or
+    /// the host callback, as `policy` allows. See the [retry](retry/index.html) module.
+    pub fn retry_host_calls(mut self, namespace: &str, policy: crate::retry::RetryPolicy) -> Self {
+        self.retries.insert(namespace.to_string(), policy);
+        self
+    }
+
+    /// Guards host calls to `namespace` with a circuit breaker, which may be shared with other
+    /// hosts calling the same downstream. A breaker sees each host call once, after any
+    /// [retries](#method.retry_host_calls). See the [circuit](circuit/index.html) module.
+    pub fn circuit_breaker(
+        mut self,
+        namespace: &str,
+        breaker: Arc<crate::circuit::CircuitBreaker>,
+    ) -> Self {
+        self.breakers.insert(namespace.to_string(), breaker);
+        self
+    }
+
+    /// Sends an audit record for every host call the guest makes to the given sink. See the
+    /// [audit](audit/index.html) module.
+    #[cfg(feature = "audit")]
+    pub fn audit_sink(mut self, sink: Arc<dyn crate::audit::AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
+    }
+
+    /// Checks every host call the guest makes against the given policy before it is handled,
+    /// failing the calls it denies. See the [audit](audit/index.html) module.
+    #[cfg(feature = "audit")]
+    pub fn host_call_policy(mut self, policy: Arc<dyn crate::audit::HostCallPolicy>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Assigns a caller-chosen ID to the module instead of the next value of the process-wide
+    /// counter. The ID is passed to the host callback and included in every error produced by
+    /// the host, so choosing stable IDs makes logs comparable across processes. Callers that
+    /// mix their own IDs with generated ones are responsible for avoiding collisions. See
+    /// [string_id](#method.string_id) to name the module instead.
+    pub fn id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Names the module with a caller-chosen string ID, such as a tenant or deployment name,
+    /// which errors produced by the host use in place of the numeric ID. The numeric ID is still
+    /// assigned as usual and stays unique, so hosts that share a name do not share the state
+    /// capability providers keep for each module.
+    pub fn string_id(mut self, id: &str) -> Self {
+        self.string_id = Some(id.into());
+        self
+    }
+
+    /// Adds a middleware layer that runs around every guest call. Layers run outermost-first
+    /// in the order they are added.
+    pub fn middleware(mut self, middleware: Arc<dyn CallMiddleware>) -> Self {
+        self.options.middleware.push(middleware);
+        self
+    }
+
+    /// Sets how long a deferred call may await resumption before it expires. Defaults to
+    /// 60 seconds. See the [deferred](deferred/index.html) module.
+    pub fn deferred_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.options.deferred_timeout = Some(timeout);
+        self
+    }
+
+    /// Attaches a typed value to the module, available during host calls through
+    /// `HostCallContext::extensions` and afterward through `WapcHost::extensions`. Only one
+    /// value of each type is kept.
+    pub fn extension<T: std::any::Any + Send + Sync>(self, value: T) -> Self {
+        self.extensions.insert(value);
+        self
+    }
+
+    /// Exposes an additional host function to the guest as `module`.`name`, so modules that
+    /// import functions outside the waPC protocol can be loaded. The engine provider links the
+    /// function before instantiating the guest. See the [host_function](host_function/index.html)
+    /// module.
+    pub fn link_host_function(
+        mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        callback: impl Fn(
+                &mut HostFunctionContext,
+                &[Val],
+            ) -> std::result::Result<Vec<Val>, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.host_functions
+            .push(HostFunction::new(module, name, ty, callback));
+        self
+    }
+
+    /// Links a library module that the guest imports from under `name`. The engine provider
+    /// instantiates libraries in registration order before the guest. Fails the build if the
+    /// name is used twice or is `wapc`. See the [linking](linking/index.html) module for the
+    /// ordering and shared memory rules.
+    pub fn link_module(mut self, name: &str, bytes: &[u8]) -> Self {
+        self.linked_modules
+            .push(crate::linking::LinkedModule::new(name, bytes));
+        self
+    }
+
+    /// Routes the guest's host calls to the provider's namespace to the provider instead of the
+    /// host callback, configuring it for this module with the given values. A provider
+    /// registered later for the same namespace replaces the earlier one. See the
+    /// [capability](capability/index.html) module.
+    pub fn capability(
+        mut self,
+        provider: Arc<dyn CapabilityProvider>,
+        config: CapabilityConfig,
+    ) -> Self {
+        let namespace = provider.namespace().to_string();
+        self.options.capability_configs.retain(|(ns, _)| *ns != namespace);
+        self.options.capability_configs.push((namespace.clone(), config));
+        self.capabilities.insert(namespace, provider);
+        self
+    }
+
+    /// Registers a plugin with the host. Any middleware the plugin contributes is added to the
+    /// middleware chain at this point.
+    pub fn plugin(mut self, plugin: Arc<dyn RuntimePlugin>) -> Self {
+        if let Some(m) = plugin.middleware() {
+            self.options.middleware.push(m);
+        }
+        self.host_functions.extend(plugin.host_functions());
+        self.plugins.push(plugin);
+        self
+    }
+
+    /// Sets the sink that receives streams opened by the guest with `__host_stream_open`.
+    /// Without a sink, attempts to open a stream fail. See the [stream](stream/index.html) module.
+    pub fn stream_sink(mut self, sink: Arc<dyn StreamSink>) -> Self {
+        self.stream_sink = Some(sink);
+        self
+    }
+
+    /// Records the timing of the most recent `max_events` guest calls and host calls so they
+    /// can be exported with [export_chrome_trace](struct.WapcHost.html#method.export_chrome_trace)
+    pub fn record_timings(mut self, max_events: usize) -> Self {
+        self.timings_capacity = Some(max_events);
+        self
+    }
+
+    /// Refuses, when the host is built, modules that need more WASI than `profile` grants. See
+    /// [WasiProfile::check](wasi/enum.WasiProfile.html#method.check). Modules loaded with
+    /// [build_from_file](#method.build_from_file) are checked before the engine provider sees
+    /// them; otherwise the engine provider must expose the module through
+    /// [module_bytes](trait.WebAssemblyEngineProvider.html#method.module_bytes). The engine
+    /// provider is still configured with the profile's
+    /// [params](wasi/enum.WasiProfile.html#method.params) by the embedder.
+    #[cfg(feature = "validate")]
+    pub fn wasi_profile(mut self, profile: crate::wasi::WasiProfile) -> Self {
+        self.wasi_profile = Some(profile);
+        self
+    }
+
+    /// Limits the size of modules loaded with [build_from_file](#method.build_from_file),
+    /// which otherwise refuses modules over
+    /// [DEFAULT_MAX_MODULE_SIZE](binary/constant.DEFAULT_MAX_MODULE_SIZE.html)
+    pub fn max_module_size(mut self, bytes: u64) -> Self {
+        self.max_module_size = Some(bytes);
+        self
+    }
+
+    /// Reads the module at `path`, hands its bytes to `engine` to create the engine provider,
+    /// and builds the host with it. The bytes are dropped as soon as `engine` returns, so an
+    /// engine that compiles the module without keeping a copy holds it in memory only once.
+    /// Modules over the [maximum size](#method.max_module_size) are refused before being read.
+    pub fn build_from_file(
+        #[allow(unused_mut)] mut self,
+        path: impl AsRef<std::path::Path>,
+        engine: impl FnOnce(&[u8]) -> Box<dyn WebAssemblyEngineProvider>,
+    ) -> Result<WapcHost> {
+        let max_size = self
+            .max_module_size
+            .unwrap_or(crate::binary::DEFAULT_MAX_MODULE_SIZE);
+        let bytes = crate::binary::load_module_file(path, max_size)?;
+        #[cfg(feature = "validate")]
+        if let Some(profile) = self.wasi_profile.take() {
+            profile.check(&crate::validate_module(&bytes)?)?;
+        }
+        let engine = engine(&bytes);
+        drop(bytes);
+        self.build(engine)
+    }
+
+    /// Creates the host, pairing it with the given engine provider and initializing the
+    /// guest module
+    pub fn build(self, engine: Box<dyn WebAssemblyEngineProvider>) -> Result<WapcHost> {
+        let settings = &self.engine_settings;
+        if let (Some(max), Some(stack)) = (settings.max_wasm_stack, settings.async_stack_size) {
+            if stack <= max {
+                return Err(crate::errors::new(crate::errors::ErrorKind::WasmMisc(format!(
+                    "The async stack size ({}) must exceed the maximum wasm stack ({})",
+                    stack, max
+                ))));
+            }
+        }
+        if settings.debug_info && settings.strip_debug_sections {
+            return Err(crate::errors::new(crate::errors::ErrorKind::WasmMisc(
+                "Debug sections cannot be stripped when debug info is enabled".to_string(),
+            )));
+        }
+        if settings.deterministic && (settings.threads || settings.relaxed_simd) {
+            return Err(crate::errors::new(crate::errors::ErrorKind::WasmMisc(
+                "Wasm threads and relaxed SIMD cannot be enabled in deterministic mode".to_string(),
+            )));
+        }
+        if settings.memory_export.is_empty() {
+            return Err(crate::errors::new(crate::errors::ErrorKind::WasmMisc(
+                "The memory export name cannot be empty".to_string(),
+            )));
+        }
+        if settings.pooling_allocation.map(|p| p.total_instances) == Some(0) {
+            return Err(crate::errors::new(crate::errors::ErrorKind::WasmMisc(
+                "The instance pool must allow at least one instance".to_string(),
+            )));
+        }
+        #[cfg(feature = "validate")]
+        if let Some(ref profile) = self.wasi_profile {
+            let bytes = engine.module_bytes().ok_or_else(|| {
+                crate::errors::new(crate::errors::ErrorKind::WasiConfiguration(
+                    "the engine provider does not expose the module to check it against the \
+                     WASI profile; use build_from_file"
+                        .to_string(),
+                ))
+            })?;
+            profile.check(&crate::validate_module(bytes)?)?;
+        }
+        crate::linking::check_names(&self.linked_modules)?;
+        let id = self
+            .id
+            .unwrap_or_else(|| GLOBAL_MODULE_COUNT.fetch_add(1, Ordering::SeqCst));
+        let mut state = ModuleState::new(self.handler, id);
+        if let Some(ref name) = self.string_id {
+            crate::errors::name_module(id, name.clone());
+        }
+        state.string_id = self.string_id;
+        state.loggers = RwLock::new(self.loggers);
+        state.time_imports = self.time_imports && !self.engine_settings.deterministic;
+        state.timings = self.timings_capacity.map(TimingRecorder::new);
+        state.stream_sink = self.stream_sink;
+        state.plugins = self.plugins;
+        state.extensions = self.extensions;
+        state.host_functions = self.host_functions;
+        state.linked_modules = self.linked_modules;
+        state.engine_settings = self.engine_settings;
+        state.capabilities = self.capabilities;
+        state.advertised = self.advertised;
+        state.max_response_size = self.max_response_size;
+        state.host_calls = crate::host_call::Policies {
+            panic_policy: self.panic_policy,
+            retries: self.retries,
+            breakers: self.breakers,
+            #[cfg(feature = "audit")]
+            audit_sink: self.audit_sink,
+            #[cfg(feature = "audit")]
+            policy: self.policy,
+        };
+
+        WapcHost::create(engine, state, self.options)
+    }
+}
@@ -0,0 +1,202 @@
+//! Memoization of guest responses for operations that are pure functions of their payload.
+//!
+//! A [ResponseCache](struct.ResponseCache.html) is call middleware that answers repeated calls
+//! from an LRU cache of successful responses, keyed by operation and payload, without invoking
+//! the guest. Enable it with
+//! [WapcHostBuilder::cache](../struct.WapcHostBuilder.html#method.cache), or register one with
+//! [WapcHostBuilder::middleware](../struct.WapcHostBuilder.html#method.middleware) to share it
+//! between hosts running the same module.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::middleware::CallMiddleware;
+use crate::Result;
+
+type Key = (String, Vec<u8>);
+
+struct Entry {
+    response: Vec<u8>,
+    inserted: Instant,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<Key, Entry>,
+    /// Keys ordered from least to most recently used
+    order: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+impl Lru {
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+        }
+    }
+}
+
+/// An LRU cache of guest responses. See the [cache](index.html) module.
+pub struct ResponseCache {
+    operations: HashSet<String>,
+    capacity: usize,
+    ttl: Duration,
+    lru: Mutex<Lru>,
+}
+
+impl ResponseCache {
+    /// Creates a cache holding up to `capacity` responses to the given operations, each for at
+    /// most `ttl`
+    pub fn new(operations: &[&str], capacity: usize, ttl: Duration) -> ResponseCache {
+        ResponseCache {
+            operations: operations.iter().map(|op| op.to_string()).collect(),
+            capacity,
+            ttl,
+            lru: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// Discards the cached response to an operation with the given payload
+    pub fn invalidate(&self, operation: &str, payload: &[u8]) {
+        self.lru.lock().unwrap().remove(&key(operation, payload));
+    }
+
+    /// Discards all cached responses to an operation
+    pub fn invalidate_operation(&self, operation: &str) {
+        let mut lru = self.lru.lock().unwrap();
+        let keys: Vec<Key> = lru
+            .entries
+            .keys()
+            .filter(|(op, _)| op == operation)
+            .cloned()
+            .collect();
+        for k in keys.iter() {
+            lru.remove(k);
+        }
+    }
+
+    /// Discards all cached responses
+    pub fn clear(&self) {
+        *self.lru.lock().unwrap() = Lru::default();
+    }
+
+    /// The number of responses currently cached
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn key(operation: &str, payload: &[u8]) -> Key {
+    (operation.to_string(), payload.to_vec())
+}
+
+impl CallMiddleware for ResponseCache {
+    fn short_circuit(
+        &self,
+        _module_id: u64,
+        operation: &str,
+        payload: &[u8],
+    ) -> Option<Result<Vec<u8>>> {
+        if !self.operations.contains(operation) {
+            return None;
+        }
+        let key = key(operation, payload);
+        let mut lru = self.lru.lock().unwrap();
+        let lru = &mut *lru;
+        let expired = match lru.entries.get_mut(&key) {
+            Some(entry) if entry.inserted.elapsed() <= self.ttl => {
+                lru.tick += 1;
+                let k = lru.order.remove(&entry.tick).unwrap();
+                entry.tick = lru.tick;
+                lru.order.insert(lru.tick, k);
+                return Some(Ok(entry.response.clone()));
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            lru.remove(&key);
+        }
+        None
+    }
+
+    fn after_call(
+        &self,
+        _module_id: u64,
+        operation: &str,
+        payload: &[u8],
+        result: &mut Result<Vec<u8>>,
+    ) {
+        if !self.operations.contains(operation) {
+            return;
+        }
+        let response = match result {
+            Ok(response) if self.capacity > 0 => response.clone(),
+            _ => return,
+        };
+        let key = key(operation, payload);
+        let mut lru = self.lru.lock().unwrap();
+        // A hit, or a response another host stored meanwhile, keeps its original expiry
+        if lru.entries.contains_key(&key) {
+            return;
+        }
+        while lru.entries.len() >= self.capacity {
+            let (_, oldest) = lru.order.pop_first().unwrap();
+            lru.entries.remove(&oldest);
+        }
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.order.insert(tick, key.clone());
+        lru.entries.insert(
+            key,
+            Entry {
+                response,
+                inserted: Instant::now(),
+                tick,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn miss_then_store(cache: &ResponseCache, op: &str, payload: &[u8], response: &[u8]) {
+        assert!(cache.short_circuit(1, op, payload).is_none());
+        cache.after_call(1, op, payload, &mut Ok(response.to_vec()));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = ResponseCache::new(&["get"], 2, Duration::from_secs(60));
+        miss_then_store(&cache, "get", b"a", b"A");
+        miss_then_store(&cache, "get", b"b", b"B");
+        assert_eq!(cache.short_circuit(1, "get", b"a").unwrap().unwrap(), b"A");
+        miss_then_store(&cache, "get", b"c", b"C");
+
+        assert!(cache.short_circuit(1, "get", b"b").is_none());
+        assert!(cache.short_circuit(1, "get", b"a").is_some());
+        assert!(cache.short_circuit(1, "put", b"a").is_none());
+
+        cache.invalidate_operation("get");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn hosts_sharing_a_module_id_store_their_own_responses() {
+        let cache = ResponseCache::new(&["get"], 4, Duration::from_secs(60));
+        assert!(cache.short_circuit(7, "get", b"a").is_none());
+        assert!(cache.short_circuit(7, "get", b"b").is_none());
+        cache.after_call(7, "get", b"a", &mut Ok(b"A".to_vec()));
+        cache.after_call(7, "get", b"b", &mut Ok(b"B".to_vec()));
+        assert_eq!(cache.short_circuit(7, "get", b"a").unwrap().unwrap(), b"A");
+        assert_eq!(cache.short_circuit(7, "get", b"b").unwrap().unwrap(), b"B");
+    }
+}
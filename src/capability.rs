@@ -0,0 +1,55 @@
+//! Capability providers: pluggable implementations of the host calls made to a namespace.
+//!
+//! In the wascc model, a guest requests a capability such as a key-value store by making host
+//! calls to a well-known namespace (e.g. `wapc:keyvalue`), and the host routes those calls to
+//! the provider registered for it. Providers are registered with
+//! [WapcHostBuilder::capability](../struct.WapcHostBuilder.html#method.capability) and can be
+//! shipped as separate crates. Host calls to namespaces without a provider go to the host
+//! callback as usual.
+//!
+//! A single provider instance is typically shared by many hosts, so it keeps per-module state
+//! keyed by module ID: each module is configured when its host is created and removed when its
+//! host is dropped.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::HostCallContext;
+
+/// Configuration values supplied to a provider for a single module
+pub type CapabilityConfig = HashMap<String, String>;
+
+/// A provider of the host calls made to a single namespace
+pub trait CapabilityProvider: Send + Sync {
+    /// The namespace whose host calls this provider handles, e.g. `wapc:keyvalue`
+    fn namespace(&self) -> &str;
+
+    /// Called when a host using this provider is created, before its guest is initialized.
+    /// Returning an error fails the creation of the host.
+    fn configure(
+        &self,
+        _module_id: u64,
+        _config: &CapabilityConfig,
+    ) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    /// Called when a host using this provider is dropped, so per-module state can be released.
+    /// May be called for a module whose configuration failed.
+    fn remove_module(&self, _module_id: u64) {}
+
+    /// The operations this provider handles, which guests can discover through the
+    /// [introspect](../introspect/index.html) host call. An empty list, the default, means the
+    /// operations are not advertised.
+    fn operations(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Handles a host call made by a guest to this provider's namespace
+    fn handle_call(
+        &self,
+        ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+}
@@ -0,0 +1,118 @@
+//! Recording of guest call and host call timings, exportable in the Chrome `trace_event`
+//! format understood by Perfetto and `chrome://tracing`
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimingKind {
+    GuestCall,
+    HostCall,
+}
+
+impl TimingKind {
+    fn category(self) -> &'static str {
+        match self {
+            TimingKind::GuestCall => "guest_call",
+            TimingKind::HostCall => "host_call",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TimingEvent {
+    kind: TimingKind,
+    name: String,
+    start: Instant,
+    duration: Duration,
+    success: bool,
+}
+
+/// A bounded buffer of the most recent call timings for a single module
+pub(crate) struct TimingRecorder {
+    capacity: usize,
+    origin: Instant,
+    events: Mutex<VecDeque<TimingEvent>>,
+}
+
+impl TimingRecorder {
+    pub(crate) fn new(capacity: usize) -> TimingRecorder {
+        TimingRecorder {
+            capacity,
+            origin: Instant::now(),
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Records a call that began at `start` and has just completed
+    pub(crate) fn record(&self, kind: TimingKind, name: &str, start: Instant, success: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(TimingEvent {
+            kind,
+            name: name.to_string(),
+            start,
+            duration: start.elapsed(),
+            success,
+        });
+    }
+
+    /// Renders every event that completed within `window` of now as a Chrome trace. Each
+    /// module is rendered as its own thread so traces from several hosts can be merged.
+    pub(crate) fn to_chrome_trace(&self, module_id: u64, window: Duration) -> String {
+        let cutoff = Instant::now().checked_sub(window);
+        let events = self.events.lock().unwrap();
+        let trace_events: Vec<_> = events
+            .iter()
+            .filter(|e| cutoff.is_none_or(|c| e.start + e.duration >= c))
+            .map(|e| {
+                json!({
+                    "name": e.name,
+                    "cat": e.kind.category(),
+                    "ph": "X",
+                    "ts": e.start.saturating_duration_since(self.origin).as_micros() as u64,
+                    "dur": e.duration.as_micros() as u64,
+                    "pid": std::process::id(),
+                    "tid": module_id,
+                    "args": { "success": e.success },
+                })
+            })
+            .collect();
+
+        json!({
+            "traceEvents": trace_events,
+            "displayTimeUnit": "ms",
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_events_are_evicted() {
+        let recorder = TimingRecorder::new(2);
+        for name in &["a", "b", "c"] {
+            recorder.record(TimingKind::GuestCall, name, Instant::now(), true);
+        }
+        let trace: serde_json::Value =
+            serde_json::from_str(&recorder.to_chrome_trace(1, Duration::from_secs(60))).unwrap();
+        let names: Vec<_> = trace["traceEvents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, vec!["b", "c"]);
+    }
+}
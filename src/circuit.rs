@@ -0,0 +1,189 @@
+//! Circuit breakers that make host calls to a failing namespace fail fast, protecting a slow
+//! or struggling downstream from a stampede of calls originating inside guests.
+//!
+//! A [CircuitBreaker](struct.CircuitBreaker.html) is registered for a namespace with
+//! [WapcHostBuilder::circuit_breaker](../struct.WapcHostBuilder.html#method.circuit_breaker),
+//! and can be shared between hosts calling the same downstream. It watches the outcome of the
+//! most recent host calls to the namespace. Once the share of failures among them reaches the
+//! breaker's threshold the circuit opens: for the cool-down period, host calls to the
+//! namespace fail immediately with status [CIRCUIT_OPEN](constant.CIRCUIT_OPEN.html) (see the
+//! [status](../status/index.html) module) without reaching the handler. After the cool-down a
+//! single trial call is let through; the circuit closes if it succeeds and opens again if not.
+//!
+//! A [StatusError](../status/struct.StatusError.html) is an application-level reply, such as a
+//! missing key, rather than a sign of a struggling downstream, so by default it does not count
+//! as a failure. [failure_if](struct.CircuitBreaker.html#method.failure_if) changes which errors
+//! count.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::status::StatusError;
+
+type Classifier = dyn Fn(&(dyn Error + Send + Sync + 'static)) -> bool + Send + Sync;
+
+/// The status code of a host call rejected because its namespace's circuit is open
+pub const CIRCUIT_OPEN: i32 = 503;
+
+/// The state of a [CircuitBreaker](struct.CircuitBreaker.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Host calls are handled normally
+    Closed,
+    /// Host calls fail fast until the cool-down ends
+    Open,
+    /// The cool-down has ended and a trial call decides whether the circuit closes
+    HalfOpen,
+}
+
+enum Phase {
+    Closed,
+    Open { until: Instant },
+    HalfOpen { trial_in_flight: bool },
+}
+
+struct Inner {
+    phase: Phase,
+    /// The outcomes of the most recent calls while closed, true for a failure
+    outcomes: VecDeque<bool>,
+}
+
+/// Trips when too many host calls to a namespace fail. See the [circuit](index.html) module.
+pub struct CircuitBreaker {
+    failure_rate: f64,
+    window: usize,
+    cool_down: Duration,
+    is_failure: Option<Box<Classifier>>,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    /// Creates a breaker that opens for `cool_down` once the share of failures among the last
+    /// `window` host calls reaches `failure_rate`, a fraction between 0 and 1. The circuit
+    /// never opens before `window` calls have been made.
+    pub fn new(failure_rate: f64, window: usize, cool_down: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            failure_rate,
+            window: window.max(1),
+            cool_down,
+            is_failure: None,
+            inner: Mutex::new(Inner {
+                phase: Phase::Closed,
+                outcomes: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Counts only the errors for which `classifier` returns true as failures, e.g. to also
+    /// count a [StatusError](../status/struct.StatusError.html) reporting an internal error
+    pub fn failure_if(
+        mut self,
+        classifier: impl Fn(&(dyn Error + Send + Sync + 'static)) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_failure = Some(Box::new(classifier));
+        self
+    }
+
+    /// Whether a host call that failed with `error` counts against the circuit
+    pub(crate) fn is_failure(&self, error: &(dyn Error + Send + Sync + 'static)) -> bool {
+        match self.is_failure {
+            Some(ref classifier) => classifier(error),
+            None => error.downcast_ref::<StatusError>().is_none(),
+        }
+    }
+
+    /// The current state of the circuit
+    pub fn state(&self) -> CircuitState {
+        match self.inner.lock().unwrap().phase {
+            Phase::Closed => CircuitState::Closed,
+            Phase::Open { until } if Instant::now() < until => CircuitState::Open,
+            Phase::Open { .. } | Phase::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether a host call may proceed. Every call that is allowed must be followed by a
+    /// [record](#method.record) of its outcome.
+    pub(crate) fn allow(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.phase {
+            Phase::Closed => true,
+            Phase::Open { until } if Instant::now() < until => false,
+            Phase::Open { .. } => {
+                inner.phase = Phase::HalfOpen {
+                    trial_in_flight: true,
+                };
+                true
+            }
+            Phase::HalfOpen {
+                ref mut trial_in_flight,
+            } => !std::mem::replace(trial_in_flight, true),
+        }
+    }
+
+    /// Records the outcome of a host call that was allowed
+    pub(crate) fn record(&self, succeeded: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let trip = match inner.phase {
+            Phase::HalfOpen { .. } => !succeeded,
+            Phase::Open { .. } => false,
+            Phase::Closed => {
+                inner.outcomes.push_back(!succeeded);
+                if inner.outcomes.len() > self.window {
+                    inner.outcomes.pop_front();
+                }
+                let failures = inner.outcomes.iter().filter(|failed| **failed).count();
+                inner.outcomes.len() == self.window
+                    && failures as f64 >= self.failure_rate * self.window as f64
+            }
+        };
+        if trip {
+            inner.phase = Phase::Open {
+                until: Instant::now() + self.cool_down,
+            };
+            inner.outcomes.clear();
+        } else if let Phase::HalfOpen { .. } = inner.phase {
+            inner.phase = Phase::Closed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_on_failures_and_closes_after_a_successful_trial() {
+        let breaker = CircuitBreaker::new(0.5, 4, Duration::from_millis(20));
+        for succeeded in [true, false, true] {
+            assert!(breaker.allow());
+            breaker.record(succeeded);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow());
+        breaker.record(false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        breaker.record(true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn status_replies_are_not_failures_by_default() {
+        let missing = StatusError::new(404, "no such key");
+        let breaker = CircuitBreaker::new(0.5, 4, Duration::from_millis(20));
+        assert!(!breaker.is_failure(&missing));
+        assert!(breaker.is_failure(&*Box::<dyn Error + Send + Sync>::from("timed out")));
+
+        let strict = CircuitBreaker::new(0.5, 4, Duration::from_millis(20))
+            .failure_if(|e| e.downcast_ref::<StatusError>().is_none_or(|s| s.code() >= 500));
+        assert!(!strict.is_failure(&missing));
+        assert!(strict.is_failure(&StatusError::new(500, "internal error")));
+    }
+}
@@ -0,0 +1,76 @@
+//! Snapshots of what a host is doing, for diagnosing hung or misbehaving guests in production
+//! without attaching a debugger.
+//!
+//! A [DebugProbe](struct.DebugProbe.html), obtained from
+//! [WapcHost::debug_probe](../struct.WapcHost.html#method.debug_probe), can be sent to another
+//! thread and take a [DebugState](struct.DebugState.html) snapshot at any time, including while
+//! a guest call is stuck. Taking a snapshot only reads the module state; it never waits for the
+//! guest.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::ModuleState;
+
+/// The most recent host call made by a guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostCallInfo {
+    pub binding: String,
+    pub namespace: String,
+    pub operation: String,
+    /// Whether the host call is still being handled
+    pub in_flight: bool,
+}
+
+/// A snapshot of a host's state
+#[derive(Debug, Clone)]
+pub struct DebugState {
+    pub module_id: u64,
+    /// The operation of the guest call in progress, if any
+    pub operation: Option<String>,
+    /// How long the guest call in progress has been running
+    pub call_duration: Option<Duration>,
+    /// The error the guest reported for the current or most recent call
+    pub guest_error: Option<String>,
+    /// The error the host reported to the guest for its most recent host call
+    pub host_error: Option<String>,
+    /// The most recent host call made by the guest
+    pub last_host_call: Option<HostCallInfo>,
+    /// The number of calls made to the guest
+    pub calls: u64,
+    /// The number of host calls made by the guest
+    pub host_calls: u64,
+    /// The number of calls queued for the host, when it is pooled
+    pub queue_depth: usize,
+    /// Where the coredump of the most recent trap was written, when coredumps are enabled
+    pub last_coredump: Option<PathBuf>,
+}
+
+/// Takes [DebugState](struct.DebugState.html) snapshots of a host from any thread. See the
+/// [debug](index.html) module.
+#[derive(Clone)]
+pub struct DebugProbe {
+    pub(crate) state: Arc<ModuleState>,
+}
+
+impl DebugProbe {
+    /// Takes a snapshot of the host's state
+    pub fn snapshot(&self) -> DebugState {
+        let state = &self.state;
+        let in_flight = state.call_started.lock().unwrap().clone();
+        let usage = state.usage.report(state.id, None, None, 0);
+        DebugState {
+            module_id: state.id,
+            call_duration: in_flight.as_ref().map(|(_, started)| started.elapsed()),
+            operation: in_flight.map(|(op, _)| op),
+            guest_error: state.guest_error.read().unwrap().clone(),
+            host_error: state.host_error.read().unwrap().clone(),
+            last_host_call: state.last_host_call.lock().unwrap().clone(),
+            calls: usage.calls,
+            host_calls: usage.host_calls,
+            queue_depth: state.load().queue_depth,
+            last_coredump: state.last_coredump.lock().unwrap().clone(),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! A protocol extension allowing a guest to defer its response to a call and fulfill it when
+//! re-invoked later, so guests can model multi-step asynchronous workflows while remaining
+//! reactive.
+//!
+//! Instead of calling `__guest_response`, a guest defers by calling `__guest_defer` with a
+//! token of its choosing and returning success. The host tracks the token and, when the
+//! embedder calls [WapcHost::resume](../struct.WapcHost.html#method.resume), invokes the
+//! `__wapc_resume!{token}` operation on the guest with the embedder's payload. The guest may
+//! then respond, fail, or defer again. Tokens that are not resumed within the host's deferred
+//! timeout expire.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The namespace of the operation invoked on the guest to resume a deferred call. The full
+/// operation name is `__wapc_resume!{token}`.
+pub const RESUME_NAMESPACE: &str = "__wapc_resume";
+
+/// The default time a deferred call may remain pending before it expires
+pub const DEFAULT_DEFERRED_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The outcome of a call that the guest is permitted to defer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallOutcome {
+    /// The guest responded immediately
+    Complete(Vec<u8>),
+    /// The guest deferred its response; pass the token to `resume` to continue
+    Deferred(String),
+}
+
+/// Host-side bookkeeping of deferred calls awaiting resumption
+pub(crate) struct DeferredRegistry {
+    timeout: Duration,
+    pending: HashMap<String, Instant>,
+}
+
+impl DeferredRegistry {
+    pub(crate) fn new(timeout: Duration) -> DeferredRegistry {
+        DeferredRegistry {
+            timeout,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, token: &str) {
+        self.pending
+            .insert(token.to_string(), Instant::now() + self.timeout);
+    }
+
+    /// Removes the token, returning whether it was pending and had not yet expired
+    pub(crate) fn take(&mut self, token: &str) -> std::result::Result<(), String> {
+        match self.pending.remove(token) {
+            Some(deadline) if deadline >= Instant::now() => Ok(()),
+            Some(_) => Err(format!("Deferred call '{}' has expired", token)),
+            None => Err(format!("No deferred call is pending for '{}'", token)),
+        }
+    }
+
+    pub(crate) fn pending(&self) -> Vec<String> {
+        self.pending.keys().cloned().collect()
+    }
+
+    /// Removes and returns the tokens of all expired deferred calls
+    pub(crate) fn expire(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, deadline)| **deadline < now)
+            .map(|(token, _)| token.clone())
+            .collect();
+        for token in expired.iter() {
+            self.pending.remove(token);
+        }
+        expired
+    }
+}
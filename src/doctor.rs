@@ -0,0 +1,331 @@
+//! A self-test of the runtime, producing a diagnostic report to attach to support tickets.
+//! Requires the `echo-guest` feature.
+//!
+//! [doctor](fn.doctor.html) runs the built-in [echo guest](../guests/constant.ECHO.html) on the
+//! embedder's engine provider and checks that the engine compiles and runs it. A
+//! [Doctor](struct.Doctor.html) additionally checks the environment the embedder is about to run
+//! guests in: a directory that must be writable, such as the engine's compilation cache, an
+//! [EpochTicker](../epoch/struct.EpochTicker.html) that must be ticking, and a
+//! [WasiProfile](../wasi/enum.WasiProfile.html) whose grants must be available.
+//!
+//! ```ignore
+//! let report = wapc::doctor(|module| Box::new(MyEngineProvider::new(module)));
+//! eprintln!("{}", report);
+//! ```
+//!
+//! The `wapc-run` binary runs the same checks with its interpreter when invoked as
+//! `wapc-run doctor`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::epoch::{EpochCounter, EpochTicker};
+use crate::wasi::WasiProfile;
+use crate::{WapcHost, WapcHostBuilder, WebAssemblyEngineProvider};
+
+/// How long a ticker is given to tick before it is reported as stalled
+pub const TICK_TIMEOUT: Duration = Duration::from_secs(1);
+
+const ECHO_PAYLOAD: &[u8] = b"wapc doctor";
+
+/// The outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check succeeded
+    Pass,
+    /// The check succeeded, but something may limit the runtime
+    Warn,
+    /// The check failed
+    Fail,
+    /// The check was not configured, or could not run because an earlier check failed
+    Skipped,
+}
+
+/// The result of a single check
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// The name of the check, e.g. `engine`
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What was found, or why the check failed
+    pub detail: String,
+}
+
+/// The results of every check, in the order they ran
+#[derive(Debug, Clone)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Whether no check failed
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+
+    /// The result of the check named `name`, if it ran
+    pub fn check(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|c| c.name == name)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "wapc {}", env!("CARGO_PKG_VERSION"))?;
+        for check in self.checks.iter() {
+            let status = match check.status {
+                CheckStatus::Pass => "pass",
+                CheckStatus::Warn => "warn",
+                CheckStatus::Fail => "FAIL",
+                CheckStatus::Skipped => "skip",
+            };
+            writeln!(f, "[{}] {}: {}", status, check.name, check.detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the checks that need nothing but the engine provider, which `engine` creates from the
+/// bytes of the echo guest
+pub fn doctor(
+    engine: impl FnOnce(&[u8]) -> Box<dyn WebAssemblyEngineProvider>,
+) -> DoctorReport {
+    Doctor::new().run(engine)
+}
+
+/// The checks to run, configured with the parts of the environment to check
+#[derive(Default)]
+pub struct Doctor<'a> {
+    cache_dir: Option<PathBuf>,
+    ticker: Option<&'a EpochTicker>,
+    wasi_profile: Option<WasiProfile>,
+}
+
+impl<'a> Doctor<'a> {
+    /// Creates a doctor that runs only the engine checks
+    pub fn new() -> Doctor<'a> {
+        Doctor::default()
+    }
+
+    /// Checks that `dir` can be created and written to
+    pub fn cache_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.cache_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Checks that `ticker` ticks within [TICK_TIMEOUT](constant.TICK_TIMEOUT.html), and that
+    /// the engine has an epoch counter for it to advance
+    pub fn epoch_ticker(mut self, ticker: &'a EpochTicker) -> Self {
+        self.ticker = Some(ticker);
+        self
+    }
+
+    /// Checks that the grants of `profile` are available on this machine, e.g. that its
+    /// directories exist
+    pub fn wasi_profile(mut self, profile: WasiProfile) -> Self {
+        self.wasi_profile = Some(profile);
+        self
+    }
+
+    /// Runs the checks, creating the engine provider with `engine` from the bytes of the echo
+    /// guest
+    pub fn run(
+        &self,
+        engine: impl FnOnce(&[u8]) -> Box<dyn WebAssemblyEngineProvider>,
+    ) -> DoctorReport {
+        let mut checks = Vec::new();
+        let host = WapcHostBuilder::new()
+            .lazy()
+            .build(engine(crate::guests::ECHO));
+        match host {
+            Ok(ref host) => {
+                checks.push(engine_check(host));
+                checks.push(compilation_check(host));
+                checks.push(echo_check(host));
+            }
+            Err(ref e) => {
+                checks.push(fail("engine", format!("Could not create a host: {}", e)));
+                checks.push(skipped("compilation", "No host to compile the guest"));
+                checks.push(skipped("echo_guest", "No host to run the guest"));
+            }
+        }
+        checks.push(match self.cache_dir {
+            Some(ref dir) => cache_dir_check(dir),
+            None => skipped("cache_dir", "No directory given"),
+        });
+        checks.push(match self.ticker {
+            Some(ticker) => epoch_check(ticker, host.as_ref().ok()),
+            None => skipped("epoch_ticker", "No ticker given"),
+        });
+        checks.push(match self.wasi_profile {
+            Some(ref profile) => wasi_check(profile),
+            None => skipped("wasi", "No WASI profile given"),
+        });
+        DoctorReport { checks }
+    }
+}
+
+fn check(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Check {
+    Check {
+        name,
+        status,
+        detail: detail.into(),
+    }
+}
+
+fn fail(name: &'static str, detail: impl Into<String>) -> Check {
+    check(name, CheckStatus::Fail, detail)
+}
+
+fn skipped(name: &'static str, detail: impl Into<String>) -> Check {
+    check(name, CheckStatus::Skipped, detail)
+}
+
+fn engine_check(host: &WapcHost) -> Check {
+    match host.engine_info() {
+        Some(info) if info.features.is_empty() => {
+            check("engine", CheckStatus::Pass, format!("{} {}", info.name, info.version))
+        }
+        Some(info) => check(
+            "engine",
+            CheckStatus::Pass,
+            format!("{} {} ({})", info.name, info.version, info.features.join(", ")),
+        ),
+        None => check(
+            "engine",
+            CheckStatus::Warn,
+            "The engine provider does not report its engine",
+        ),
+    }
+}
+
+/// Instantiates the guest and has the engine compile it ahead of the first call, which fails
+/// where the engine's code generation is unavailable, e.g. when executable memory is denied
+fn compilation_check(host: &WapcHost) -> Check {
+    let started = Instant::now();
+    match host.ensure_initialized().and_then(|_| host.warmup(&[])) {
+        Ok(()) => check(
+            "compilation",
+            CheckStatus::Pass,
+            format!("Instantiated the guest in {:?}", started.elapsed()),
+        ),
+        Err(e) => fail("compilation", e.to_string()),
+    }
+}
+
+fn echo_check(host: &WapcHost) -> Check {
+    let started = Instant::now();
+    match host.call("echo", ECHO_PAYLOAD) {
+        Ok(ref response) if response == ECHO_PAYLOAD => check(
+            "echo_guest",
+            CheckStatus::Pass,
+            format!("Round trip in {:?}", started.elapsed()),
+        ),
+        Ok(response) => fail(
+            "echo_guest",
+            format!("Expected the payload back, got {:?}", String::from_utf8_lossy(&response)),
+        ),
+        Err(e) => fail("echo_guest", e.to_string()),
+    }
+}
+
+fn cache_dir_check(dir: &Path) -> Check {
+    let probe = dir.join(format!(".wapc-doctor-{}", std::process::id()));
+    let written = std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&probe, b"ok"))
+        .and_then(|_| std::fs::remove_file(&probe));
+    match written {
+        Ok(()) => check("cache_dir", CheckStatus::Pass, format!("{} is writable", dir.display())),
+        Err(e) => fail("cache_dir", format!("{} is not writable: {}", dir.display(), e)),
+    }
+}
+
+/// Counts the ticks it is given
+#[derive(Default)]
+struct TickProbe(AtomicUsize);
+
+impl EpochCounter for TickProbe {
+    fn increment_epoch(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn epoch_check(ticker: &EpochTicker, host: Option<&WapcHost>) -> Check {
+    let probe = Arc::new(TickProbe::default());
+    ticker.register(probe.clone());
+    let deadline = Instant::now() + TICK_TIMEOUT;
+    while probe.0.load(Ordering::SeqCst) == 0 {
+        if Instant::now() >= deadline {
+            return fail(
+                "epoch_ticker",
+                format!(
+                    "The ticker did not tick within {:?}; it is stopped or only ticks manually",
+                    TICK_TIMEOUT
+                ),
+            );
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    match host.map(|host| host.engine.borrow().epoch_counter().is_some()) {
+        Some(true) => check("epoch_ticker", CheckStatus::Pass, "Ticking"),
+        Some(false) => check(
+            "epoch_ticker",
+            CheckStatus::Warn,
+            "Ticking, but the engine has no epoch counter, so calls cannot be interrupted",
+        ),
+        None => check("epoch_ticker", CheckStatus::Pass, "Ticking; the engine was not checked"),
+    }
+}
+
+fn wasi_check(profile: &WasiProfile) -> Check {
+    match profile.params() {
+        Ok(_) => check("wasi", CheckStatus::Pass, format!("{:?} can be granted", profile)),
+        Err(e) => fail("wasi", format!("{:?} cannot be granted: {}", profile, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{echo_guest, MockEngine};
+
+    #[test]
+    fn reports_on_the_engine_and_environment() {
+        let ticker = EpochTicker::start(Duration::from_millis(1));
+        let report = Doctor::new()
+            .cache_dir(std::env::temp_dir().join("wapc-doctor"))
+            .epoch_ticker(&ticker)
+            .wasi_profile(WasiProfile::PureCompute)
+            .run(|_| MockEngine::boxed(echo_guest));
+
+        assert!(report.healthy(), "{}", report);
+        assert_eq!(report.check("engine").unwrap().status, CheckStatus::Warn);
+        assert_eq!(report.check("echo_guest").unwrap().status, CheckStatus::Pass);
+        assert_eq!(report.check("cache_dir").unwrap().status, CheckStatus::Pass);
+        assert_eq!(report.check("epoch_ticker").unwrap().status, CheckStatus::Warn);
+        assert_eq!(report.check("wasi").unwrap().status, CheckStatus::Pass);
+        ticker.stop();
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let file = std::env::temp_dir().join(format!("wapc-doctor-{}", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let ticker = EpochTicker::new();
+        let report = Doctor::new()
+            .cache_dir(file.join("cache"))
+            .epoch_ticker(&ticker)
+            .run(|_| MockEngine::boxed(|_| 0));
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(!report.healthy());
+        assert_eq!(report.check("echo_guest").unwrap().status, CheckStatus::Fail);
+        assert_eq!(report.check("cache_dir").unwrap().status, CheckStatus::Fail);
+        assert_eq!(report.check("epoch_ticker").unwrap().status, CheckStatus::Fail);
+        assert_eq!(report.check("wasi").unwrap().status, CheckStatus::Skipped);
+        assert!(report.to_string().contains("[FAIL] echo_guest"));
+    }
+}
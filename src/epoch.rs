@@ -0,0 +1,189 @@
+//! Shared epoch ticking for engines that interrupt guests with epoch-based deadlines.
+//!
+//! Engines such as wasmtime bound guest execution by counting epochs: a call is interrupted
+//! once the engine's epoch passes its deadline. Something has to increment that epoch at a
+//! steady rate, once per engine rather than once per host, and stop doing so when the engine is
+//! dropped. An [EpochTicker](struct.EpochTicker.html) does this for every engine registered
+//! with it, either on its own background thread ([start](struct.EpochTicker.html#method.start))
+//! or whenever the embedder calls [tick](struct.EpochTicker.html#method.tick).
+//!
+//! Engine providers expose their engine's counter through
+//! [WebAssemblyEngineProvider::epoch_counter](../trait.WebAssemblyEngineProvider.html#method.epoch_counter),
+//! returning the same `Arc` from every provider that shares an engine so that it is ticked
+//! only once.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::WapcHost;
+
+/// An engine-wide epoch counter, implemented by engine providers
+pub trait EpochCounter: Send + Sync {
+    /// Advances the engine's epoch by one
+    fn increment_epoch(&self);
+}
+
+#[derive(Default)]
+struct Counters(Mutex<Vec<Weak<dyn EpochCounter>>>);
+
+impl Counters {
+    fn register(&self, counter: &Arc<dyn EpochCounter>) -> bool {
+        let mut counters = self.0.lock().unwrap();
+        counters.retain(|c| c.strong_count() > 0);
+        if counters.iter().any(|c| same_counter(c, counter)) {
+            return false;
+        }
+        counters.push(Arc::downgrade(counter));
+        true
+    }
+
+    fn tick(&self) -> usize {
+        let live: Vec<_> = {
+            let mut counters = self.0.lock().unwrap();
+            counters.retain(|c| c.strong_count() > 0);
+            counters.iter().filter_map(Weak::upgrade).collect()
+        };
+        // Increment outside the lock so that a slow engine cannot block registration
+        for counter in live.iter() {
+            counter.increment_epoch();
+        }
+        live.len()
+    }
+}
+
+fn same_counter(a: &Weak<dyn EpochCounter>, b: &Arc<dyn EpochCounter>) -> bool {
+    std::ptr::eq(a.as_ptr() as *const (), Arc::as_ptr(b) as *const ())
+}
+
+/// Increments the epoch of every registered engine, each time it ticks. Engines are held
+/// weakly, so they are released when the last host using them is dropped.
+pub struct EpochTicker {
+    counters: Arc<Counters>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Default for EpochTicker {
+    fn default() -> Self {
+        EpochTicker::new()
+    }
+}
+
+impl EpochTicker {
+    /// Creates a ticker that only ticks when [tick](#method.tick) is called
+    pub fn new() -> EpochTicker {
+        EpochTicker {
+            counters: Arc::new(Counters::default()),
+            stop: None,
+            thread: None,
+        }
+    }
+
+    /// Creates a ticker that ticks every `interval` on a background thread until it is stopped
+    /// or dropped. Ticks keep to a fixed rate, so a late tick does not delay the ones after it.
+    pub fn start(interval: Duration) -> EpochTicker {
+        let counters = Arc::new(Counters::default());
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let counters = counters.clone();
+            std::thread::spawn(move || {
+                let mut next = Instant::now() + interval;
+                loop {
+                    match stopped.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                        Err(RecvTimeoutError::Timeout) => {
+                            counters.tick();
+                            next += interval;
+                        }
+                        _ => return,
+                    }
+                }
+            })
+        };
+        EpochTicker {
+            counters,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Registers an engine's epoch counter. Returns `false` if it was already registered.
+    pub fn register(&self, counter: Arc<dyn EpochCounter>) -> bool {
+        self.counters.register(&counter)
+    }
+
+    /// Registers the epoch counter of the engine behind `host`. Returns `false` if the engine
+    /// provider has no epoch counter or its engine was already registered.
+    pub fn register_host(&self, host: &WapcHost) -> bool {
+        match host.engine.borrow().epoch_counter() {
+            Some(counter) => self.register(counter),
+            None => false,
+        }
+    }
+
+    /// Increments the epoch of every registered engine that is still alive, returning how many
+    /// were incremented
+    pub fn tick(&self) -> usize {
+        self.counters.tick()
+    }
+
+    /// Stops the background thread, if the ticker was started with one, and waits for it to
+    /// exit
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for EpochTicker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[derive(Default)]
+    struct Counter(AtomicU64);
+
+    impl EpochCounter for Counter {
+        fn increment_epoch(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn engines_are_ticked_once_until_dropped() {
+        let ticker = EpochTicker::new();
+        let counter = Arc::new(Counter::default());
+        let shared: Arc<dyn EpochCounter> = counter.clone();
+        assert!(ticker.register(shared.clone()));
+        assert!(!ticker.register(shared.clone()));
+
+        assert_eq!(ticker.tick(), 1);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        drop((shared, counter));
+        assert_eq!(ticker.tick(), 0);
+    }
+
+    #[test]
+    fn background_ticker_advances_epochs() {
+        let ticker = EpochTicker::start(Duration::from_millis(1));
+        let counter = Arc::new(Counter::default());
+        ticker.register(counter.clone());
+        while counter.0.load(Ordering::SeqCst) < 3 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        ticker.stop();
+    }
+}
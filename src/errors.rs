@@ -14,60 +14,220 @@
 
 //! Library-specific error types and utility functions
 
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fmt;
+use std::sync::{Arc, OnceLock, RwLock};
 
 #[derive(Debug)]
-pub struct Error(Box<ErrorKind>);
+pub struct Error {
+    kind: Box<ErrorKind>,
+    module_id: Option<u64>,
+    module_name: Option<Arc<str>>,
+    backtrace: Option<Vec<GuestFrame>>,
+}
 
 pub fn new(kind: ErrorKind) -> Error {
-    Error(Box::new(kind))
+    Error {
+        kind: Box::new(kind),
+        module_id: None,
+        module_name: None,
+        backtrace: None,
+    }
+}
+
+/// The string IDs of the live modules that were given one, by numeric ID
+fn module_names() -> &'static RwLock<HashMap<u64, Arc<str>>> {
+    static NAMES: OnceLock<RwLock<HashMap<u64, Arc<str>>>> = OnceLock::new();
+    NAMES.get_or_init(Default::default)
+}
+
+/// Records the string ID of a module so errors associated with it can name it
+pub(crate) fn name_module(id: u64, name: Arc<str>) {
+    module_names().write().unwrap().insert(id, name);
+}
+
+/// Forgets the string ID of a module when its host is dropped
+pub(crate) fn forget_module(id: u64) {
+    module_names().write().unwrap().remove(&id);
+}
+
+/// The string ID the module with the given numeric ID was built with, if any
+pub(crate) fn module_name(id: u64) -> Option<Arc<str>> {
+    module_names().read().unwrap().get(&id).cloned()
+}
+
+/// A frame of the guest's stack at the time it trapped, innermost first. Source locations are
+/// only known for guests compiled with DWARF debug info and hosts built with
+/// [debug_info](../struct.WapcHostBuilder.html#method.debug_info) enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestFrame {
+    /// The index of the function in the module
+    pub func_index: u32,
+    /// The function's name, demangled if possible
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
 }
 
+impl fmt::Display for GuestFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.function {
+            Some(ref name) => write!(f, "{}", name)?,
+            None => write!(f, "<wasm function {}>", self.func_index)?,
+        }
+        if let Some(ref file) = self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The kinds of error produced by the library. More kinds may be added in minor releases, so
+/// matches on this enum need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ErrorKind {
     NoSuchFunction(String),
     IO(std::io::Error),
     WasmMisc(String),
     HostCallFailure(Box<dyn StdError + Sync + Send>),
     GuestCallFailure(String),
+    Serialization(String),
+    MissingRequiredExport { required: String, found: Vec<String> },
+    HostClosed,
+    GuestInitTimeout(std::time::Duration),
+    CapabilityConfiguration(String),
+    FeatureNotEnabled(String),
+    OperationSignatureMismatch { operation: String, expected: String, found: String },
+    ModuleTooLarge { size: u64, limit: u64 },
+    QueueFull,
+    CallTimeout(std::time::Duration),
+    GuestStackOverflow(String),
+    WasiConfiguration(String),
+    InvalidTraceContext(String),
+    ResponseTooLarge { size: u64, limit: u64 },
+    MemoryOutOfBounds { offset: u64, len: u64, size: u64 },
 }
 
 impl Error {
     pub fn kind(&self) -> &ErrorKind {
-        &self.0
+        &self.kind
     }
 
     pub fn into_kind(self) -> ErrorKind {
-        *self.0
+        *self.kind
+    }
+
+    /// The ID of the module that produced this error, if known
+    pub fn module_id(&self) -> Option<u64> {
+        self.module_id
+    }
+
+    /// The string ID of the module that produced this error, if it was built with one
+    pub fn module_name(&self) -> Option<&str> {
+        self.module_name.as_deref()
+    }
+
+    /// Decodes the guest's error classification if this error is a guest call failure. See
+    /// the [guest_error](../guest_error/index.html) module for the encoding convention.
+    pub fn guest_error(&self) -> Option<crate::guest_error::GuestError> {
+        match *self.kind {
+            ErrorKind::GuestCallFailure(ref reason) => {
+                Some(crate::guest_error::GuestError::decode(reason))
+            }
+            _ => None,
+        }
+    }
+
+    /// The guest's stack when it trapped, if the engine provider captured it
+    pub fn backtrace(&self) -> Option<&[GuestFrame]> {
+        self.backtrace.as_deref()
+    }
+
+    /// Attaches the guest's stack at the time of a trap to this error
+    pub fn with_backtrace(mut self, frames: Vec<GuestFrame>) -> Error {
+        self.backtrace = Some(frames);
+        self
+    }
+
+    /// Associates this error with the module that produced it
+    pub fn with_module(mut self, module_id: u64) -> Error {
+        self.module_id = Some(module_id);
+        self.module_name = module_name(module_id);
+        self
     }
 }
 
 impl StdError for Error {
     fn description(&self) -> &str {
-        match *self.0 {
+        match *self.kind {
             ErrorKind::NoSuchFunction(_) => "No such function in Wasm module",
             ErrorKind::IO(_) => "I/O error",
             ErrorKind::WasmMisc(_) => "WebAssembly failure",
             ErrorKind::HostCallFailure(_) => "Error occurred during host call",
             ErrorKind::GuestCallFailure(_) => "Guest call failure",
+            ErrorKind::Serialization(_) => "Serialization failure",
+            ErrorKind::MissingRequiredExport { .. } => "Module is missing a required export",
+            ErrorKind::HostClosed => "Host has been shut down",
+            ErrorKind::GuestInitTimeout(_) => "Guest initialization timed out",
+            ErrorKind::CapabilityConfiguration(_) => "Capability provider configuration failed",
+            ErrorKind::FeatureNotEnabled(_) => "Module requires a disabled WebAssembly feature",
+            ErrorKind::OperationSignatureMismatch { .. } => {
+                "Operation encoding does not match the guest interface"
+            }
+            ErrorKind::ModuleTooLarge { .. } => "Module exceeds the maximum module size",
+            ErrorKind::QueueFull => "Host call queue is full",
+            ErrorKind::CallTimeout(_) => "Call did not complete in time",
+            ErrorKind::GuestStackOverflow(_) => "Guest exhausted its call stack",
+            ErrorKind::WasiConfiguration(_) => "Invalid WASI configuration",
+            ErrorKind::InvalidTraceContext(_) => "Invalid W3C trace context",
+            ErrorKind::ResponseTooLarge { .. } => "Guest response is too large",
+            ErrorKind::MemoryOutOfBounds { .. } => "Guest memory access out of bounds",
         }
     }
 
     fn cause(&self) -> Option<&dyn StdError> {
-        match *self.0 {
+        match *self.kind {
             ErrorKind::NoSuchFunction(_) => None,
             ErrorKind::IO(ref err) => Some(err),
             ErrorKind::WasmMisc(_) => None,
             ErrorKind::HostCallFailure(_) => None,
             ErrorKind::GuestCallFailure(_) => None,
+            ErrorKind::Serialization(_) => None,
+            ErrorKind::MissingRequiredExport { .. } => None,
+            ErrorKind::HostClosed => None,
+            ErrorKind::GuestInitTimeout(_) => None,
+            ErrorKind::CapabilityConfiguration(_) => None,
+            ErrorKind::FeatureNotEnabled(_) => None,
+            ErrorKind::OperationSignatureMismatch { .. } => None,
+            ErrorKind::ModuleTooLarge { .. } => None,
+            ErrorKind::QueueFull => None,
+            ErrorKind::CallTimeout(_) => None,
+            ErrorKind::GuestStackOverflow(_) => None,
+            ErrorKind::WasiConfiguration(_) => None,
+            ErrorKind::InvalidTraceContext(_) => None,
+            ErrorKind::ResponseTooLarge { .. } => None,
+            ErrorKind::MemoryOutOfBounds { .. } => None,
         }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self.0 {
+        match (&self.module_name, self.module_id) {
+            (Some(name), _) => write!(f, "[module {}] ", name)?,
+            (None, Some(id)) => write!(f, "[module {}] ", id)?,
+            (None, None) => {}
+        }
+        match *self.kind {
             ErrorKind::NoSuchFunction(ref fname) => {
                 write!(f, "No such function in Wasm module: {}", fname)
             }
@@ -77,13 +237,66 @@ impl fmt::Display for Error {
                 write!(f, "Error occurred during host call: {}", err)
             }
             ErrorKind::GuestCallFailure(ref reason) => write!(f, "Guest call failure: {}", reason),
+            ErrorKind::Serialization(ref reason) => write!(f, "Serialization failure: {}", reason),
+            ErrorKind::MissingRequiredExport {
+                ref required,
+                ref found,
+            } => write!(
+                f,
+                "Module does not export required function {}; it exports [{}]. Make sure the module was built with a waPC guest SDK rather than as a plain WASI command or wasm-bindgen library",
+                required,
+                found.join(", ")
+            ),
+            ErrorKind::HostClosed => write!(f, "Host has been shut down"),
+            ErrorKind::GuestInitTimeout(ref timeout) => {
+                write!(f, "Guest initialization did not complete within {:?}", timeout)
+            }
+            ErrorKind::CapabilityConfiguration(ref reason) => {
+                write!(f, "Capability provider configuration failed: {}", reason)
+            }
+            ErrorKind::FeatureNotEnabled(ref feature) => {
+                write!(f, "Module requires the {} feature, which is not enabled", feature)
+            }
+            ErrorKind::OperationSignatureMismatch {
+                ref operation,
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "Operation {} uses the {} encoding but was called with {}",
+                operation, expected, found
+            ),
+            ErrorKind::ModuleTooLarge { size, limit } => {
+                write!(f, "Module is {} bytes, exceeding the {} byte limit", size, limit)
+            }
+            ErrorKind::QueueFull => write!(f, "Host call queue is full"),
+            ErrorKind::CallTimeout(ref timeout) => {
+                write!(f, "Call did not complete within {:?}", timeout)
+            }
+            ErrorKind::GuestStackOverflow(ref trap) => {
+                write!(f, "Guest exhausted its call stack: {}", trap)
+            }
+            ErrorKind::WasiConfiguration(ref reason) => {
+                write!(f, "Invalid WASI configuration: {}", reason)
+            }
+            ErrorKind::InvalidTraceContext(ref header) => {
+                write!(f, "Invalid traceparent: {}", header)
+            }
+            ErrorKind::ResponseTooLarge { size, limit } => {
+                write!(f, "Guest response of {} bytes exceeds the {} byte limit", size, limit)
+            }
+            ErrorKind::MemoryOutOfBounds { offset, len, size } => write!(
+                f,
+                "Access of {} bytes at offset {} is outside the guest's {} byte memory",
+                len, offset, size
+            ),
         }
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(source: std::io::Error) -> Error {
-        Error(Box::new(ErrorKind::IO(source)))
+        new(ErrorKind::IO(source))
     }
 }
 
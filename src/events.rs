@@ -0,0 +1,42 @@
+//! A lightweight event channel between host and guest.
+//!
+//! The host queues events for a guest with
+//! [WapcHost::publish_to_guest](../struct.WapcHost.html#method.publish_to_guest). The guest
+//! drains its queue by making a host call to the `__wapc_poll_events` operation in the
+//! `wapc:events` namespace; the response contains every queued event, framed as described in
+//! [decode](fn.decode.html), and an empty response means there are no events.
+//!
+//! A guest emits events with the `__wapc_emit_event` host function, and each event is
+//! delivered to every subscriber registered with
+//! [WapcHost::subscribe](../struct.WapcHost.html#method.subscribe) for its topic.
+
+/// The namespace of host calls answered by the event channel
+pub const EVENTS_NAMESPACE: &str = "wapc:events";
+
+/// The operation a guest invokes in the `wapc:events` namespace to drain its queued events
+pub const POLL_OPERATION: &str = "__wapc_poll_events";
+
+/// An event published by the host or emitted by a guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Encodes events into a `__wapc_poll_events` response. Each event is a topic frame followed by
+/// a payload frame, using the same frame encoding as the [batch](../batch/index.html) module.
+pub fn encode(events: &[Event]) -> Vec<u8> {
+    let frames: Vec<(&str, &[u8])> = events
+        .iter()
+        .map(|e| (e.topic.as_str(), e.payload.as_slice()))
+        .collect();
+    crate::batch::encode_requests(&frames)
+}
+
+/// Decodes a `__wapc_poll_events` response into its events
+pub fn decode(buf: &[u8]) -> Result<Vec<Event>, String> {
+    Ok(crate::batch::decode_requests(buf)?
+        .into_iter()
+        .map(|(topic, payload)| Event { topic, payload })
+        .collect())
+}
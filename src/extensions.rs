@@ -0,0 +1,65 @@
+//! Arbitrary typed data that embedders attach to a module, for use by their own host callbacks,
+//! handlers, plugins and host functions
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// A map holding at most one value of each type. Values are reference counted, so retrieving
+/// one never blocks other readers or writers for longer than the lookup itself.
+#[derive(Default)]
+pub struct Extensions {
+    map: RwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl Extensions {
+    /// Inserts a value, returning the previous value of the same type, if any
+    pub fn insert<T: Any + Send + Sync>(&self, value: T) -> Option<Arc<T>> {
+        self.map
+            .write()
+            .unwrap()
+            .insert(TypeId::of::<T>(), Arc::new(value))
+            .and_then(|prev| prev.downcast().ok())
+    }
+
+    /// Retrieves the value of the given type, if any
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.map
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|v| v.downcast().ok())
+    }
+
+    /// Removes and returns the value of the given type, if any
+    pub fn remove<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.map
+            .write()
+            .unwrap()
+            .remove(&TypeId::of::<T>())
+            .and_then(|v| v.downcast().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Extensions;
+    use std::sync::Mutex;
+
+    #[test]
+    fn values_are_keyed_by_type() {
+        let ext = Extensions::default();
+        assert!(ext.insert(5u32).is_none());
+        ext.insert(Mutex::new(vec!["a"]));
+
+        assert_eq!(*ext.get::<u32>().unwrap(), 5);
+        ext.get::<Mutex<Vec<&str>>>().unwrap().lock().unwrap().push("b");
+        assert_eq!(*ext.get::<Mutex<Vec<&str>>>().unwrap().lock().unwrap(), ["a", "b"]);
+
+        assert_eq!(*ext.insert(6u32).unwrap(), 5);
+        assert_eq!(*ext.remove::<u32>().unwrap(), 6);
+        assert!(ext.get::<u32>().is_none());
+        assert!(ext.get::<u64>().is_none());
+    }
+}
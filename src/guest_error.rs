@@ -0,0 +1,129 @@
+//! A standard classification for guest errors, so embedders such as API gateways can map guest
+//! failures to responses (e.g. HTTP 4xx vs 5xx) without matching on message text.
+//!
+//! Guests opt in by prefixing the error message they pass to `__guest_error` with a class tag
+//! in square brackets, e.g. `[bad_request] missing field 'name'`. Use [encode](fn.encode.html)
+//! to produce such a message. Errors without a recognized tag are `Unclassified`.
+
+use std::fmt;
+
+/// The class of failure reported by a guest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GuestErrorClass {
+    /// The request was well-formed but could not be satisfied, e.g. a failed business rule
+    UserError,
+    /// The guest failed for reasons unrelated to the request
+    InternalError,
+    /// The guest does not handle the requested operation
+    UnsupportedOperation,
+    /// The request payload was malformed
+    BadRequest,
+    /// The guest did not classify the error
+    Unclassified,
+}
+
+const TAGGED: [GuestErrorClass; 4] = [
+    GuestErrorClass::UserError,
+    GuestErrorClass::InternalError,
+    GuestErrorClass::UnsupportedOperation,
+    GuestErrorClass::BadRequest,
+];
+
+impl GuestErrorClass {
+    /// The tag used for this class in an encoded error message
+    pub fn tag(self) -> &'static str {
+        match self {
+            GuestErrorClass::UserError => "user_error",
+            GuestErrorClass::InternalError => "internal_error",
+            GuestErrorClass::UnsupportedOperation => "unsupported_operation",
+            GuestErrorClass::BadRequest => "bad_request",
+            GuestErrorClass::Unclassified => "unclassified",
+        }
+    }
+
+    /// A suggested HTTP status code for this class of error
+    pub fn http_status(self) -> u16 {
+        match self {
+            GuestErrorClass::UserError => 422,
+            GuestErrorClass::InternalError => 500,
+            GuestErrorClass::UnsupportedOperation => 501,
+            GuestErrorClass::BadRequest => 400,
+            GuestErrorClass::Unclassified => 500,
+        }
+    }
+
+    /// Whether the error was caused by the caller rather than by the guest
+    pub fn is_client_error(self) -> bool {
+        self.http_status() < 500
+    }
+}
+
+/// A guest error message split into its class and the remaining message text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestError {
+    pub class: GuestErrorClass,
+    pub message: String,
+}
+
+impl GuestError {
+    /// Decodes a raw guest error message. Messages without a recognized tag are returned whole
+    /// as `Unclassified`.
+    pub fn decode(raw: &str) -> GuestError {
+        for class in TAGGED.iter() {
+            let tag = class.tag();
+            if raw.len() > tag.len() + 1
+                && raw.starts_with('[')
+                && raw[1..].starts_with(tag)
+                && raw[tag.len() + 1..].starts_with(']')
+            {
+                return GuestError {
+                    class: *class,
+                    message: raw[tag.len() + 2..].trim_start().to_string(),
+                };
+            }
+        }
+        GuestError {
+            class: GuestErrorClass::Unclassified,
+            message: raw.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for GuestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&encode(self.class, &self.message))
+    }
+}
+
+/// Encodes an error message with its class tag, as a guest should pass it to `__guest_error`
+pub fn encode(class: GuestErrorClass, message: &str) -> String {
+    match class {
+        GuestErrorClass::Unclassified => message.to_string(),
+        _ => format!("[{}] {}", class.tag(), message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classified_messages_round_trip() {
+        for class in TAGGED.iter() {
+            let raw = encode(*class, "something went wrong");
+            let decoded = GuestError::decode(&raw);
+            assert_eq!(decoded.class, *class);
+            assert_eq!(decoded.message, "something went wrong");
+            assert_eq!(decoded.to_string(), raw);
+        }
+    }
+
+    #[test]
+    fn untagged_messages_are_unclassified() {
+        for raw in &["plain failure", "[bogus] tag", "[bad_request", "[]"] {
+            let decoded = GuestError::decode(raw);
+            assert_eq!(decoded.class, GuestErrorClass::Unclassified);
+            assert_eq!(decoded.message, *raw);
+        }
+    }
+}
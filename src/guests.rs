@@ -0,0 +1,15 @@
+//! Known-good waPC guest modules embedded in the crate, so consumers can verify their host
+//! and engine provider wiring without sourcing a `.wasm` file of their own
+
+/// A pure (non-WASI) waPC guest that responds to every operation with the payload it was
+/// given. It imports only `__guest_request` and `__guest_response`, so it never performs host
+/// calls. The source is in `src/guests/echo.wat`.
+pub const ECHO: &[u8] = include_bytes!("guests/echo.wasm");
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn echo_is_a_wasm_module() {
+        assert_eq!(&super::ECHO[..8], b"\0asm\x01\0\0\0");
+    }
+}
@@ -0,0 +1,22 @@
+;; A minimal waPC guest that responds to every operation with the payload it was given.
+;; Rebuild echo.wasm after editing with e.g. `wat2wasm echo.wat -o echo.wasm`.
+(module
+  (import "wapc" "__guest_request" (func $guest_request (param i32 i32)))
+  (import "wapc" "__guest_response" (func $guest_response (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "__guest_call") (param $op_len i32) (param $msg_len i32) (result i32)
+    (local $needed i32)
+    ;; the operation is written at offset 0 and the payload directly after it
+    (local.set $needed (i32.add (local.get $op_len) (local.get $msg_len)))
+    (if (i32.gt_u (local.get $needed) (i32.mul (memory.size) (i32.const 65536)))
+      (then
+        (if (i32.eq
+              (memory.grow
+                (i32.sub
+                  (i32.div_u (i32.add (local.get $needed) (i32.const 65535)) (i32.const 65536))
+                  (memory.size)))
+              (i32.const -1))
+          (then (return (i32.const 0))))))
+    (call $guest_request (i32.const 0) (local.get $op_len))
+    (call $guest_response (local.get $op_len) (local.get $msg_len))
+    (i32.const 1)))
@@ -0,0 +1,233 @@
+//! A thread-safe, cloneable handle to a host running on its own executor thread.
+//!
+//! When many producer threads call one host, wrapping it in a mutex makes them convoy behind
+//! each other with no limit on how many are waiting. A
+//! [WapcHostHandle](struct.WapcHostHandle.html) instead queues calls for a dedicated executor
+//! thread that owns the host, and bounds the queue:
+//! [try_call](struct.WapcHostHandle.html#method.try_call) fails immediately with `ErrorKind::QueueFull` when it is full, and
+//! [call_timeout](struct.WapcHostHandle.html#method.call_timeout) gives up with
+//! `ErrorKind::CallTimeout` rather than wait indefinitely.
+
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::errors::{self, ErrorKind};
+use crate::{HostLoad, ModuleState, Result, WapcCaller, WapcHost};
+
+type Job = Box<dyn FnOnce(&WapcHost) + Send>;
+
+struct Queue {
+    free: Mutex<usize>,
+    freed: Condvar,
+    state: Arc<ModuleState>,
+}
+
+/// A handle to a host owned by an executor thread, which runs until every clone of the handle
+/// is dropped
+#[derive(Clone)]
+pub struct WapcHostHandle {
+    jobs: Sender<Job>,
+    queue: Arc<Queue>,
+}
+
+impl WapcHostHandle {
+    /// Starts an executor thread, creates its host there with `factory`, and returns a handle
+    /// that queues at most `queue_depth` calls for it. Returns the factory's error if it fails.
+    pub fn spawn(
+        queue_depth: usize,
+        factory: impl FnOnce() -> Result<WapcHost> + Send + 'static,
+    ) -> Result<WapcHostHandle> {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let host = match factory() {
+                Ok(host) => host,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready_tx.send(Ok(host.state.clone()));
+            for job in rx {
+                job(&host);
+            }
+        });
+        let state = ready_rx.recv().map_err(|_| executor_gone())??;
+        Ok(WapcHostHandle {
+            jobs,
+            queue: Arc::new(Queue {
+                free: Mutex::new(queue_depth.max(1)),
+                freed: Condvar::new(),
+                state,
+            }),
+        })
+    }
+
+    /// Invokes an operation, waiting for room in the queue if it is full
+    pub fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut free = self.queue.free.lock().unwrap();
+        while *free == 0 {
+            free = self.queue.freed.wait(free).unwrap();
+        }
+        *free -= 1;
+        drop(free);
+        self.submit(op, payload, None)
+    }
+
+    /// Invokes an operation, failing with `ErrorKind::QueueFull` if the queue is full
+    pub fn try_call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        {
+            let mut free = self.queue.free.lock().unwrap();
+            if *free == 0 {
+                return Err(errors::new(ErrorKind::QueueFull).with_module(self.id()));
+            }
+            *free -= 1;
+        }
+        self.submit(op, payload, None)
+    }
+
+    /// Invokes an operation, failing with `ErrorKind::CallTimeout` if it has not completed
+    /// within `timeout`, including time spent waiting for room in the queue. A call that is
+    /// still queued when the timeout expires is discarded rather than run; one that has
+    /// already started runs to completion, but its result is dropped.
+    pub fn call_timeout(&self, op: &str, payload: &[u8], timeout: Duration) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + timeout;
+        let mut free = self.queue.free.lock().unwrap();
+        while *free == 0 {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Err(errors::new(ErrorKind::CallTimeout(timeout)).with_module(self.id()));
+            }
+            free = self.queue.freed.wait_timeout(free, remaining).unwrap().0;
+        }
+        *free -= 1;
+        drop(free);
+        self.submit(op, payload, Some((deadline, timeout)))
+    }
+
+    /// Returns whether the host is executing a call and how many calls are queued for it
+    pub fn load(&self) -> HostLoad {
+        self.queue.state.load()
+    }
+
+    /// Runs `f` with the host, on the executor thread, after the calls already queued. Does
+    /// not count against the queue depth.
+    pub fn execute<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WapcHost) -> R + Send + 'static,
+    ) -> Result<R> {
+        let (tx, rx) = mpsc::channel();
+        self.jobs
+            .send(Box::new(move |host| {
+                let _ = tx.send(f(host));
+            }))
+            .map_err(|_| executor_gone())?;
+        rx.recv().map_err(|_| executor_gone())
+    }
+
+    fn submit(
+        &self,
+        op: &str,
+        payload: &[u8],
+        deadline: Option<(Instant, Duration)>,
+    ) -> Result<Vec<u8>> {
+        let (tx, rx) = mpsc::channel();
+        let queue = self.queue.clone();
+        let (op, payload) = (op.to_string(), payload.to_vec());
+        queue.state.queued.fetch_add(1, Ordering::SeqCst);
+        let job: Job = Box::new(move |host| {
+            queue.state.queued.fetch_sub(1, Ordering::SeqCst);
+            *queue.free.lock().unwrap() += 1;
+            queue.freed.notify_one();
+            let result = match deadline {
+                Some((deadline, timeout)) if Instant::now() >= deadline => {
+                    Err(errors::new(ErrorKind::CallTimeout(timeout)).with_module(host.id()))
+                }
+                _ => host.call(&op, &payload),
+            };
+            let _ = tx.send(result);
+        });
+        if self.jobs.send(job).is_err() {
+            return Err(executor_gone());
+        }
+        match deadline {
+            Some((deadline, timeout)) => rx
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|e| match e {
+                    mpsc::RecvTimeoutError::Timeout => {
+                        errors::new(ErrorKind::CallTimeout(timeout)).with_module(self.id())
+                    }
+                    mpsc::RecvTimeoutError::Disconnected => executor_gone(),
+                })?,
+            None => rx.recv().map_err(|_| executor_gone())?,
+        }
+    }
+}
+
+impl WapcCaller for WapcHostHandle {
+    fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        WapcHostHandle::call(self, op, payload)
+    }
+
+    fn id(&self) -> u64 {
+        self.queue.state.id
+    }
+
+    fn replace_module(&self, module: &[u8]) -> Result<()> {
+        let module = module.to_vec();
+        self.execute(move |host| host.replace_module(&module))?
+    }
+}
+
+fn executor_gone() -> errors::Error {
+    errors::new(ErrorKind::GuestCallFailure(
+        "Host executor thread is no longer running".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{echo_guest, MockEngine};
+    use crate::ModuleState;
+
+    #[test]
+    fn bounded_queue_rejects_and_times_out() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (started_tx, release_rx) = (Mutex::new(started_tx), Mutex::new(release_rx));
+        let handle = WapcHostHandle::spawn(1, move || {
+            let guest = move |state: &ModuleState| {
+                if &*state.get_guest_request().unwrap().operation == "block" {
+                    started_tx.lock().unwrap().send(()).unwrap();
+                    release_rx.lock().unwrap().recv().unwrap();
+                }
+                echo_guest(state)
+            };
+            WapcHost::new(MockEngine::boxed(guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap();
+        assert_eq!(handle.call("echo", b"hi").unwrap(), b"hi");
+
+        let blocked = handle.clone();
+        let blocking = std::thread::spawn(move || blocked.call("block", b"1"));
+        started_rx.recv().unwrap();
+        let queued = handle.clone();
+        let waiting = std::thread::spawn(move || queued.call("echo", b"2"));
+        while handle.load().queue_depth == 0 {
+            std::thread::yield_now();
+        }
+
+        let full = handle.try_call("echo", b"3").unwrap_err();
+        assert!(matches!(full.kind(), ErrorKind::QueueFull));
+        let timeout = Duration::from_millis(20);
+        let late = handle.call_timeout("echo", b"4", timeout).unwrap_err();
+        assert!(matches!(late.kind(), ErrorKind::CallTimeout(_)));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(blocking.join().unwrap().unwrap(), b"1");
+        assert_eq!(waiting.join().unwrap().unwrap(), b"2");
+    }
+}
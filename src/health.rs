@@ -0,0 +1,35 @@
+//! Types describing the result of a [WapcHost::health_check](../struct.WapcHost.html#method.health_check)
+
+use std::time::Duration;
+
+/// The operation a guest can handle to take part in health checks. Guests should respond
+/// quickly with any (possibly empty) payload when healthy, and return an error otherwise.
+pub const HEALTH_OPERATION: &str = "__wapc_health";
+
+/// The way in which a guest's health was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthProbe {
+    /// The guest responded to the `__wapc_health` operation
+    Operation,
+    /// The guest does not handle `__wapc_health`, so its linear memory was read instead
+    Memory,
+}
+
+/// A structured report of a single health check
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// The ID of the module that was checked
+    pub module_id: u64,
+    /// Whether the guest responded, and did so within the timeout
+    pub healthy: bool,
+    /// How the guest's health was determined
+    pub probe: HealthProbe,
+    /// The time the probe took to complete
+    pub latency: Duration,
+    /// Whether the probe took longer than the requested timeout
+    pub timed_out: bool,
+    /// The guest's response to `__wapc_health`, if it handled the operation
+    pub response: Option<Vec<u8>>,
+    /// A description of the failure, if the guest is unhealthy
+    pub detail: Option<String>,
+}
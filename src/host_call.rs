@@ -0,0 +1,268 @@
+//! The stages a host call made by the guest passes through, in the order they run.
+//!
+//! [ModuleState::do_host_call](../struct.ModuleState.html#method.do_host_call) runs the
+//! [answering stages](constant.STAGES.html) in order until one of them answers the call, and
+//! dispatches the calls none of them answer to a capability provider or the host callback.
+//! Every call is then shown to the [observers](constant.OBSERVERS.html), in order, before its
+//! outcome is stored for the guest. Each stage reads only the state it needs from the
+//! `ModuleState`; behavior added to host calls belongs in a stage of its own, placed in these
+//! lists, so that the order of the checks stays visible in one place.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::chrome_trace::TimingKind;
+use crate::circuit::CircuitBreaker;
+use crate::retry::RetryPolicy;
+use crate::{
+    circuit, events, introspect, limits, panic_message, recorder, status, trace,
+    HostCallContext, ModuleState, PanicPolicy,
+};
+
+pub(crate) type HostResult = std::result::Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+
+/// The configuration of the stages that only host calls use
+#[derive(Default)]
+pub(crate) struct Policies {
+    pub(crate) panic_policy: PanicPolicy,
+    /// The retry policy of each namespace that has one
+    pub(crate) retries: HashMap<String, RetryPolicy>,
+    /// The circuit breaker of each namespace that has one
+    pub(crate) breakers: HashMap<String, Arc<CircuitBreaker>>,
+    #[cfg(feature = "audit")]
+    pub(crate) audit_sink: Option<Arc<dyn crate::audit::AuditSink>>,
+    #[cfg(feature = "audit")]
+    pub(crate) policy: Option<Arc<dyn crate::audit::HostCallPolicy>>,
+}
+
+/// A host call on its way through the stages
+pub(crate) struct HostCall<'a> {
+    pub(crate) ctx: HostCallContext<'a>,
+    pub(crate) operation: &'a str,
+    pub(crate) payload: &'a [u8],
+    pub(crate) started: Instant,
+    /// When the call was made and what the host call policy decided about it, for the audit
+    /// trail. The policy is consulted once, as the call is made, so that the audit record
+    /// shows the decision the call was handled under.
+    #[cfg(feature = "audit")]
+    pub(crate) audit: (std::time::SystemTime, crate::audit::Decision),
+}
+
+impl<'a> HostCall<'a> {
+    pub(crate) fn new(
+        state: &ModuleState,
+        ctx: HostCallContext<'a>,
+        operation: &'a str,
+        payload: &'a [u8],
+    ) -> HostCall<'a> {
+        #[cfg(feature = "audit")]
+        let audit = (
+            std::time::SystemTime::now(),
+            match state.host_calls.policy.as_ref().map(|p| p.check(&ctx, operation)) {
+                Some(Err(reason)) => crate::audit::Decision::Denied(reason),
+                _ => crate::audit::Decision::Allowed,
+            },
+        );
+        #[cfg(not(feature = "audit"))]
+        let _ = state;
+        HostCall {
+            ctx,
+            operation,
+            payload,
+            started: Instant::now(),
+            #[cfg(feature = "audit")]
+            audit,
+        }
+    }
+
+    fn is(&self, namespace: &str, operation: &str) -> bool {
+        self.ctx.namespace == namespace && self.operation == operation
+    }
+}
+
+/// A stage that may answer a host call, ending it
+type Stage = fn(&ModuleState, &HostCall) -> Option<HostResult>;
+
+/// A stage that is shown the outcome of every host call
+type Observer = fn(&ModuleState, &HostCall, &HostResult);
+
+/// The stages that may answer a host call before it is dispatched, in order: calls the host
+/// call policy denies, then calls to a namespace whose circuit is open, then the namespaces
+/// the host implements itself
+pub(crate) const STAGES: [Stage; 6] = [
+    policy,
+    circuit_breaker,
+    guest_events,
+    introspection,
+    trace_context,
+    response_limits,
+];
+
+/// The stages shown the outcome of every host call, in order
+pub(crate) const OBSERVERS: [Observer; 4] = [timings, plugins, audit_trail, recording];
+
+/// Runs `call` through the answering stages, dispatching it if none of them answers
+pub(crate) fn handle(state: &ModuleState, call: &HostCall) -> HostResult {
+    STAGES
+        .iter()
+        .find_map(|stage| stage(state, call))
+        .unwrap_or_else(|| dispatch(state, call))
+}
+
+/// Shows the outcome of `call` to every observer
+pub(crate) fn observe(state: &ModuleState, call: &HostCall, result: &HostResult) {
+    for observer in OBSERVERS.iter() {
+        observer(state, call, result);
+    }
+}
+
+/// Stores the outcome of a host call for the guest to read, returning what `__host_call`
+/// returns: 1 on success and 0 on failure
+pub(crate) fn respond(state: &ModuleState, result: HostResult) -> i32 {
+    match result {
+        Ok(v) => {
+            state.usage.bytes_in(v.len());
+            *state.host_response.write().unwrap() = Some(v);
+            state.host_status.store(status::STATUS_OK, Ordering::SeqCst);
+            1
+        }
+        Err(e) => {
+            *state.host_error.write().unwrap() = Some(format!("{}", e));
+            state.host_status.store(status::of(e.as_ref()), Ordering::SeqCst);
+            0
+        }
+    }
+}
+
+fn policy(_state: &ModuleState, _call: &HostCall) -> Option<HostResult> {
+    #[cfg(feature = "audit")]
+    if let crate::audit::Decision::Denied(ref reason) = _call.audit.1 {
+        return Some(Err(format!("Host call denied by policy: {}", reason).into()));
+    }
+    None
+}
+
+fn circuit_breaker(state: &ModuleState, call: &HostCall) -> Option<HostResult> {
+    match state.host_calls.breakers.get(call.ctx.namespace) {
+        Some(breaker) if !breaker.allow() => {
+            let message = format!("Circuit open for namespace {}", call.ctx.namespace);
+            Some(Err(Box::new(status::StatusError::new(circuit::CIRCUIT_OPEN, message))))
+        }
+        _ => None,
+    }
+}
+
+fn guest_events(state: &ModuleState, call: &HostCall) -> Option<HostResult> {
+    if !call.is(events::EVENTS_NAMESPACE, events::POLL_OPERATION) {
+        return None;
+    }
+    let queued = std::mem::take(&mut *state.guest_events.lock().unwrap());
+    Some(Ok(events::encode(&queued)))
+}
+
+fn introspection(state: &ModuleState, call: &HostCall) -> Option<HostResult> {
+    if !call.is(introspect::INTROSPECT_NAMESPACE, introspect::LIST_OPERATION) {
+        return None;
+    }
+    Some(serde_json::to_vec(&state.capabilities_report()).map_err(|e| e.into()))
+}
+
+fn trace_context(_state: &ModuleState, call: &HostCall) -> Option<HostResult> {
+    if !call.is(trace::TRACE_NAMESPACE, trace::CONTEXT_OPERATION) {
+        return None;
+    }
+    Some(Ok(call.ctx.trace.map(trace::TraceContext::encode).unwrap_or_default()))
+}
+
+fn response_limits(state: &ModuleState, call: &HostCall) -> Option<HostResult> {
+    if !call.is(limits::LIMITS_NAMESPACE, limits::MAX_RESPONSE_SIZE_OPERATION) {
+        return None;
+    }
+    Some(Ok(limits::encode(state.max_response_size)))
+}
+
+/// Hands the call to the capability provider for its namespace, or else the host callback,
+/// retrying it under the namespace's retry policy. A panic is turned into a failed call unless
+/// the host's panic policy is to abort. The outcome is recorded with the namespace's circuit
+/// breaker.
+fn dispatch(state: &ModuleState, call: &HostCall) -> HostResult {
+    let (ctx, namespace) = (&call.ctx, call.ctx.namespace);
+    let attempt = || match state.capabilities.get(namespace) {
+        Some(provider) => provider.handle_call(ctx, call.operation, call.payload),
+        None => match state.host_callback {
+            Some(ref h) => h.handle(ctx, call.operation, call.payload),
+            None => Err("Missing host callback function!".into()),
+        },
+    };
+    let handle = || match state.host_calls.retries.get(namespace) {
+        Some(policy) => policy.run(attempt),
+        None => attempt(),
+    };
+    let result = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(handle)) {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic_message(panic.as_ref());
+            if state.host_calls.panic_policy == PanicPolicy::Abort {
+                error!("Host callback panicked, aborting: {}", message);
+                std::process::abort();
+            }
+            Err(format!("Host callback panicked: {}", message).into())
+        }
+    };
+    if let Some(breaker) = state.host_calls.breakers.get(namespace) {
+        let failed = result.as_ref().err().is_some_and(|e| breaker.is_failure(e.as_ref()));
+        breaker.record(!failed);
+    }
+    result
+}
+
+fn timings(state: &ModuleState, call: &HostCall, result: &HostResult) {
+    if let Some(ref recorder) = state.timings {
+        let name = format!("{}:{}!{}", call.ctx.binding, call.ctx.namespace, call.operation);
+        recorder.record(TimingKind::HostCall, &name, call.started, result.is_ok());
+    }
+}
+
+fn plugins(state: &ModuleState, call: &HostCall, result: &HostResult) {
+    if state.plugins.is_empty() {
+        return;
+    }
+    let elapsed = call.started.elapsed();
+    let error = result.as_ref().err().map(|e| e.to_string());
+    for p in state.plugins.iter() {
+        p.on_host_call(&call.ctx, call.operation, error.as_deref(), elapsed);
+    }
+}
+
+fn audit_trail(_state: &ModuleState, _call: &HostCall, _result: &HostResult) {
+    #[cfg(feature = "audit")]
+    if let Some(ref sink) = _state.host_calls.audit_sink {
+        let (call, result) = (_call, _result);
+        sink.record(&crate::audit::AuditRecord {
+            timestamp_ms: crate::audit::timestamp_ms(call.audit.0),
+            module_id: call.ctx.module_id,
+            binding: call.ctx.binding.to_string(),
+            namespace: call.ctx.namespace.to_string(),
+            operation: call.operation.to_string(),
+            payload_sha256: crate::audit::payload_hash(call.payload),
+            decision: call.audit.1.clone(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            duration: call.started.elapsed(),
+        });
+    }
+}
+
+fn recording(state: &ModuleState, call: &HostCall, result: &HostResult) {
+    if let Some(ref mut recorder) = *state.recorder.lock().unwrap() {
+        recorder.host_call(recorder::HostCallRecord {
+            binding: call.ctx.binding.to_string(),
+            namespace: call.ctx.namespace.to_string(),
+            operation: call.operation.to_string(),
+            payload: call.payload.to_vec(),
+            outcome: recorder::Outcome::of(result),
+        });
+    }
+}
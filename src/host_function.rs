@@ -0,0 +1,182 @@
+//! Additional host functions, beyond the waPC protocol, that an embedder exposes to guests.
+//!
+//! Host functions are registered with
+//! [WapcHostBuilder::link_host_function](../struct.WapcHostBuilder.html#method.link_host_function)
+//! (or contributed by a plugin) and are available to the engine provider through
+//! [ModuleState::host_functions](../struct.ModuleState.html#method.host_functions). Engine
+//! providers must link every host function into the guest's imports before instantiating it,
+//! and invoke it with [HostFunction::call](struct.HostFunction.html#method.call) when the guest
+//! calls the import.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::ModuleState;
+
+/// The type of a WebAssembly value crossing the host function boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+/// A WebAssembly value crossing the host function boundary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Val {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Val {
+    /// The type of this value
+    pub fn ty(&self) -> ValType {
+        match self {
+            Val::I32(_) => ValType::I32,
+            Val::I64(_) => ValType::I64,
+            Val::F32(_) => ValType::F32,
+            Val::F64(_) => ValType::F64,
+        }
+    }
+}
+
+/// The signature of a host function
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+impl FuncType {
+    pub fn new(params: &[ValType], results: &[ValType]) -> FuncType {
+        FuncType {
+            params: params.to_vec(),
+            results: results.to_vec(),
+        }
+    }
+}
+
+/// The context in which a host function is invoked
+pub struct HostFunctionContext<'a> {
+    /// The state of the calling module, including its ID and extensions
+    pub state: &'a ModuleState,
+    /// The calling module's linear memory, if the engine provider can supply it
+    pub memory: Option<&'a mut [u8]>,
+}
+
+type HostFunctionCallback = dyn Fn(&mut HostFunctionContext, &[Val]) -> std::result::Result<Vec<Val>, Box<dyn Error + Send + Sync>>
+    + Send
+    + Sync;
+
+/// A host function to be linked into a guest's imports
+#[derive(Clone)]
+pub struct HostFunction {
+    module: String,
+    name: String,
+    ty: FuncType,
+    callback: Arc<HostFunctionCallback>,
+}
+
+impl fmt::Debug for HostFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("module", &self.module)
+            .field("name", &self.name)
+            .field("ty", &self.ty)
+            .finish()
+    }
+}
+
+impl HostFunction {
+    /// Creates a host function imported by guests as `module`.`name` with the given signature
+    pub fn new(
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        callback: impl Fn(
+                &mut HostFunctionContext,
+                &[Val],
+            ) -> std::result::Result<Vec<Val>, Box<dyn Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> HostFunction {
+        HostFunction {
+            module: module.to_string(),
+            name: name.to_string(),
+            ty,
+            callback: Arc::new(callback),
+        }
+    }
+
+    /// The module name under which guests import the function
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    /// The name under which guests import the function
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The signature of the function
+    pub fn ty(&self) -> &FuncType {
+        &self.ty
+    }
+
+    /// Invokes the function, verifying that the parameters and results match its signature.
+    /// An error should be surfaced to the guest as a trap.
+    pub fn call(
+        &self,
+        ctx: &mut HostFunctionContext,
+        params: &[Val],
+    ) -> std::result::Result<Vec<Val>, Box<dyn Error + Send + Sync>> {
+        if !params.iter().map(Val::ty).eq(self.ty.params.iter().cloned()) {
+            return Err(format!(
+                "Host function {}.{} expects parameters {:?}",
+                self.module, self.name, self.ty.params
+            )
+            .into());
+        }
+        let results = (self.callback)(ctx, params)?;
+        if !results.iter().map(Val::ty).eq(self.ty.results.iter().cloned()) {
+            return Err(format!(
+                "Host function {}.{} must return {:?}",
+                self.module, self.name, self.ty.results
+            )
+            .into());
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_checks_signature() {
+        let add = HostFunction::new(
+            "env",
+            "add",
+            FuncType::new(&[ValType::I32, ValType::I32], &[ValType::I32]),
+            |_, params| match params {
+                [Val::I32(a), Val::I32(b)] => Ok(vec![Val::I32(a + b)]),
+                _ => unreachable!(),
+            },
+        );
+        let state = ModuleState::default();
+        let mut ctx = HostFunctionContext {
+            state: &state,
+            memory: None,
+        };
+
+        assert_eq!(add.call(&mut ctx, &[Val::I32(2), Val::I32(3)]).unwrap(), vec![Val::I32(5)]);
+        assert!(add.call(&mut ctx, &[Val::I32(2)]).is_err());
+        assert!(add.call(&mut ctx, &[Val::I32(2), Val::I64(3)]).is_err());
+    }
+}
@@ -0,0 +1,53 @@
+//! A built-in host call that lets guests discover which namespaces and operations the host
+//! answers, so they can feature-detect optional capabilities instead of failing on first use.
+//!
+//! A guest makes a host call to the `list` operation in the `wapc:introspect` namespace; the
+//! host answers it itself with a JSON [Capabilities](struct.Capabilities.html) document listing
+//! its built-in namespaces, the namespaces of its
+//! [capability providers](../capability/index.html), and any namespaces the embedder
+//! advertised for its host callback with
+//! [WapcHostBuilder::advertise](../struct.WapcHostBuilder.html#method.advertise):
+//!
+//! ```text
+//! {"namespaces":[{"namespace":"wapc:keyvalue","operations":["get","set","del","keys"]}]}
+//! ```
+//!
+//! An empty operation list means the namespace is available but its operations are not listed.
+
+use serde::{Deserialize, Serialize};
+
+/// The namespace of host calls answered by the host's introspection
+pub const INTROSPECT_NAMESPACE: &str = "wapc:introspect";
+
+/// The operation a guest invokes in the `wapc:introspect` namespace to list the host's
+/// namespaces
+pub const LIST_OPERATION: &str = "list";
+
+/// The namespaces a host answers host calls for
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub namespaces: Vec<NamespaceInfo>,
+}
+
+/// A namespace a host answers host calls for, and the operations within it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamespaceInfo {
+    pub namespace: String,
+    pub operations: Vec<String>,
+}
+
+impl NamespaceInfo {
+    pub(crate) fn new(namespace: &str, operations: &[&str]) -> NamespaceInfo {
+        NamespaceInfo {
+            namespace: namespace.to_string(),
+            operations: operations.iter().map(|op| op.to_string()).collect(),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Returns the entry for the given namespace, if the host answers it
+    pub fn namespace(&self, namespace: &str) -> Option<&NamespaceInfo> {
+        self.namespaces.iter().find(|n| n.namespace == namespace)
+    }
+}
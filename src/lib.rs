@@ -107,6 +107,21 @@
 //! | wapc | __host_error | ptr: i32 | Instructs the host to write the host error payload to the given location |
 //! | wapc | __host_error_len | -> i32 | Queries the host for the length of the current host error (0 if none) |
 //!
+//! ## Optional Host Exports
+//! Functions that extend the core protocol. Guests that do not use them need not import them. Unless
+//! noted, they are always exported by the host.
+//!
+//! | Module         | Function       | Parameters      | Description                             |
+//! |----------------|----------------|-----------------|-----------------------------------------|
+//! | wapc | __host_time_ms | -> i64 | Returns the wall-clock time in milliseconds since the Unix epoch. Only exported when enabled on the [WapcHostBuilder](struct.WapcHostBuilder.html) |
+//! | wapc | __host_monotonic_ms | -> i64 | Returns a monotonic time in milliseconds, suitable for timeouts. Only exported when enabled on the [WapcHostBuilder](struct.WapcHostBuilder.html) |
+//! | wapc | __guest_defer | ptr: i32<br/>len: i32 | Tells the host the size and location of a token with which the guest defers its response (see the [deferred](deferred/index.html) module) |
+//! | wapc | __host_stream_open | name_ptr: i32<br/>name_len: i32<br/>-> i32 | Opens a named stream to the host, returning its ID (0 on error; see the [stream](stream/index.html) module) |
+//! | wapc | __host_stream_write | id: i32<br/>ptr: i32<br/>len: i32<br/>-> i32 | Writes a chunk to an open stream, returning 1 on success or 0 on error |
+//! | wapc | __host_stream_close | id: i32<br/>-> i32 | Closes an open stream, returning 1 on success or 0 on error |
+//! | wapc | __host_call_status | _same as `__host_call`_ | Performs a host call, returning 1 on success or a failure status code chosen by the host (see the [status](status/index.html) module) |
+//! | wapc | __wapc_emit_event | topic_ptr: i32<br/>topic_len: i32<br/>ptr: i32<br/>len: i32 | Emits an event to the host's subscribers (see the [events](events/index.html) module) |
+//!
 //!
 //! ## Required Guest Exports
 //! List of functions that must be exported by the guest (invoked by the host)
@@ -119,16 +134,85 @@
 extern crate log;
 
 pub mod errors;
+pub mod assemblyscript;
+#[cfg(feature = "audit")]
+pub mod audit;
+pub mod batch;
+pub mod binary;
+pub mod cache;
+pub mod capability;
+mod builder;
+mod chrome_trace;
+mod host_call;
+pub mod circuit;
+pub mod debug;
+pub mod deferred;
+#[cfg(feature = "echo-guest")]
+pub mod doctor;
+pub mod epoch;
+pub mod events;
+pub mod extensions;
+#[cfg(feature = "echo-guest")]
+pub mod guests;
+pub mod guest_error;
+pub mod handle;
+pub mod health;
+pub mod host_function;
+pub mod introspect;
+pub mod limits;
+pub mod linking;
+pub mod memory;
+pub mod metadata;
+pub mod middleware;
+pub mod mock;
+pub mod plugin;
+pub mod pool;
+pub mod providers;
+pub mod recorder;
+pub mod retry;
+pub mod route;
+#[cfg(any(
+    feature = "grpc",
+    feature = "http-server",
+    feature = "json-rpc",
+    feature = "nats"
+))]
+pub mod server;
+pub mod status;
+pub mod shutdown;
+pub mod stats;
+pub mod stream;
+pub mod trace;
+pub mod usage;
+pub mod wasi;
+#[cfg(feature = "validate")]
+pub mod validate;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
+
+pub use builder::WapcHostBuilder;
+#[cfg(feature = "echo-guest")]
+pub use doctor::doctor;
+pub use handle::WapcHostHandle;
+pub use pool::WapcHostPool;
+pub use recorder::replay;
+pub use usage::ResourceReport;
+#[cfg(feature = "validate")]
+pub use validate::{validate_module, ModuleReport};
+
+use chrome_trace::{TimingKind, TimingRecorder};
 
 
 /// A result type for errors that occur within the wapc library
 pub type Result<T> = std::result::Result<T, errors::Error>;
 
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
 
 use std::error::Error;
-use std::cell::RefCell;
-use std::sync::{Arc, RwLock};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 static GLOBAL_MODULE_COUNT: AtomicU64 = AtomicU64::new(1);
 
@@ -150,6 +234,18 @@ impl WapcFunctions {
     pub const HOST_ERROR_FN: &'static str = "__host_error";
     pub const HOST_ERROR_LEN_FN: &'static str = "__host_error_len";
 
+    // -- Protocol extensions called by guest, exported by host
+    pub const GUEST_DEFER_FN: &'static str = "__guest_defer";
+    pub const HOST_STREAM_OPEN_FN: &'static str = "__host_stream_open";
+    pub const HOST_STREAM_WRITE_FN: &'static str = "__host_stream_write";
+    pub const HOST_STREAM_CLOSE_FN: &'static str = "__host_stream_close";
+    pub const EMIT_EVENT_FN: &'static str = "__wapc_emit_event";
+    pub const HOST_CALL_STATUS_FN: &'static str = "__host_call_status";
+
+    // -- Optional functions called by guest, exported by host only when enabled
+    pub const HOST_TIME_MS_FN: &'static str = "__host_time_ms";
+    pub const HOST_MONOTONIC_MS_FN: &'static str = "__host_monotonic_ms";
+
     // -- Functions called by host, exported by guest
     pub const GUEST_CALL: &'static str = "__guest_call";
     pub const WAPC_INIT: &'static str = "wapc_init";
@@ -159,13 +255,23 @@ impl WapcFunctions {
     pub const REQUIRED_STARTS: [&'static str;2] = [Self::TINYGO_START, Self::WAPC_INIT];
 }
 
-/// Parameters defining the options for enabling WASI on a module (if applicable)
+/// Parameters defining the options for enabling WASI on a module (if applicable). Prefer
+/// [builder](#method.builder), which names each setting and validates them.
 #[derive(Debug, Default)]
 pub struct WasiParams {
     pub argv: Vec<String>,
     pub map_dirs: Vec<(String, String)>,
     pub env_vars: Vec<(String, String)>,
     pub preopened_dirs: Vec<String>,
+    /// Host directories preopened for reading only. Engine providers must refuse writes to
+    /// them.
+    pub read_only_dirs: Vec<String>,
+    /// Whether the guest may read the host process's stdin
+    pub inherit_stdin: bool,
+    /// Whether the guest may write to the host process's stdout
+    pub inherit_stdout: bool,
+    /// Whether the guest may write to the host process's stderr
+    pub inherit_stderr: bool,
 }
 
 impl WasiParams {
@@ -180,6 +286,150 @@ impl WasiParams {
             map_dirs,
             preopened_dirs,
             env_vars,
+            ..WasiParams::default()
+        }
+    }
+
+    /// Returns a [WasiParamsBuilder](wasi/struct.WasiParamsBuilder.html) that grants the guest
+    /// nothing until told otherwise
+    pub fn builder() -> wasi::WasiParamsBuilder {
+        wasi::WasiParamsBuilder::deny_all()
+    }
+}
+
+/// Identifies the WebAssembly engine behind an engine provider, so embedders that build
+/// plugins against a particular engine version can assert compatibility at startup
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EngineInfo {
+    /// The engine's name, e.g. `wasmtime`
+    pub name: String,
+    /// The version of the engine crate the provider was built with
+    pub version: String,
+    /// The engine features the provider enabled, e.g. `cranelift` or `pooling-allocator`
+    pub features: Vec<String>,
+}
+
+/// Settings that affect how the engine provider compiles and executes the guest. They are
+/// configured on the [WapcHostBuilder](struct.WapcHostBuilder.html) and read by engine providers
+/// from [ModuleState::engine_settings](struct.ModuleState.html#method.engine_settings) during
+/// initialization. Engine providers must refuse modules that need a disabled feature with a
+/// [FeatureNotEnabled](errors/enum.ErrorKind.html#variant.FeatureNotEnabled) error; with the
+/// `validate` feature, [validate::check_features](validate/fn.check_features.html) does this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EngineSettings {
+    /// Execute the guest deterministically, as required for consensus-critical workloads. The
+    /// engine provider must disable threads and relaxed SIMD, canonicalize NaNs, give WASI a
+    /// fixed clock and random source, and refuse to instantiate modules that import other
+    /// nondeterministic functions. The host's time imports are never exported in this mode.
+    pub deterministic: bool,
+    /// Enable the threads proposal: shared memories and atomics. The engine provider must
+    /// allocate memories the module declares as shared and let guest-spawned threads (such as
+    /// those started through `wasi-threads`) run against them. waPC's invocation model is
+    /// unchanged: a host runs one guest call at a time, and host calls are only accepted from
+    /// the thread executing that call. A host call made from any other thread fails without
+    /// touching the call in progress, so guest workers must hand results back to the calling
+    /// thread through shared memory. Cannot be combined with `deterministic`.
+    pub threads: bool,
+    /// Enable the fixed-width SIMD proposal. On by default.
+    pub simd: bool,
+    /// Enable the relaxed SIMD proposal, whose results may differ between hardware platforms.
+    /// Requires `simd`, and cannot be combined with `deterministic`.
+    pub relaxed_simd: bool,
+    /// The maximum stack space, in bytes, that guest code may use before it traps. Traps caused
+    /// by exhausting it are reported as
+    /// [GuestStackOverflow](errors/enum.ErrorKind.html#variant.GuestStackOverflow) errors.
+    /// `None` leaves the engine's default in place.
+    pub max_wasm_stack: Option<usize>,
+    /// The size, in bytes, of the native stacks engines allocate for guest calls that run
+    /// asynchronously. Must be larger than `max_wasm_stack`. `None` leaves the engine's default
+    /// in place.
+    pub async_stack_size: Option<usize>,
+    /// Capture a coredump, in the standard wasm-coredump format including the guest's memory
+    /// and stack, whenever a guest call traps. The engine provider hands it to the host through
+    /// [take_coredump](trait.WebAssemblyEngineProvider.html#method.take_coredump).
+    pub coredump_on_trap: bool,
+    /// Read the DWARF debug info of guests compiled with it, so that traps carry source-level
+    /// backtraces. The engine provider hands them to the host through
+    /// [take_backtrace](trait.WebAssemblyEngineProvider.html#method.take_backtrace).
+    pub debug_info: bool,
+    /// Strip DWARF custom sections from modules before the engine provider caches their
+    /// compiled form, to keep the cache small. Cannot be combined with `debug_info`.
+    pub strip_debug_sections: bool,
+    /// Allocate instances from a pre-reserved pool rather than mapping memory for each one,
+    /// which makes instantiating short-lived guests much cheaper. Engine providers that share
+    /// an engine between hosts should size the shared pool from these settings. `None` uses
+    /// the engine's on-demand allocator.
+    pub pooling_allocation: Option<PoolingAllocation>,
+    /// Enable the multi-memory proposal, letting guests define or import more than one linear
+    /// memory. Payloads are still exchanged through the single memory named by `memory_export`.
+    pub multi_memory: bool,
+    /// The name of the exported memory the engine provider reads and writes waPC payloads,
+    /// errors and host call arguments through. Defaults to `memory`. Guests whose data memory
+    /// is exported under another name, or that export several memories, select it here.
+    pub memory_export: String,
+    /// Interrupt guest calls every this many epochs to hand the rest of their time slice back
+    /// to the host: from the engine's epoch deadline callback, the engine provider calls
+    /// [ModuleState::yield_time_slice](struct.ModuleState.html#method.yield_time_slice) and then
+    /// extends the deadline by the same amount rather than trapping. The engine's epoch must be
+    /// advanced, e.g. by an [EpochTicker](epoch/struct.EpochTicker.html). `None` never yields.
+    pub time_slice_epochs: Option<u64>,
+}
+
+/// Limits of a pooling instance allocator, such as wasmtime's, which reserves the memory for
+/// every instance up front so that instantiation only has to claim a free slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PoolingAllocation {
+    /// The number of instances that can exist at once. Instantiating beyond it fails.
+    pub total_instances: u32,
+    /// The largest linear memory, in bytes, a pooled instance may grow to. `None` leaves the
+    /// engine's default in place.
+    pub max_memory_size: Option<usize>,
+    /// The largest number of elements a pooled instance's tables may hold. `None` leaves the
+    /// engine's default in place.
+    pub table_elements: Option<usize>,
+}
+
+impl PoolingAllocation {
+    /// Creates pooling limits for up to `total_instances` instances at once
+    pub fn new(total_instances: u32) -> PoolingAllocation {
+        PoolingAllocation {
+            total_instances,
+            max_memory_size: None,
+            table_elements: None,
+        }
+    }
+
+    /// Limits the linear memory of each pooled instance, in bytes
+    pub fn max_memory_size(mut self, bytes: usize) -> PoolingAllocation {
+        self.max_memory_size = Some(bytes);
+        self
+    }
+
+    /// Limits the number of elements in each pooled instance's tables
+    pub fn table_elements(mut self, elements: usize) -> PoolingAllocation {
+        self.table_elements = Some(elements);
+        self
+    }
+}
+
+impl Default for EngineSettings {
+    fn default() -> EngineSettings {
+        EngineSettings {
+            deterministic: false,
+            threads: false,
+            simd: true,
+            relaxed_simd: false,
+            max_wasm_stack: None,
+            async_stack_size: None,
+            coredump_on_trap: false,
+            debug_info: false,
+            strip_debug_sections: false,
+            pooling_allocation: None,
+            multi_memory: false,
+            memory_export: "memory".to_string(),
+            time_slice_epochs: None,
         }
     }
 }
@@ -194,22 +444,113 @@ pub struct ModuleState {
     host_response: RwLock<Option<Vec<u8>>>,
     guest_error: RwLock<Option<String>>,
     host_error: RwLock<Option<String>>,
-    host_callback: Option<Box<HostCallback>>,
+    guest_deferred: RwLock<Option<String>>,
+    host_callback: Option<Arc<dyn HostHandler>>,
+    loggers: RwLock<Vec<Box<LogCallback>>>,
+    busy: AtomicBool,
+    queued: AtomicUsize,
+    closed: AtomicBool,
+    time_imports: bool,
+    timings: Option<TimingRecorder>,
+    stream_sink: Option<Arc<dyn stream::StreamSink>>,
+    streams: Mutex<HashMap<i32, Box<dyn stream::StreamWriter>>>,
+    next_stream_id: AtomicI32,
+    host_status: AtomicI32,
+    plugins: Vec<Arc<dyn plugin::RuntimePlugin>>,
+    guest_events: Mutex<Vec<events::Event>>,
+    subscribers: RwLock<Vec<(Option<String>, Box<EventCallback>)>>,
+    extensions: extensions::Extensions,
+    host_functions: Vec<host_function::HostFunction>,
+    linked_modules: Vec<linking::LinkedModule>,
+    recorder: Mutex<Option<recorder::Recorder>>,
+    engine_settings: EngineSettings,
+    capabilities: HashMap<String, Arc<dyn capability::CapabilityProvider>>,
+    usage: usage::UsageCounters,
+    call_thread: Mutex<Option<std::thread::ThreadId>>,
+    call_started: Mutex<Option<(String, Instant)>>,
+    last_host_call: Mutex<Option<debug::HostCallInfo>>,
+    last_coredump: Mutex<Option<std::path::PathBuf>>,
+    host_calls: host_call::Policies,
+    advertised: Vec<introspect::NamespaceInfo>,
+    max_response_size: Option<usize>,
+    oversized_response: RwLock<Option<usize>>,
+    trace: RwLock<Option<trace::TraceContext>>,
+    time_slicer: RwLock<Option<Arc<TimeSlicer>>>,
     id: u64,
+    string_id: Option<Arc<str>>,
 }
 
 impl ModuleState {
-    pub(crate) fn new(host_callback: Box<HostCallback>, id: u64) -> ModuleState {
+    pub(crate) fn new(host_callback: Option<Arc<dyn HostHandler>>, id: u64) -> ModuleState {
         ModuleState {
-            host_callback: Some(Box::new(host_callback)),
+            host_callback,
             id,
+            string_id: None,
             guest_request: RwLock::new(None),
             guest_response: RwLock::new(None),
             host_response: RwLock::new(None),
             guest_error: RwLock::new(None),
             host_error: RwLock::new(None),
+            guest_deferred: RwLock::new(None),
+            loggers: RwLock::new(Vec::new()),
+            busy: AtomicBool::new(false),
+            queued: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            time_imports: false,
+            timings: None,
+            stream_sink: None,
+            streams: Mutex::new(HashMap::new()),
+            next_stream_id: AtomicI32::new(1),
+            host_status: AtomicI32::new(status::STATUS_OK),
+            plugins: Vec::new(),
+            guest_events: Mutex::new(Vec::new()),
+            subscribers: RwLock::new(Vec::new()),
+            extensions: extensions::Extensions::default(),
+            host_functions: Vec::new(),
+            linked_modules: Vec::new(),
+            recorder: Mutex::new(None),
+            engine_settings: EngineSettings::default(),
+            capabilities: HashMap::new(),
+            usage: usage::UsageCounters::default(),
+            call_thread: Mutex::new(None),
+            call_started: Mutex::new(None),
+            last_host_call: Mutex::new(None),
+            last_coredump: Mutex::new(None),
+            host_calls: host_call::Policies::default(),
+            advertised: Vec::new(),
+            max_response_size: None,
+            oversized_response: RwLock::new(None),
+            trace: RwLock::new(None),
+            time_slicer: RwLock::new(None),
         }
     }
+
+    pub(crate) fn load(&self) -> HostLoad {
+        HostLoad {
+            busy: self.busy.load(Ordering::SeqCst),
+            queue_depth: self.queued.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Marks a module as busy for as long as the guard is alive. Guards nest: dropping an inner
+/// guard leaves the module busy while an outer one is alive.
+pub(crate) struct BusyGuard<'a> {
+    flag: &'a AtomicBool,
+    was_busy: bool,
+}
+
+impl<'a> BusyGuard<'a> {
+    pub(crate) fn new(flag: &'a AtomicBool) -> BusyGuard<'a> {
+        let was_busy = flag.swap(true, Ordering::SeqCst);
+        BusyGuard { flag, was_busy }
+    }
+}
+
+impl Drop for BusyGuard<'_> {
+    fn drop(&mut self) {
+        self.flag.store(self.was_busy, Ordering::SeqCst);
+    }
 }
 
 impl ModuleState {
@@ -228,9 +569,85 @@ impl ModuleState {
         *self.guest_error.write().unwrap() = Some(error);
     }
 
-    /// Sets the value indicating the response data from a guest call
+    /// Lists the namespaces this host answers, as returned to guests by the
+    /// [introspect](introspect/index.html) host call
+    pub fn capabilities_report(&self) -> introspect::Capabilities {
+        let mut namespaces = vec![
+            introspect::NamespaceInfo::new(
+                introspect::INTROSPECT_NAMESPACE,
+                &[introspect::LIST_OPERATION],
+            ),
+            introspect::NamespaceInfo::new(events::EVENTS_NAMESPACE, &[events::POLL_OPERATION]),
+            introspect::NamespaceInfo::new(trace::TRACE_NAMESPACE, &[trace::CONTEXT_OPERATION]),
+            introspect::NamespaceInfo::new(
+                limits::LIMITS_NAMESPACE,
+                &[limits::MAX_RESPONSE_SIZE_OPERATION],
+            ),
+        ];
+        let mut providers: Vec<_> = self
+            .capabilities
+            .values()
+            .map(|p| introspect::NamespaceInfo {
+                namespace: p.namespace().to_string(),
+                operations: p.operations(),
+            })
+            .collect();
+        providers.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        namespaces.extend(providers);
+        namespaces.extend(self.advertised.iter().cloned());
+        introspect::Capabilities { namespaces }
+    }
+
+    /// Sets the guest error from the raw bytes the guest wrote with `__guest_error`. Standard
+    /// waPC guests write a plain UTF-8 message, which is used as is. Guests built with SDKs that
+    /// write some other encoding never cause the call to trap here: bytes that are not valid
+    /// UTF-8 are decoded lossily, so the caller still sees a readable error.
+    pub fn set_guest_error_bytes(&self, error: &[u8]) {
+        // Some C-based guests include the terminating NUL in the length they report
+        let error = error.strip_suffix(&[0]).unwrap_or(error);
+        let message = match std::str::from_utf8(error) {
+            Ok(message) => message.to_string(),
+            Err(_) => {
+                debug!("Guest error is not valid UTF-8; decoding it lossily");
+                String::from_utf8_lossy(error).into_owned()
+            }
+        };
+        self.set_guest_error(message);
+    }
+
+    /// Sets the value indicating the response data from a guest call. A response larger than
+    /// the host's [maximum](#method.max_response_size) is discarded and fails the call.
     pub fn set_guest_response(&self, response: Vec<u8>) {
-        *self.guest_response.write().unwrap() = Some(response);
+        if self.accept_guest_response(response.len()) {
+            *self.guest_response.write().unwrap() = Some(response);
+        }
+    }
+
+    /// The largest guest response the host accepts, in bytes, if it limits responses. See the
+    /// [limits](limits/index.html) module.
+    pub fn max_response_size(&self) -> Option<usize> {
+        self.max_response_size
+    }
+
+    /// Called by the engine provider with the length the guest passed to `__guest_response`,
+    /// before copying the response out of guest memory. Returns false if the response exceeds
+    /// the host's maximum, in which case the engine provider should not copy it: the call
+    /// fails with a [ResponseTooLarge](errors/enum.ErrorKind.html#variant.ResponseTooLarge)
+    /// error.
+    pub fn accept_guest_response(&self, len: usize) -> bool {
+        match self.max_response_size {
+            Some(limit) if len > limit => {
+                *self.oversized_response.write().unwrap() = Some(len);
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Sets a value indicating that the guest has deferred its response to the current call
+    /// until it is resumed with the given token
+    pub fn set_guest_deferred(&self, token: String) {
+        *self.guest_deferred.write().unwrap() = Some(token);
     }
 
     /// Queries the value of the current guest response
@@ -251,32 +668,214 @@ impl ModuleState {
         operation: &str,
         payload: &[u8],
     ) -> std::result::Result<i32, Box<dyn Error>> {
+        if self.engine_settings.threads
+            && *self.call_thread.lock().unwrap() != Some(std::thread::current().id())
+        {
+            return Err("Host calls are only accepted from the thread running the guest call".into())
+        }
         let id = {
             *self.host_response.write().unwrap() = None;
             *self.host_error.write().unwrap() = None;
             self.id
         };
-        let result = {
-            match self.host_callback {
-                Some(ref f) => f(id, binding, namespace, operation, &payload),
-                None => Err("Missing host callback function!".into()),
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "wapc_host_call",
+            module_id = id,
+            binding,
+            namespace,
+            operation,
+            payload_size = payload.len()
+        )
+        .entered();
+        self.usage.host_call(payload.len());
+        *self.last_host_call.lock().unwrap() = Some(debug::HostCallInfo {
+            binding: binding.to_string(),
+            namespace: namespace.to_string(),
+            operation: operation.to_string(),
+            in_flight: true,
+        });
+        let trace = self.trace.read().unwrap();
+        let ctx = HostCallContext {
+            module_id: id,
+            binding,
+            namespace,
+            extensions: &self.extensions,
+            trace: trace.as_ref(),
+        };
+        let call = host_call::HostCall::new(self, ctx, operation, payload);
+        let result = host_call::handle(self, &call);
+        if let Some(ref mut last) = *self.last_host_call.lock().unwrap() {
+            last.in_flight = false;
+        }
+        host_call::observe(self, &call, &result);
+        Ok(host_call::respond(self, result))
+    }
+
+    /// Invoked when the guest module calls `__host_call_status`. Performs the host call exactly
+    /// as [do_host_call](#method.do_host_call), but returns 1 on success or the failure status
+    /// code chosen by the host callback. See the [status](status/index.html) module.
+    pub fn do_host_call_status(
+        &self,
+        binding: &str,
+        namespace: &str,
+        operation: &str,
+        payload: &[u8],
+    ) -> std::result::Result<i32, Box<dyn Error>> {
+        self.do_host_call(binding, namespace, operation, payload)?;
+        Ok(self.host_status.load(Ordering::SeqCst))
+    }
+
+    /// Returns the typed extension data attached to this module by the embedder
+    pub fn extensions(&self) -> &extensions::Extensions {
+        &self.extensions
+    }
+
+    /// Returns the settings the engine provider must apply when compiling and executing the guest
+    pub fn engine_settings(&self) -> &EngineSettings {
+        &self.engine_settings
+    }
+
+    /// Returns the additional host functions the engine provider must link into the guest's
+    /// imports before instantiating it. See the [host_function](host_function/index.html) module.
+    pub fn host_functions(&self) -> &[host_function::HostFunction] {
+        &self.host_functions
+    }
+
+    /// Returns the library modules the engine provider must instantiate, in order, before the
+    /// guest. See the [linking](linking/index.html) module.
+    pub fn linked_modules(&self) -> &[linking::LinkedModule] {
+        &self.linked_modules
+    }
+
+    /// Invoked when the guest module emits an event. The event is delivered to every subscriber
+    /// for its topic, in the order they subscribed.
+    pub fn do_emit_event(&self, topic: &str, payload: &[u8]) {
+        for (filter, callback) in self.subscribers.read().unwrap().iter() {
+            if filter.as_deref().is_none_or(|f| f == topic) {
+                callback(self.id, topic, payload);
             }
+        }
+    }
+
+    /// Invoked when the guest module opens a stream to the host. Returns the new stream's ID, or
+    /// 0 if the stream could not be opened, in which case the host error is set
+    pub fn do_stream_open(&self, name: &str) -> i32 {
+        *self.host_error.write().unwrap() = None;
+        let writer = match self.stream_sink {
+            Some(ref sink) => sink.open(self.id, name),
+            None => Err("No stream sink is registered with the host".into()),
         };
-        Ok(match result {
-            Ok(v) => {
-                *self.host_response.write().unwrap() = Some(v);
-                1
+        match writer {
+            Ok(w) => {
+                let id = self.next_stream_id.fetch_add(1, Ordering::SeqCst);
+                self.streams.lock().unwrap().insert(id, w);
+                id
             }
             Err(e) => {
                 *self.host_error.write().unwrap() = Some(format!("{}", e));
                 0
             }
-        })
+        }
+    }
+
+    /// Invoked when the guest module writes a chunk to an open stream. Returns 1 on success, or
+    /// 0 on failure, in which case the host error is set
+    pub fn do_stream_write(&self, id: i32, chunk: &[u8]) -> i32 {
+        *self.host_error.write().unwrap() = None;
+        let result = match self.streams.lock().unwrap().get_mut(&id) {
+            Some(w) => w.write(chunk),
+            None => Err(format!("No open stream with ID {}", id).into()),
+        };
+        self.stream_result(result)
+    }
+
+    /// Invoked when the guest module closes an open stream. Returns 1 on success, or 0 on
+    /// failure, in which case the host error is set
+    pub fn do_stream_close(&self, id: i32) -> i32 {
+        *self.host_error.write().unwrap() = None;
+        let writer = self.streams.lock().unwrap().remove(&id);
+        let result = match writer {
+            Some(w) => w.close(),
+            None => Err(format!("No open stream with ID {}", id).into()),
+        };
+        self.stream_result(result)
+    }
+
+    fn stream_result(
+        &self,
+        result: std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>,
+    ) -> i32 {
+        match result {
+            Ok(_) => 1,
+            Err(e) => {
+                *self.host_error.write().unwrap() = Some(format!("{}", e));
+                0
+            }
+        }
+    }
+
+    /// Drops the writers of any streams the guest left open
+    fn abandon_streams(&self) {
+        let mut streams = self.streams.lock().unwrap();
+        if !streams.is_empty() {
+            warn!(
+                "Guest module {} left {} stream(s) open at the end of a call",
+                self.id,
+                streams.len()
+            );
+            streams.clear();
+        }
+    }
+
+    /// Invoked by the engine provider each time a running guest call's time slice ends, as set
+    /// by [EngineSettings::time_slice_epochs](struct.EngineSettings.html#structfield.time_slice_epochs).
+    /// Blocks while the host's scheduler, if it has one, lets other tenants' calls run, and
+    /// returns when the call may continue.
+    pub fn yield_time_slice(&self) {
+        let slicer = self.time_slicer.read().unwrap().clone();
+        if let Some(slicer) = slicer {
+            slicer();
+        }
+    }
+
+    pub(crate) fn set_time_slicer(&self, slicer: Arc<TimeSlicer>) {
+        *self.time_slicer.write().unwrap() = Some(slicer);
+    }
+
+    /// Indicates whether the engine provider should export the optional time functions
+    /// (`__host_time_ms` and `__host_monotonic_ms`) to the guest module
+    pub fn time_imports_enabled(&self) -> bool {
+        self.time_imports
+    }
+
+    /// Invoked when the guest module wants the current wall-clock time, in milliseconds
+    /// since the Unix epoch
+    pub fn do_host_time_ms(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0)
+    }
+
+    /// Invoked when the guest module wants a monotonic time source, in milliseconds since an
+    /// arbitrary fixed point in the life of the host process. Suitable for measuring timeouts.
+    pub fn do_host_monotonic_ms(&self) -> i64 {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed().as_millis() as i64
     }
 
-    /// Invoked when the guest module wants to write a message to the host's `stdout`
+    /// Invoked when the guest module wants to write a message to the host's `stdout`. The
+    /// message is delivered to every attached logger, or to the `log` crate if there are none
     pub fn do_console_log(&self, msg: &str) {
-        info!("Guest module {}: {}", self.id, msg);
+        let loggers = self.loggers.read().unwrap();
+        if loggers.is_empty() {
+            info!("Guest module {}: {}", self.id, msg);
+        } else {
+            for logger in loggers.iter() {
+                logger(self.id, msg);
+            }
+        }
     }
 }
 
@@ -285,7 +884,10 @@ impl ModuleState {
 /// in a way that conforms to the waPC conversation protocol.
 pub trait WebAssemblyEngineProvider {
     /// Tell the engine provider that it can do whatever processing it needs to do for
-    /// initialization and give it access to the module state
+    /// initialization and give it access to the module state. If the module lacks an export the
+    /// protocol requires, the engine should return a
+    /// [MissingRequiredExport](errors/enum.ErrorKind.html#variant.MissingRequiredExport) error
+    /// listing the exports it found, which the host passes to the caller unchanged.
     fn init(
         &mut self,
         host: Arc<ModuleState>,
@@ -301,6 +903,132 @@ pub trait WebAssemblyEngineProvider {
     /// Called by the host to replace the WebAssembly module bytes of the previously initialized module. Engine must return an
     /// error if it does not support bytes replacement.
     fn replace(&mut self, bytes: &[u8]) -> std::result::Result<(), Box<dyn std::error::Error>>;
+    /// Called by the host to discard the guest instance and instantiate the current module afresh,
+    /// running its start functions again, so that state leaked by the guest is reclaimed. Engines
+    /// that cannot re-instantiate return an error, which is the default behavior.
+    fn reset(&mut self) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err("This engine provider does not support resetting the guest instance".into())
+    }
+    /// Called by the host to drop the guest instance and its store, releasing linear memory,
+    /// while keeping the compiled module. The host calls `init` again before the next guest
+    /// call, which must then only instantiate. Engines that cannot unload return an error,
+    /// which is the default behavior.
+    fn unload(&mut self) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err("This engine provider does not support unloading the guest instance".into())
+    }
+    /// Called by the host to replace the module of a guest that has not been instantiated yet,
+    /// so that the next `init` instantiates `module` without ever instantiating the old one.
+    /// Engines that cannot return an error, which is the default behavior; the host then
+    /// initializes the old module and [replaces](#tymethod.replace) it.
+    fn set_module(
+        &mut self,
+        _module: &[u8],
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err("This engine provider does not support swapping an uninstantiated module".into())
+    }
+    /// Called by the host to give an embedder read access to the guest module's linear memory
+    /// between calls. Engines that cannot expose linear memory return an error, which is the
+    /// default behavior.
+    fn with_memory(
+        &self,
+        _f: &mut dyn FnMut(&[u8]),
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err("This engine provider does not expose guest memory".into())
+    }
+    /// Called by the host to give an embedder write access to the guest module's linear memory
+    /// between calls. Engines that cannot expose linear memory return an error, which is the
+    /// default behavior.
+    fn with_memory_mut(
+        &mut self,
+        _f: &mut dyn FnMut(&mut [u8]),
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err("This engine provider does not expose guest memory".into())
+    }
+    /// Called by the host to limit the fuel available to subsequent guest calls, or to lift the
+    /// limit when `None`. Guests that run out of fuel must trap. Engines without fuel metering
+    /// return an error, which is the default behavior.
+    fn set_fuel(
+        &mut self,
+        _fuel: Option<u64>,
+    ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Err("This engine provider does not support fuel metering".into())
+    }
+    /// Called by the host to query the fuel remaining under the limit set with `set_fuel`
+    fn remaining_fuel(&self) -> Option<u64> {
+        None
+    }
+    /// Called by the host to read the bytes of the module currently loaded, e.g. to extract its
+    /// [metadata](metadata/index.html). Engines that do not retain the bytes return `None`,
+    /// which is the default behavior.
+    fn module_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+    /// Called by the host to query the number of elements in each of the guest's tables, for
+    /// resource accounting. Engines that cannot inspect tables return `None`, which is the
+    /// default behavior.
+    fn table_sizes(&self) -> Option<Vec<u32>> {
+        None
+    }
+    /// Called by the host to compile the guest's `__guest_call` export ahead of the first call.
+    /// Engines that compile lazily or tier up should do that work here; the default does
+    /// nothing, which suits engines that compile the whole module during `init`.
+    fn warmup(&mut self) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+    /// Called by the host to obtain the epoch counter of the engine behind this provider, so an
+    /// [EpochTicker](epoch/struct.EpochTicker.html) can advance it. Providers that share an
+    /// engine must return the same `Arc`. Engines without epoch interruption return `None`,
+    /// which is the default behavior.
+    fn epoch_counter(&self) -> Option<Arc<dyn epoch::EpochCounter>> {
+        None
+    }
+    /// Called by the host to invoke an exported guest function directly, outside the waPC
+    /// protocol. Engines that cannot return an error, which is the default behavior.
+    fn call_export(
+        &mut self,
+        _name: &str,
+        _params: &[host_function::Val],
+    ) -> std::result::Result<Vec<host_function::Val>, Box<dyn std::error::Error>> {
+        Err("This engine provider does not support calling exports directly".into())
+    }
+    /// Called by the host to read the value of an exported guest global. Engines that cannot
+    /// return an error, which is the default behavior.
+    fn global(
+        &self,
+        _name: &str,
+    ) -> std::result::Result<host_function::Val, Box<dyn std::error::Error>> {
+        Err("This engine provider does not expose guest globals".into())
+    }
+    /// Called by the host after a guest call traps to collect the coredump captured for it when
+    /// [EngineSettings::coredump_on_trap](struct.EngineSettings.html#structfield.coredump_on_trap)
+    /// is set. Engines that cannot capture coredumps return `None`, which is the default behavior.
+    fn take_coredump(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+    /// Called by the host after a guest call traps to collect the guest's stack at the time of
+    /// the trap, innermost frame first, with source locations when
+    /// [EngineSettings::debug_info](struct.EngineSettings.html#structfield.debug_info) is set.
+    /// Engines that cannot capture backtraces return `None`, which is the default behavior.
+    fn take_backtrace(&mut self) -> Option<Vec<errors::GuestFrame>> {
+        None
+    }
+    /// Called by the host to identify the engine behind this provider. Providers that do not
+    /// report it return `None`, which is the default behavior.
+    fn engine_info(&self) -> Option<EngineInfo> {
+        None
+    }
+    /// Called by the host to obtain a handle that interrupts a running guest call from another
+    /// thread, e.g. via epoch interruption. Engines that cannot interrupt a guest return `None`,
+    /// which is the default behavior.
+    fn interrupt_handle(&self) -> Option<Arc<dyn InterruptHandle>> {
+        None
+    }
+}
+
+/// A thread-safe handle supplied by an engine provider for interrupting a running guest call.
+/// The interrupted call must fail (e.g. by trapping) rather than run to completion.
+pub trait InterruptHandle: Send + Sync {
+    fn interrupt(&self);
 }
 
 /// The module host (waPC) must provide an implementation of this trait to the engine provider
@@ -330,33 +1058,165 @@ pub trait ModuleHost {
     fn do_console_log(&self, msg: &str);
 }
 
-type HostCallback = dyn Fn(
-    u64,
-    &str,
-    &str,
-    &str,
-    &[u8],
-) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
-+ Sync
-+ Send
-+ 'static;
+/// Describes the origin of a host call made by a guest module
+#[derive(Clone, Copy)]
+pub struct HostCallContext<'a> {
+    /// The unique identifier of the module making the call
+    pub module_id: u64,
+    /// The binding name supplied by the guest
+    pub binding: &'a str,
+    /// The namespace supplied by the guest
+    pub namespace: &'a str,
+    /// The typed extension data attached to the module by the embedder
+    pub extensions: &'a extensions::Extensions,
+    /// The trace context of the guest call that led to this host call, if it was made with
+    /// [WapcHost::call_with_context](struct.WapcHost.html#method.call_with_context)
+    pub trace: Option<&'a trace::TraceContext>,
+}
+
+impl<'a> std::fmt::Debug for HostCallContext<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Extension values are arbitrary embedder types, so they are left out
+        f.debug_struct("HostCallContext")
+            .field("module_id", &self.module_id)
+            .field("binding", &self.binding)
+            .field("namespace", &self.namespace)
+            .field("trace", &self.trace)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handler for host calls made by guest modules. This is an alternative to supplying a closure
+/// when creating a [WapcHost](struct.WapcHost.html), and makes it easy to share a single stateful
+/// handler (using interior mutability) across many hosts or to substitute a mock in tests.
+///
+/// Any closure with the host callback signature implements this trait.
+pub trait HostHandler: Send + Sync {
+    /// Handles a single host call, returning either the response payload or an error that
+    /// will be made available to the guest
+    fn handle(
+        &self,
+        ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+impl<F> HostHandler for F
+where
+    F: Fn(
+            u64,
+            &str,
+            &str,
+            &str,
+            &[u8],
+        ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+        + Sync
+        + Send
+        + 'static,
+{
+    fn handle(
+        &self,
+        ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+        self(ctx.module_id, ctx.binding, ctx.namespace, operation, payload)
+    }
+}
+
+pub(crate) type LogCallback = dyn Fn(u64, &str) + Sync + Send + 'static;
+
+type EventCallback = dyn Fn(u64, &str, &[u8]) + Sync + Send + 'static;
+
+type DropHook = dyn FnOnce(u64);
+
+pub(crate) type TimeSlicer = dyn Fn() + Sync + Send + 'static;
+
+/// What to do when a host callback or capability provider panics while handling a host call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PanicPolicy {
+    /// Catch the panic and fail the host call with an error describing it, which the guest
+    /// receives like any other host error
+    #[default]
+    Convert,
+    /// Log the panic and abort the process, for embedders that consider a panicking callback
+    /// to have left shared state unusable
+    Abort,
+}
+
+/// How a host clears a guest's data after every call, for hosts whose successive calls come
+/// from tenants that must not see each other's data. Whichever is chosen, the host's copies of
+/// the request, response and errors are dropped too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryScrub {
+    /// Re-instantiate the guest, so the next call starts from fresh linear memory
+    Reinstantiate,
+    /// Overwrite the guest's linear memory with zeros and then re-instantiate it, for engines
+    /// that may hand the old memory's pages to the new instance without clearing them
+    ZeroAndReinstantiate,
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// A cheap snapshot of how loaded a host is, suitable for polling by routers and load
+/// balancers when making placement decisions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostLoad {
+    /// Indicates whether a guest call is currently executing
+    pub busy: bool,
+    /// The number of calls waiting to be executed, for wrappers that queue calls. Always 0 for
+    /// a bare `WapcHost`
+    pub queue_depth: usize,
+}
 
 #[derive(Debug, Clone)]
 /// Represents a waPC invocation, which is a combination of an operation string and the
 /// corresponding binary payload
+///
+/// Both parts are reference counted, so the invocation is copied exactly once per call (when
+/// it is created) no matter how many times it is cloned or retrieved with `get_guest_request`.
+/// Measured with `cargo bench --bench invocation`, whose engine retrieves the invocation once
+/// per call as a `__guest_request` import does, this saves four allocations per call over
+/// storing a `String` and a `Vec<u8>`, which were copied again into the host's state and on
+/// every retrieval.
 pub struct Invocation {
-    pub operation: String,
-    pub msg: Vec<u8>,
+    pub operation: Arc<str>,
+    pub msg: Arc<[u8]>,
 }
 
 impl Invocation {
     /// Creates a new invocation
-    fn new(op: &str, msg: Vec<u8>) -> Invocation {
+    fn new(op: &str, msg: &[u8]) -> Invocation {
         Invocation {
-            operation: op.to_string(),
-            msg,
+            operation: Arc::from(op),
+            msg: Arc::from(msg),
         }
     }
+
+    /// Splits the operation name into its namespace and operation, following the
+    /// `namespace!Operation` convention
+    pub fn route(&self) -> route::Route<'_> {
+        route::Route::parse(&self.operation)
+    }
+}
+
+/// The operations an embedder performs against a waPC host. `WapcHost` implements this
+/// trait, and code that depends on `dyn WapcCaller` rather than `WapcHost` can be unit
+/// tested with a [MockWapcHost](mock/struct.MockWapcHost.html) instead of real wasm bytes.
+pub trait WapcCaller {
+    /// Invokes the given operation on the guest with an opaque payload
+    fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>>;
+    /// Returns the unique identifier of the guest module
+    fn id(&self) -> u64;
+    /// Replaces the guest module with new WebAssembly module bytes
+    fn replace_module(&self, module: &[u8]) -> Result<()>;
 }
 
 /// A WebAssembly host runtime for waPC-compliant modules
@@ -368,6 +1228,72 @@ impl Invocation {
 pub struct WapcHost {
     engine: RefCell<Box<dyn WebAssemblyEngineProvider>>,
     state: Arc<ModuleState>,
+    options: HostOptions,
+    deferred: RefCell<deferred::DeferredRegistry>,
+    batch_supported: Cell<Option<bool>>,
+    consecutive_errors: Cell<u32>,
+    calls_since_reset: Cell<u64>,
+    fuel_consumed: Cell<u64>,
+    metadata: RefCell<Option<metadata::ModuleMetadata>>,
+    interface: RefCell<Option<Option<metadata::InterfaceDescriptor>>>,
+    drop_hooks: RefCell<Vec<Box<DropHook>>>,
+    initialized: Cell<bool>,
+    /// Whether the guest instance has not run since it was last reset, so resetting it again
+    /// would change nothing
+    pristine: Cell<bool>,
+    /// Whether the most recent guest call ended in a trap rather than returning
+    trapped: Cell<bool>,
+}
+
+/// Host-level configuration that, unlike `ModuleState`, is never seen by the engine provider
+#[derive(Default)]
+pub(crate) struct HostOptions {
+    pub(crate) middleware: Vec<Arc<dyn middleware::CallMiddleware>>,
+    pub(crate) deferred_timeout: Option<std::time::Duration>,
+    pub(crate) stats: Option<stats::CallStats>,
+    pub(crate) recycle_after_errors: Option<u32>,
+    pub(crate) recycle_after_calls: Option<u64>,
+    pub(crate) init_timeout: Option<std::time::Duration>,
+    pub(crate) cache: Option<Arc<cache::ResponseCache>>,
+    pub(crate) capability_configs: Vec<(String, capability::CapabilityConfig)>,
+    pub(crate) warmup: Option<Vec<String>>,
+    pub(crate) lazy: bool,
+    pub(crate) scrub: Option<MemoryScrub>,
+    pub(crate) coredump_dir: Option<std::path::PathBuf>,
+}
+
+/// Interrupts guest initialization (e.g. a `_start` function that never returns) once its
+/// deadline passes
+struct InitWatchdog {
+    done: std::sync::mpsc::Sender<()>,
+    fired: Arc<AtomicBool>,
+}
+
+impl InitWatchdog {
+    fn start(
+        timeout: std::time::Duration,
+        interrupt: Option<Arc<dyn InterruptHandle>>,
+    ) -> InitWatchdog {
+        let (done, wait) = std::sync::mpsc::channel::<()>();
+        let fired = Arc::new(AtomicBool::new(false));
+        if let Some(interrupt) = interrupt {
+            let fired = fired.clone();
+            std::thread::spawn(move || {
+                let timed_out = wait.recv_timeout(timeout);
+                if let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = timed_out {
+                    fired.store(true, Ordering::SeqCst);
+                    interrupt.interrupt();
+                }
+            });
+        }
+        InitWatchdog { done, fired }
+    }
+
+    /// Stops the watchdog, returning whether it interrupted initialization
+    fn finish(self) -> bool {
+        let _ = self.done.send(());
+        self.fired.load(Ordering::SeqCst)
+    }
 }
 
 impl WapcHost {
@@ -387,29 +1313,191 @@ impl WapcHost {
         + Sync
         + Send,
     ) -> Result<Self> {
-        let id = GLOBAL_MODULE_COUNT.fetch_add(1, Ordering::SeqCst);
-        //let state = Rc::new(RefCell::new(ModuleState::new(id, Box::new(host_callback))));
-        let state = Arc::new(ModuleState::new(Box::new(host_callback), id));
+        WapcHostBuilder::new()
+            .host_callback(host_callback)
+            .build(engine)
+    }
+
+    /// Creates a new instance of a waPC-compliant host runtime from the module file at `path`,
+    /// creating the engine provider from its bytes with `engine`. Modules larger than
+    /// [DEFAULT_MAX_MODULE_SIZE](binary/constant.DEFAULT_MAX_MODULE_SIZE.html) are refused; use
+    /// [WapcHostBuilder::build_from_file](struct.WapcHostBuilder.html#method.build_from_file) to
+    /// choose another limit.
+    pub fn from_file(
+        path: impl AsRef<std::path::Path>,
+        engine: impl FnOnce(&[u8]) -> Box<dyn WebAssemblyEngineProvider>,
+        host_callback: impl Fn(
+            u64,
+            &str,
+            &str,
+            &str,
+            &[u8],
+        )
+            -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+        + 'static
+        + Sync
+        + Send,
+    ) -> Result<Self> {
+        WapcHostBuilder::new()
+            .host_callback(host_callback)
+            .build_from_file(path, engine)
+    }
+
+    /// Creates a new instance of a waPC-compliant host runtime that dispatches host calls
+    /// to the given [HostHandler](trait.HostHandler.html). The same handler can be shared
+    /// by any number of hosts.
+    pub fn new_with_handler(
+        engine: Box<dyn WebAssemblyEngineProvider>,
+        handler: Arc<dyn HostHandler>,
+    ) -> Result<Self> {
+        WapcHostBuilder::new().handler(handler).build(engine)
+    }
+
+    /// Creates a new instance of a waPC-compliant host runtime with a logger that receives
+    /// all console log messages emitted by the guest module. Additional loggers can be attached
+    /// with [add_logger](struct.WapcHost.html#method.add_logger).
+    pub fn new_with_logger(
+        engine: Box<dyn WebAssemblyEngineProvider>,
+        host_callback: impl Fn(
+            u64,
+            &str,
+            &str,
+            &str,
+            &[u8],
+        )
+            -> std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>
+        + 'static
+        + Sync
+        + Send,
+        logger: impl Fn(u64, &str) + 'static + Sync + Send,
+    ) -> Result<Self> {
+        WapcHostBuilder::new()
+            .host_callback(host_callback)
+            .logger(logger)
+            .build(engine)
+    }
 
+    pub(crate) fn create(
+        engine: Box<dyn WebAssemblyEngineProvider>,
+        state: ModuleState,
+        options: HostOptions,
+    ) -> Result<Self> {
+        let deferred = deferred::DeferredRegistry::new(
+            options
+                .deferred_timeout
+                .unwrap_or(deferred::DEFAULT_DEFERRED_TIMEOUT),
+        );
         let mh = WapcHost {
             engine: RefCell::new(engine),
-            state: state.clone(),
+            state: Arc::new(state),
+            options,
+            deferred: RefCell::new(deferred),
+            batch_supported: Cell::new(None),
+            consecutive_errors: Cell::new(0),
+            calls_since_reset: Cell::new(0),
+            fuel_consumed: Cell::new(0),
+            metadata: RefCell::new(None),
+            interface: RefCell::new(None),
+            drop_hooks: RefCell::new(Vec::new()),
+            pristine: Cell::new(false),
+            trapped: Cell::new(false),
+            initialized: Cell::new(false),
         };
 
-        mh.initialize(state)?;
+        for (namespace, config) in mh.options.capability_configs.iter() {
+            mh.state.capabilities[namespace]
+                .configure(mh.state.id, config)
+                .map_err(|e| {
+                    errors::new(errors::ErrorKind::CapabilityConfiguration(format!(
+                        "{}: {}",
+                        namespace, e
+                    )))
+                    .with_module(mh.state.id)
+                })?;
+        }
+        if !mh.options.lazy {
+            mh.ensure_initialized()?;
+        }
+        for p in mh.state.plugins.iter() {
+            p.on_host_created(mh.state.id);
+        }
 
         Ok(mh)
     }
 
+    /// Instantiates the guest and runs its start functions, followed by any
+    /// [warmup](struct.WapcHostBuilder.html#method.warmup), if that has not happened yet. Hosts
+    /// built in [lazy](struct.WapcHostBuilder.html#method.lazy) mode do this on their first
+    /// call; calling it beforehand moves the cost, and any initialization error, to a time of
+    /// the embedder's choosing. Initialization that fails is retried by the next call.
+    pub fn ensure_initialized(&self) -> Result<()> {
+        if self.initialized.get() {
+            return Ok(());
+        }
+        self.initialize(self.state.clone())?;
+        self.initialized.set(true);
+        if let Some(ref ops) = self.options.warmup {
+            self.warmup(&ops.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
+
+    /// Whether the guest is instantiated. Hosts built in
+    /// [lazy](struct.WapcHostBuilder.html#method.lazy) mode start out uninitialized, and
+    /// [unload](#method.unload) returns a host to that state.
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.get()
+    }
+
+    /// Drops the guest instance and its store, releasing the guest's linear memory, while the
+    /// engine keeps the compiled module. The next call instantiates the guest afresh and runs
+    /// its start functions again, so guest state does not survive unloading. Does nothing if
+    /// the guest is not instantiated, and returns an error if the engine provider does not
+    /// support unloading.
+    pub fn unload(&self) -> Result<()> {
+        if !self.initialized.get() {
+            return Ok(());
+        }
+        self.engine.borrow_mut().unload().map_err(|e| {
+            errors::new(errors::ErrorKind::WasmMisc(format!(
+                "Failed to unload guest module: {}",
+                e
+            )))
+            .with_module(self.state.id)
+        })?;
+        self.state.abandon_streams();
+        self.initialized.set(false);
+        debug!("Unloaded guest module {}", self.state.id);
+        Ok(())
+    }
+
     fn initialize(&self, state: Arc<ModuleState>) -> Result<()> {
-        match self.engine.borrow_mut().init(state) {
+        let id = state.id;
+        let timeout = self.options.init_timeout;
+        let watchdog =
+            timeout.map(|t| InitWatchdog::start(t, self.engine.borrow().interrupt_handle()));
+        let started = Instant::now();
+        let result = self.engine.borrow_mut().init(state);
+        if let Some(timeout) = timeout {
+            // Engines that cannot be interrupted are still held to the deadline after the fact
+            let interrupted = watchdog.is_some_and(InitWatchdog::finish);
+            if interrupted || started.elapsed() > timeout {
+                return Err(
+                    errors::new(errors::ErrorKind::GuestInitTimeout(timeout)).with_module(id)
+                );
+            }
+        }
+        match result {
             Ok(_) => Ok(()),
-            Err(e) => Err(crate::errors::new(
-                crate::errors::ErrorKind::GuestCallFailure(format!(
+            // Engine providers may report structured errors such as MissingRequiredExport
+            Err(e) => Err(match e.downcast::<errors::Error>() {
+                Ok(e) => *e,
+                Err(e) => crate::errors::new(crate::errors::ErrorKind::GuestCallFailure(format!(
                     "Failed to initialize guest module: {}",
                     e
-                )),
-            )),
+                ))),
+            }
+            .with_module(id)),
         }
     }
 
@@ -420,38 +1508,746 @@ impl WapcHost {
         self.state.id
     }
 
-    /// Invokes the `__guest_call` function within the guest module as per the waPC specification.
-    /// Provide an operation name and an opaque payload of bytes and the function returns a `Result`
-    /// containing either an error or an opaque reply of bytes.    
-    ///
-    /// It is worth noting that the _first_ time `call` is invoked, the WebAssembly module
-    /// might incur a "cold start" penalty, depending on which underlying engine you're using. This
-    /// might be due to lazy initialization or JIT-compilation.
-    pub fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
-        let inv = Invocation::new(op, payload.to_vec());
+    /// The string ID this module was built with, if it was given one with
+    /// [WapcHostBuilder::string_id](struct.WapcHostBuilder.html#method.string_id)
+    pub fn string_id(&self) -> Option<&str> {
+        self.state.string_id.as_deref()
+    }
 
-        {
-            *self.state.guest_response.write().unwrap() = None;
-            *self.state.guest_request.write().unwrap() = Some((inv).clone());
-            *self.state.guest_error.write().unwrap() = None;
-            *self.state.host_response.write().unwrap() = None;
-            *self.state.host_error.write().unwrap() = None;
+    /// Returns the per-operation call statistics, if enabled with
+    /// [WapcHostBuilder::collect_stats](struct.WapcHostBuilder.html#method.collect_stats)
+    pub fn stats(&self) -> Option<&stats::CallStats> {
+        self.options.stats.as_ref()
+    }
+
+    /// Returns the response cache, if enabled with
+    /// [WapcHostBuilder::cache](struct.WapcHostBuilder.html#method.cache), for invalidation
+    pub fn cache(&self) -> Option<&cache::ResponseCache> {
+        self.options.cache.as_deref()
+    }
+
+    /// Returns a handle that can close this host from another thread. See the
+    /// [shutdown](shutdown/index.html) module.
+    pub fn shutdown_handle(&self) -> shutdown::ShutdownHandle {
+        shutdown::ShutdownHandle {
+            state: self.state.clone(),
+            interrupt: self.engine.borrow().interrupt_handle(),
+        }
+    }
+
+    /// Closes the host, giving an in-flight call up to `timeout` to finish before interrupting
+    /// it, then drops the engine provider and with it the guest instance. To close a host owned
+    /// by another thread, use a [shutdown_handle](#method.shutdown_handle) instead.
+    pub fn shutdown(self, timeout: std::time::Duration) {
+        self.shutdown_handle().shutdown(timeout);
+    }
+
+    /// Returns whether this host is currently executing a call and how many calls are
+    /// waiting behind it. This is an inexpensive, lock-free read.
+    pub fn load(&self) -> HostLoad {
+        self.state.load()
+    }
+
+    /// Exports the guest call and host call timings recorded during the given window (ending now)
+    /// as Chrome `trace_event` JSON, which can be loaded into Perfetto or `chrome://tracing`.
+    /// Timings are only recorded when enabled with
+    /// [record_timings](struct.WapcHostBuilder.html#method.record_timings); otherwise the
+    /// exported trace contains no events.
+    pub fn export_chrome_trace(&self, window: std::time::Duration) -> String {
+        match self.state.timings {
+            Some(ref recorder) => recorder.to_chrome_trace(self.state.id, window),
+            None => TimingRecorder::new(0).to_chrome_trace(self.state.id, window),
+        }
+    }
+
+    /// Returns the typed extension data attached to this module, which is also available to
+    /// host callbacks, handlers and plugins through the module state and `HostCallContext`
+    pub fn extensions(&self) -> &extensions::Extensions {
+        &self.state.extensions
+    }
+
+    /// Queues an event for the guest, which it will receive the next time it polls with the
+    /// `__wapc_poll_events` host call. See the [events](events/index.html) module.
+    pub fn publish_to_guest(&self, topic: &str, payload: &[u8]) {
+        self.state.guest_events.lock().unwrap().push(events::Event {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+        });
+    }
+
+    /// Subscribes to events emitted by the guest. With a topic, only events on that topic are
+    /// delivered; with `None`, every event is. The callback receives the module ID, topic, and
+    /// payload.
+    pub fn subscribe(
+        &self,
+        topic: Option<&str>,
+        callback: impl Fn(u64, &str, &[u8]) + 'static + Sync + Send,
+    ) {
+        self.state
+            .subscribers
+            .write()
+            .unwrap()
+            .push((topic.map(|t| t.to_string()), Box::new(callback)));
+    }
+
+    /// Attaches a logger that will receive the module ID and text of every console log message
+    /// emitted by the guest. Any number of loggers can be attached and each will receive every
+    /// message in the order in which they were added. When no loggers are attached, messages
+    /// are written via the `log` crate at the `info` level.
+    pub fn add_logger(&self, logger: impl Fn(u64, &str) + 'static + Sync + Send) {
+        self.state.loggers.write().unwrap().push(Box::new(logger));
+    }
+
+    /// Invokes the `__guest_call` function within the guest module as per the waPC specification.
+    /// Provide an operation name and an opaque payload of bytes and the function returns a `Result`
+    /// containing either an error or an opaque reply of bytes.    
+    ///
+    /// It is worth noting that the _first_ time `call` is invoked, the WebAssembly module
+    /// might incur a "cold start" penalty, depending on which underlying engine you're using. This
+    /// might be due to lazy initialization or JIT-compilation.
+    pub fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "wapc_call",
+            module_id = self.state.id,
+            operation = op,
+            payload_size = payload.len()
+        )
+        .entered();
+        let started = Instant::now();
+        let result = if self.options.middleware.is_empty() {
+            self.invoke(op, payload)
+        } else {
+            self.invoke_with_middleware(op, payload)
+        }
+        .map_err(|e| e.with_module(self.state.id));
+        if let Some(ref recorder) = self.state.timings {
+            recorder.record(TimingKind::GuestCall, op, started, result.is_ok());
+        }
+        if let Some(ref stats) = self.options.stats {
+            let bytes_out = result.as_ref().ok().map(Vec::len);
+            stats.record(op, payload.len(), bytes_out, started.elapsed());
+        }
+        if !self.state.plugins.is_empty() {
+            let elapsed = started.elapsed();
+            let error = result.as_ref().err().map(|e| e.to_string());
+            for p in self.state.plugins.iter() {
+                p.on_guest_call(self.state.id, op, error.as_deref(), elapsed);
+            }
+        }
+        if let Some(ref mut recorder) = *self.state.recorder.lock().unwrap() {
+            if let Err(e) = recorder.call(op, payload, recorder::Outcome::of(&result)) {
+                warn!("Failed to record call to guest module {}: {}", self.state.id, e);
+            }
+        }
+        self.recycle_if_due(result.is_ok());
+        result
+    }
+
+    /// Clears the guest's data after a call if the builder asked for it. A result must not be
+    /// handed out if the data behind it could not be cleared, so a failed scrub replaces it.
+    fn scrubbed<T>(&self, result: Result<T>) -> Result<T> {
+        let scrub = match self.options.scrub {
+            Some(scrub) => scrub,
+            None => return result,
+        };
+        *self.state.host_response.write().unwrap() = None;
+        *self.state.guest_response.write().unwrap() = None;
+        *self.state.guest_request.write().unwrap() = None;
+        *self.state.guest_error.write().unwrap() = None;
+        *self.state.host_error.write().unwrap() = None;
+        if self.pristine.get() {
+            return result;
+        }
+        if scrub == MemoryScrub::ZeroAndReinstantiate {
+            self.with_memory_mut(|mem| mem.fill(0))?;
+        }
+        self.reset().and(result)
+    }
+
+    /// Registers a hook that is called with the module ID when this host is dropped, after its
+    /// capability providers and plugins have been notified, so embedder state keyed by the
+    /// module ID can be released. Hooks run in reverse registration order, including when the
+    /// host is dropped during a panic; a hook that panics is logged and does not prevent the
+    /// others from running.
+    pub fn on_drop(&self, hook: impl FnOnce(u64) + 'static) {
+        self.drop_hooks.borrow_mut().push(Box::new(hook));
+    }
+
+    /// Runs `f` with this host and then drops it, tearing down the guest instance and notifying
+    /// capability providers, plugins, and [drop hooks](#method.on_drop) whether `f` returns or
+    /// panics
+    pub fn scope<R>(self, f: impl FnOnce(&WapcHost) -> R) -> R {
+        f(&self)
+    }
+
+    /// Invokes an operation like [call](#method.call), taking the payload as any
+    /// [Buf](https://docs.rs/bytes/1/bytes/trait.Buf.html) and returning the response as
+    /// [Bytes](https://docs.rs/bytes/1/bytes/struct.Bytes.html). A payload held in one
+    /// contiguous chunk, such as `Bytes`, is not copied, and the response is handed over
+    /// without copying either.
+    #[cfg(feature = "bytes")]
+    pub fn call_bytes(&self, op: &str, mut payload: impl bytes::Buf) -> Result<bytes::Bytes> {
+        let response = if payload.chunk().len() == payload.remaining() {
+            self.call(op, payload.chunk())
+        } else {
+            self.call(op, &payload.copy_to_bytes(payload.remaining()))
+        };
+        response.map(bytes::Bytes::from)
+    }
+
+    /// Performs a guest call carrying a W3C trace context, which the host callback sees on every
+    /// host call the guest makes during it and which the guest can read through the
+    /// `wapc:trace` host call. See the [trace](trace/index.html) module.
+    pub fn call_with_context(
+        &self,
+        op: &str,
+        payload: &[u8],
+        context: trace::TraceContext,
+    ) -> Result<Vec<u8>> {
+        let previous = self.state.trace.write().unwrap().replace(context);
+        let result = self.call(op, payload);
+        *self.state.trace.write().unwrap() = previous;
+        result
+    }
+
+    /// Performs a guest call with at most `fuel_limit` units of fuel, returning the call's result
+    /// along with the fuel it consumed, e.g. for per-instruction billing. A guest that exhausts
+    /// its fuel traps and the call fails. Requires an engine provider with fuel metering.
+    pub fn call_with_fuel(
+        &self,
+        op: &str,
+        payload: &[u8],
+        fuel_limit: u64,
+    ) -> (Result<Vec<u8>>, u64) {
+        if let Err(e) = self.engine.borrow_mut().set_fuel(Some(fuel_limit)) {
+            let reason = format!("Failed to set fuel: {}", e);
+            let err = errors::new(errors::ErrorKind::WasmMisc(reason)).with_module(self.state.id);
+            return (Err(err), 0);
+        }
+        let result = self.call(op, payload);
+        let mut engine = self.engine.borrow_mut();
+        let consumed = fuel_limit - engine.remaining_fuel().unwrap_or(0).min(fuel_limit);
+        if let Err(e) = engine.set_fuel(None) {
+            warn!("Failed to lift fuel limit on guest module {}: {}", self.state.id, e);
+        }
+        self.fuel_consumed.set(self.fuel_consumed.get() + consumed);
+        (result, consumed)
+    }
+
+    /// Returns the total fuel consumed by calls made with [call_with_fuel](#method.call_with_fuel)
+    /// over the lifetime of this host
+    pub fn fuel_consumed(&self) -> u64 {
+        self.fuel_consumed.get()
+    }
+
+    /// Returns the metadata the module ships in its custom sections, such as its claims and the
+    /// operations it exports. See the [metadata](metadata/index.html) module. The metadata is
+    /// read once and cached until the module is replaced. Returns an error if the engine
+    /// provider does not expose the module bytes or the metadata is malformed.
+    pub fn metadata(&self) -> Result<metadata::ModuleMetadata> {
+        if let Some(ref metadata) = *self.metadata.borrow() {
+            return Ok(metadata.clone());
+        }
+        let metadata = match self.engine.borrow().module_bytes() {
+            Some(bytes) => metadata::extract(bytes),
+            None => Err(errors::new(errors::ErrorKind::WasmMisc(
+                "This engine provider does not expose the module bytes".to_string(),
+            ))),
+        }
+        .map_err(|e| e.with_module(self.state.id))?;
+        *self.metadata.borrow_mut() = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Returns the interface the guest describes, from its `wapc_interface` custom section or,
+    /// failing that, its answer to the `__wapc_describe` operation. Returns `None` if the guest
+    /// describes neither way. The result is negotiated once and cached until the module is
+    /// replaced.
+    pub fn interface(&self) -> Option<metadata::InterfaceDescriptor> {
+        if let Some(ref interface) = *self.interface.borrow() {
+            return interface.clone();
         }
+        let interface = match self.metadata() {
+            Ok(metadata::ModuleMetadata {
+                interface: Some(interface),
+                ..
+            }) => Some(interface),
+            _ => match self.invoke_outcome(metadata::DESCRIBE_OPERATION, &[]) {
+                Ok(deferred::CallOutcome::Complete(response)) => {
+                    serde_json::from_slice(&response).ok()
+                }
+                _ => None,
+            },
+        };
+        *self.interface.borrow_mut() = Some(interface.clone());
+        interface
+    }
 
-        let callresult = match self
+    /// Calls the guest's exported function `name` directly with the given parameters and
+    /// returns its results, for diagnostics tooling.
+    ///
+    /// This is outside the waPC protocol: no invocation is set up, so the function cannot
+    /// make host calls that expect one, and nothing about the call is recorded in statistics,
+    /// middleware or plugins. The export's signature is defined by the guest's toolchain.
+    /// Returns an error if the engine provider does not support calling exports.
+    pub fn call_raw_export(
+        &self,
+        name: &str,
+        params: &[host_function::Val],
+    ) -> Result<Vec<host_function::Val>> {
+        let _busy = BusyGuard::new(&self.state.busy);
+        self.ensure_initialized()?;
+        self.pristine.set(false);
+        let result = self
             .engine
             .borrow_mut()
-            .call(inv.operation.len() as i32, inv.msg.len() as i32)
+            .call_export(name, params)
+            .map_err(|e| {
+                errors::new(errors::ErrorKind::WasmMisc(format!(
+                    "Failed to call export {}: {}",
+                    name, e
+                )))
+                .with_module(self.state.id)
+            });
+        self.scrubbed(result)
+    }
+
+    /// Reads the value of the guest's exported global `name`, e.g. a version number, for
+    /// diagnostics tooling. Returns an error if the global does not exist or the engine
+    /// provider does not expose globals.
+    pub fn global(&self, name: &str) -> Result<host_function::Val> {
+        self.ensure_initialized()?;
+        self.engine.borrow().global(name).map_err(|e| {
+            errors::new(errors::ErrorKind::WasmMisc(format!(
+                "Failed to read global {}: {}",
+                name, e
+            )))
+            .with_module(self.state.id)
+        })
+    }
+
+    /// Returns a probe that takes snapshots of this host's state from any thread, even while a
+    /// guest call is in progress. See the [debug](debug/index.html) module.
+    pub fn debug_probe(&self) -> debug::DebugProbe {
+        debug::DebugProbe {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Takes a snapshot of this host's state. Use a [debug_probe](#method.debug_probe) to take
+    /// one from another thread.
+    pub fn debug_state(&self) -> debug::DebugState {
+        self.debug_probe().snapshot()
+    }
+
+    /// Returns the name, version and enabled features of the WebAssembly engine running this
+    /// host's guest, if the engine provider reports them
+    pub fn engine_info(&self) -> Option<EngineInfo> {
+        self.engine.borrow().engine_info()
+    }
+
+    /// Returns the resources this module has used since the host was created: its memory and
+    /// table sizes, as far as the engine exposes them, fuel consumed, call and host call counts,
+    /// and the bytes passed in and out of the guest
+    pub fn resource_report(&self) -> ResourceReport {
+        let memory_pages = self.with_memory(|mem| (mem.len() / 65536) as u32).ok();
+        let table_sizes = self.engine.borrow().table_sizes();
+        self.state.usage.report(
+            self.state.id,
+            memory_pages,
+            table_sizes,
+            self.fuel_consumed.get(),
+        )
+    }
+
+    /// Records every subsequent guest call, and the host calls made while handling it, to a
+    /// trace file at the given path, replacing any recording in progress. The trace can be
+    /// re-driven against another build of the module with [replay](recorder/fn.replay.html).
+    pub fn record_to(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let recorder = recorder::Recorder::create(path.as_ref())?;
+        *self.state.recorder.lock().unwrap() = Some(recorder);
+        Ok(())
+    }
+
+    /// Stops recording calls started with [record_to](#method.record_to)
+    pub fn stop_recording(&self) {
+        *self.state.recorder.lock().unwrap() = None;
+    }
+
+    /// Applies the recycle policy configured on the builder after a call completes
+    fn recycle_if_due(&self, succeeded: bool) {
+        let errors = if succeeded {
+            0
+        } else {
+            self.consecutive_errors.get() + 1
+        };
+        let calls = self.calls_since_reset.get() + 1;
+        self.consecutive_errors.set(errors);
+        self.calls_since_reset.set(calls);
+
+        let due = self.options.recycle_after_errors.is_some_and(|n| errors >= n)
+            || self.options.recycle_after_calls.is_some_and(|n| calls >= n);
+        if due {
+            debug!("Recycling guest module {} after {} calls", self.state.id, calls);
+            if let Err(e) = self.reset() {
+                warn!("Failed to recycle guest module {}: {}", self.state.id, e);
+            }
+        }
+    }
+
+    /// Prepares the guest for its first real call: the engine provider compiles `__guest_call`
+    /// if it compiles lazily, and then each of `ops` is invoked once with an empty payload so
+    /// the code behind it is compiled and paged in. Guests that return an error for the empty
+    /// payload are fine, as the call still exercised the operation; any other failure, such as
+    /// a trap, is returned. Warmup calls bypass middleware, statistics and recording.
+    pub fn warmup(&self, ops: &[&str]) -> Result<()> {
+        let id = self.state.id;
+        self.engine.borrow_mut().warmup().map_err(|e| {
+            errors::new(errors::ErrorKind::WasmMisc(format!("Failed to warm up guest: {}", e)))
+                .with_module(id)
+        })?;
+        for op in ops {
+            match self.invoke_outcome(op, &[]) {
+                Ok(_) => {}
+                Err(e)
+                    if !self.trapped.get()
+                        && matches!(e.kind(), errors::ErrorKind::GuestCallFailure(_)) =>
+                {
+                    debug!("Warmup call to {} on guest module {} failed: {}", op, id, e)
+                }
+                Err(e) => return Err(e.with_module(id)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Discards the guest instance and instantiates the current module afresh, reclaiming any
+    /// memory or state accumulated by the guest. The module's ID, configuration and loggers are
+    /// retained. Returns an error if the engine provider does not support resetting.
+    pub fn reset(&self) -> Result<()> {
+        self.consecutive_errors.set(0);
+        self.calls_since_reset.set(0);
+        if !self.initialized.get() {
+            return self.ensure_initialized();
+        }
+        if self.pristine.get() {
+            return Ok(());
+        }
+        self.engine.borrow_mut().reset().map_err(|e| {
+            errors::new(errors::ErrorKind::WasmMisc(format!(
+                "Failed to reset guest module: {}",
+                e
+            )))
+            .with_module(self.state.id)
+        })?;
+        self.pristine.set(true);
+        Ok(())
+    }
+
+    /// Invokes several operations with a single crossing of the host/guest boundary, returning
+    /// one result per operation in the same order. The invocations are framed as described in
+    /// the [batch](batch/index.html) module. If the guest has never successfully handled a batch
+    /// and fails this one, it is assumed not to support batching: the calls are made one at a
+    /// time, and future batches go straight to sequential calls. Once a guest has handled a
+    /// batch, a failed batch is not retried sequentially, as some of its operations may already
+    /// have run; every request fails with the batch's error instead. Replacing the module
+    /// discards what was learned about its support for batching.
+    pub fn call_batch(&self, requests: &[(&str, &[u8])]) -> Vec<Result<Vec<u8>>> {
+        if self.batch_supported.get() != Some(false) {
+            let response = self
+                .call(batch::BATCH_OPERATION, &batch::encode_requests(requests))
+                .and_then(|r| {
+                    batch::decode_responses(&r).map_err(|e| {
+                        errors::new(errors::ErrorKind::GuestCallFailure(e))
+                            .with_module(self.state.id)
+                    })
+                });
+            match response {
+                Ok(responses) if responses.len() == requests.len() => {
+                    self.batch_supported.set(Some(true));
+                    return responses
+                        .into_iter()
+                        .map(|r| {
+                            r.map_err(|e| {
+                                errors::new(errors::ErrorKind::GuestCallFailure(e))
+                                    .with_module(self.state.id)
+                            })
+                        })
+                        .collect();
+                }
+                response if self.batch_supported.get() == Some(true) => {
+                    let reason = match response {
+                        Ok(responses) => format!(
+                            "Guest answered {} of {} batched requests",
+                            responses.len(),
+                            requests.len()
+                        ),
+                        Err(e) => e.to_string(),
+                    };
+                    return requests
+                        .iter()
+                        .map(|_| {
+                            Err(errors::new(errors::ErrorKind::GuestCallFailure(format!(
+                                "Batch failed: {}",
+                                reason
+                            )))
+                            .with_module(self.state.id))
+                        })
+                        .collect();
+                }
+                _ => {
+                    debug!(
+                        "Module {} does not support batching, falling back to sequential calls",
+                        self.state.id
+                    );
+                    self.batch_supported.set(Some(false));
+                }
+            }
+        }
+        requests
+            .iter()
+            .map(|(op, payload)| self.call(op, payload))
+            .collect()
+    }
+
+    /// Invokes an operation within a namespace, following the `namespace!Operation` naming
+    /// convention. Equivalent to calling `call` with `"{namespace}!{operation}"`.
+    pub fn call_route(&self, namespace: &str, operation: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        self.call(&route::Route::new(namespace, operation).to_string(), payload)
+    }
+
+    /// Serializes the given value with MessagePack, invokes the operation with it as the payload,
+    /// and deserializes the guest's MessagePack response. Serialization failures in either
+    /// direction are reported as `ErrorKind::Serialization`. If the guest's
+    /// [interface](#method.interface) declares a different encoding for the operation, the call
+    /// is not made and `ErrorKind::OperationSignatureMismatch` is returned.
+    #[cfg(feature = "msgpack")]
+    pub fn call_serde<T, R>(&self, op: &str, payload: &T) -> Result<R>
+    where
+        T: serde::Serialize + ?Sized,
+        R: serde::de::DeserializeOwned,
+    {
+        if let Some(interface) = self.interface() {
+            let declared = interface.operation(op).map(|d| (&d.input, &d.output));
+            if let Some((input, output)) = declared {
+                let expected = [input, output]
+                    .iter()
+                    .filter_map(|e| e.as_deref())
+                    .find(|e| *e != metadata::MSGPACK_ENCODING);
+                if let Some(expected) = expected {
+                    return Err(errors::new(errors::ErrorKind::OperationSignatureMismatch {
+                        operation: op.to_string(),
+                        expected: expected.to_string(),
+                        found: metadata::MSGPACK_ENCODING.to_string(),
+                    })
+                    .with_module(self.state.id));
+                }
+            }
+        }
+        let bytes = rmp_serde::to_vec_named(payload).map_err(|e| {
+            errors::new(errors::ErrorKind::Serialization(format!(
+                "Failed to serialize payload for '{}': {}",
+                op, e
+            )))
+            .with_module(self.state.id)
+        })?;
+        let response = self.call(op, &bytes)?;
+        rmp_serde::from_slice(&response).map_err(|e| {
+            errors::new(errors::ErrorKind::Serialization(format!(
+                "Failed to deserialize response from '{}': {}",
+                op, e
+            )))
+            .with_module(self.state.id)
+        })
+    }
+
+    fn invoke_with_middleware(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        let id = self.state.id;
+        let mut op = op.to_string();
+        let mut payload = payload.to_vec();
+        let mut entered = 0;
+        let mut answer = None;
+        // The call as each middleware saw it, for those that a later middleware may rewrite it
+        // for, so that each one's after_call sees the same call as its short_circuit did
+        let mut seen: Vec<(String, Vec<u8>)> = Vec::new();
+        let layers = self.options.middleware.len();
+        for m in self.options.middleware.iter() {
+            if let Err(e) = m.before_call(id, &mut op, &mut payload) {
+                answer = Some(Err(e));
+                break;
+            }
+            entered += 1;
+            answer = m.short_circuit(id, &op, &payload);
+            if answer.is_some() {
+                break;
+            }
+            if entered < layers {
+                seen.push((op.clone(), payload.clone()));
+            }
+        }
+        let mut result = match answer {
+            Some(result) => result,
+            None => self.invoke(&op, &payload),
+        };
+        for (i, m) in self.options.middleware[..entered].iter().enumerate().rev() {
+            let (op, payload) = seen.get(i).map_or((&op, &payload), |(o, p)| (o, p));
+            m.after_call(id, op, payload, &mut result);
+        }
+        result
+    }
+
+    fn invoke(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        match self.invoke_outcome(op, payload)? {
+            deferred::CallOutcome::Complete(r) => Ok(r),
+            deferred::CallOutcome::Deferred(token) => {
+                self.deferred.borrow_mut().insert(&token);
+                Err(errors::new(errors::ErrorKind::GuestCallFailure(format!(
+                    "Guest deferred its response with token '{}'; use call_deferrable to accept deferred responses",
+                    token
+                ))))
+            }
+        }
+    }
+
+    /// Invokes an operation on the guest, allowing the guest to defer its response. A deferred
+    /// call must be continued with [resume](#method.resume) before the host's deferred timeout
+    /// elapses. See the [deferred](deferred/index.html) module for the protocol.
+    pub fn call_deferrable(&self, op: &str, payload: &[u8]) -> Result<deferred::CallOutcome> {
+        let outcome = self
+            .invoke_outcome(op, payload)
+            .map_err(|e| e.with_module(self.state.id))?;
+        if let deferred::CallOutcome::Deferred(ref token) = outcome {
+            self.deferred.borrow_mut().insert(token);
+        }
+        Ok(outcome)
+    }
+
+    /// Resumes a deferred call by invoking `__wapc_resume!{token}` on the guest with the given
+    /// payload. Fails without invoking the guest if the token is unknown or has expired.
+    pub fn resume(&self, token: &str, payload: &[u8]) -> Result<deferred::CallOutcome> {
+        self.deferred.borrow_mut().take(token).map_err(|msg| {
+            errors::new(errors::ErrorKind::GuestCallFailure(msg)).with_module(self.state.id)
+        })?;
+        let op = route::Route::new(deferred::RESUME_NAMESPACE, token).to_string();
+        self.call_deferrable(&op, payload)
+    }
+
+    /// Returns the tokens of all deferred calls awaiting resumption, including any that have
+    /// expired but not yet been purged
+    pub fn pending_deferred(&self) -> Vec<String> {
+        self.deferred.borrow().pending()
+    }
+
+    /// Purges all expired deferred calls, returning their tokens
+    pub fn expire_deferred(&self) -> Vec<String> {
+        self.deferred.borrow_mut().expire()
+    }
+
+    /// Writes the coredump the engine captured for the trap that just ended a call to the
+    /// configured directory. A coredump that cannot be written is logged, so that the trap is
+    /// still reported.
+    fn write_coredump(&self) {
+        let dir = match self.options.coredump_dir {
+            Some(ref dir) => dir,
+            None => return,
+        };
+        let dump = match self.engine.borrow_mut().take_coredump() {
+            Some(dump) => dump,
+            None => return,
+        };
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let path = dir.join(format!("wapc-{}-{}.coredump", self.state.id, millis));
+        match std::fs::write(&path, dump) {
+            Ok(()) => {
+                let id = self.state.id;
+                warn!("Guest module {} trapped; wrote coredump to {}", id, path.display());
+                *self.state.last_coredump.lock().unwrap() = Some(path);
+            }
+            Err(e) => warn!(
+                "Failed to write coredump of guest module {} to {}: {}",
+                self.state.id,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Invokes the guest, the path every waPC call takes, scrubbing its data afterwards. The
+    /// host is busy throughout, so a shutdown waits for the call to drain.
+    fn invoke_outcome(&self, op: &str, payload: &[u8]) -> Result<deferred::CallOutcome> {
+        let _busy = BusyGuard::new(&self.state.busy);
+        let outcome = self.invoke_guest(op, payload);
+        self.scrubbed(outcome)
+    }
+
+    fn invoke_guest(&self, op: &str, payload: &[u8]) -> Result<deferred::CallOutcome> {
+        if self.state.closed.load(Ordering::SeqCst) {
+            return Err(errors::new(errors::ErrorKind::HostClosed));
+        }
+        self.ensure_initialized()?;
+        let inv = Invocation::new(op, payload);
+        self.state.usage.call(payload.len());
+
         {
+            *self.state.guest_deferred.write().unwrap() = None;
+            *self.state.guest_response.write().unwrap() = None;
+            *self.state.oversized_response.write().unwrap() = None;
+            *self.state.guest_request.write().unwrap() = Some(inv.clone());
+            *self.state.guest_error.write().unwrap() = None;
+            *self.state.host_response.write().unwrap() = None;
+            *self.state.host_error.write().unwrap() = None;
+        }
+
+        *self.state.call_thread.lock().unwrap() = Some(std::thread::current().id());
+        *self.state.call_started.lock().unwrap() = Some((op.to_string(), Instant::now()));
+        self.pristine.set(false);
+        self.trapped.set(false);
+        let callresult = self
+            .engine
+            .borrow_mut()
+            .call(inv.operation.len() as i32, inv.msg.len() as i32);
+        *self.state.call_thread.lock().unwrap() = None;
+        *self.state.call_started.lock().unwrap() = None;
+        let callresult = match callresult {
             Ok(c) => c,
             Err(e) => {
-                return Err(errors::new(errors::ErrorKind::GuestCallFailure(format!(
-                    "{}",
-                    e
-                ))));
+                self.trapped.set(true);
+                self.state.abandon_streams();
+                self.write_coredump();
+                let backtrace = self.engine.borrow_mut().take_backtrace();
+                let traced = |error: errors::Error| match backtrace {
+                    Some(frames) => error.with_backtrace(frames),
+                    None => error,
+                };
+                if is_stack_overflow(e.as_ref()) {
+                    *self.state.guest_error.write().unwrap() = None;
+                    let kind = errors::ErrorKind::GuestStackOverflow(e.to_string());
+                    return Err(traced(errors::new(kind)));
+                }
+                // A guest that recorded an error before trapping (such as through the
+                // AssemblyScript abort shim) is reported by that error rather than the trap
+                let reason = self
+                    .state
+                    .guest_error
+                    .write()
+                    .unwrap()
+                    .take()
+                    .unwrap_or_else(|| format!("{}", e));
+                return Err(traced(errors::new(errors::ErrorKind::GuestCallFailure(reason))));
             }
         };
+        self.state.abandon_streams();
 
+        if let Some(size) = self.state.oversized_response.write().unwrap().take() {
+            return Err(errors::new(errors::ErrorKind::ResponseTooLarge {
+                size: size as u64,
+                limit: self.state.max_response_size.unwrap_or_default() as u64,
+            }));
+        }
         if callresult == 0 {
             // invocation failed
             let lock = self.state.guest_error.read().unwrap();
@@ -463,8 +2259,17 @@ impl WapcHost {
             }
         } else {
             // invocation succeeded
-            match *self.state.guest_response.read().unwrap() {
-                Some(ref e) => Ok(e.clone()),
+            // The response is moved out rather than cloned, as it may be large
+            let response = self.state.guest_response.write().unwrap().take();
+            match response {
+                Some(e) => {
+                    self.state.usage.bytes_out(e.len());
+                    Ok(deferred::CallOutcome::Complete(e))
+                }
+                None if self.state.guest_deferred.read().unwrap().is_some() => {
+                    let token = self.state.guest_deferred.write().unwrap().take();
+                    Ok(deferred::CallOutcome::Deferred(token.unwrap_or_default()))
+                }
                 None => {
                     let lock = self.state.guest_error.read().unwrap();
                     match *lock {
@@ -478,6 +2283,95 @@ impl WapcHost {
         }
     }
 
+    /// Runs the given closure with read access to the guest module's linear memory, for embedders
+    /// implementing their own shared-buffer protocols on top of waPC.
+    ///
+    /// This bypasses the waPC protocol entirely: the layout of guest memory is defined by the
+    /// guest's toolchain and allocator, and any addresses must be agreed upon out of band. The
+    /// slice is only valid for the duration of the closure, as the guest may grow (and therefore
+    /// move) its memory during the next call. Returns an error if the engine provider does not
+    /// expose guest memory.
+    pub fn with_memory<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        self.engine
+            .borrow()
+            .with_memory(&mut |mem| result = f.take().map(|f| f(mem)))
+            .map_err(|e| memory_error(e).with_module(self.state.id))?;
+        result.ok_or_else(|| {
+            memory_error("Engine provider did not supply guest memory".into())
+                .with_module(self.state.id)
+        })
+    }
+
+    /// Runs the given closure with write access to the guest module's linear memory. The same
+    /// caveats as [with_memory](#method.with_memory) apply; additionally, writing over memory
+    /// owned by the guest's allocator or stack will corrupt the guest.
+    pub fn with_memory_mut<R>(&self, f: impl FnOnce(&mut [u8]) -> R) -> Result<R> {
+        self.pristine.set(false);
+        let mut f = Some(f);
+        let mut result = None;
+        self.engine
+            .borrow_mut()
+            .with_memory_mut(&mut |mem| result = f.take().map(|f| f(mem)))
+            .map_err(|e| memory_error(e).with_module(self.state.id))?;
+        result.ok_or_else(|| {
+            memory_error("Engine provider did not supply guest memory".into())
+                .with_module(self.state.id)
+        })
+    }
+
+    /// Checks that the guest is responsive. If the guest handles the standard `__wapc_health`
+    /// operation, its response is used, and any error it returns (or trap) makes it unhealthy.
+    /// A guest that does not handle the operation, reporting it as unsupported, is checked by a
+    /// read of its linear memory instead. The probe is not counted in call statistics, recorded,
+    /// or counted towards [recycling](struct.WapcHostBuilder.html#method.recycle_after_errors).
+    ///
+    /// Because waPC calls are synchronous, the timeout cannot interrupt a hung guest; a probe
+    /// that completes after the timeout has elapsed is reported as unhealthy and `timed_out`.
+    pub fn health_check(&self, timeout: std::time::Duration) -> health::HealthReport {
+        let started = Instant::now();
+        let outcome = self.invoke_outcome(health::HEALTH_OPERATION, &[]);
+        let (probe, response, mut detail) = match outcome {
+            Ok(deferred::CallOutcome::Complete(r)) => {
+                (health::HealthProbe::Operation, Some(r), None)
+            }
+            Ok(deferred::CallOutcome::Deferred(_)) => (
+                health::HealthProbe::Operation,
+                None,
+                Some("Guest deferred its health response".to_string()),
+            ),
+            Err(e) if !is_unsupported_operation(&e) => {
+                (health::HealthProbe::Operation, None, Some(e.to_string()))
+            }
+            Err(call_err) => match self.with_memory(|mem| mem.len()) {
+                Ok(_) => (health::HealthProbe::Memory, None, None),
+                Err(e) => (
+                    health::HealthProbe::Memory,
+                    None,
+                    Some(format!("{}; {}", call_err, e)),
+                ),
+            },
+        };
+        let latency = started.elapsed();
+        let timed_out = latency > timeout;
+        if timed_out && detail.is_none() {
+            detail = Some(format!(
+                "Health probe took {:?}, exceeding the {:?} timeout",
+                latency, timeout
+            ));
+        }
+        health::HealthReport {
+            module_id: self.state.id,
+            healthy: detail.is_none(),
+            probe,
+            latency,
+            timed_out,
+            response,
+            detail,
+        }
+    }
+
     /// Performs a live "hot swap" of the WebAssembly module. Since all internal waPC execution is assumed to be
     /// single-threaded and non-reentrant, this call is synchronous and so
     /// you should never attempt to invoke `call` from another thread while performing this hot swap.
@@ -489,11 +2383,1489 @@ impl WapcHost {
     /// like the environment variables, mapped directories, pre-opened files, etc. Not abiding by this could lead
     /// to privilege escalation attacks or non-deterministic behavior after the swap.
     pub fn replace_module(&self, module: &[u8]) -> Result<()> {
+        *self.metadata.borrow_mut() = None;
+        *self.interface.borrow_mut() = None;
+        self.batch_supported.set(None);
+        // A lazy host that has not started yet instantiates the new module on its first call
+        if !self.initialized.get() {
+            match self.engine.borrow_mut().set_module(module) {
+                Ok(_) => return Ok(()),
+                Err(e) => debug!("Instantiating guest {} to replace it: {}", self.state.id, e),
+            }
+        }
+        self.ensure_initialized()?;
+        self.pristine.set(false);
         match self.engine.borrow_mut().replace(module) {
             Ok(_) => Ok(()),
             Err(e) => Err(errors::new(errors::ErrorKind::GuestCallFailure(
                 format!("Failed to swap module bytes: {}", e)
-            )))
+            ))
+            .with_module(self.state.id))
+        }
+    }
+}
+
+/// Recognizes a guest trap caused by stack exhaustion, whether the engine provider reported it
+/// as a `GuestStackOverflow` error or passed on its engine's own trap
+fn is_stack_overflow(e: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(e) = e.downcast_ref::<errors::Error>() {
+        return matches!(e.kind(), errors::ErrorKind::GuestStackOverflow(_));
+    }
+    let message = e.to_string().to_lowercase();
+    ["call stack exhausted", "stack overflow"]
+        .iter()
+        .any(|m| message.contains(m))
+}
+
+/// Whether a call failed because the guest does not handle the operation at all
+fn is_unsupported_operation(e: &errors::Error) -> bool {
+    match e.kind() {
+        errors::ErrorKind::NoSuchFunction(_) => true,
+        _ => e.guest_error().map(|g| g.class)
+            == Some(guest_error::GuestErrorClass::UnsupportedOperation),
+    }
+}
+
+fn memory_error(e: Box<dyn std::error::Error>) -> errors::Error {
+    errors::new(errors::ErrorKind::WasmMisc(format!(
+        "Unable to access guest memory: {}",
+        e
+    )))
+}
+
+impl Drop for WapcHost {
+    fn drop(&mut self) {
+        // Cleanup may run while a panic unwinds, where a second panic would abort the process,
+        // so each step is isolated and a failing step does not skip the rest
+        let id = self.state.id;
+        let run = |step: &str, f: &mut dyn FnMut()| {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+            if let Err(panic) = result {
+                error!("{} panicked cleaning up module {}: {}", step, id, panic_message(&*panic));
+            }
+        };
+        for provider in self.state.capabilities.values() {
+            run("Capability provider", &mut || provider.remove_module(id));
+        }
+        for p in self.state.plugins.iter() {
+            run("Plugin", &mut || p.on_host_dropped(id));
+        }
+        let hooks = std::mem::take(&mut *self.drop_hooks.borrow_mut());
+        for hook in hooks.into_iter().rev() {
+            let mut hook = Some(hook);
+            run("Drop hook", &mut || (hook.take().unwrap())(id));
+        }
+        if self.state.string_id.is_some() {
+            errors::forget_module(id);
+        }
+    }
+}
+
+impl WapcCaller for WapcHost {
+    fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        WapcHost::call(self, op, payload)
+    }
+
+    fn id(&self) -> u64 {
+        WapcHost::id(self)
+    }
+
+    fn replace_module(&self, module: &[u8]) -> Result<()> {
+        WapcHost::replace_module(self, module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type GuestFn = dyn FnMut(&ModuleState) -> i32;
+
+    /// Returned by a mock guest to make the engine report a trap
+    const TRAP: i32 = -1;
+
+    /// An engine provider that runs a closure in place of a real guest module
+    pub(crate) struct MockEngine {
+        state: Option<Arc<ModuleState>>,
+        guest: Box<GuestFn>,
+        memory: Vec<u8>,
+    }
+
+    impl MockEngine {
+        pub(crate) fn boxed(
+            guest: impl FnMut(&ModuleState) -> i32 + 'static,
+        ) -> Box<dyn WebAssemblyEngineProvider> {
+            Box::new(MockEngine {
+                state: None,
+                guest: Box::new(guest),
+                memory: vec![0; 64],
+            })
+        }
+    }
+
+    impl WebAssemblyEngineProvider for MockEngine {
+        fn init(
+            &mut self,
+            host: Arc<ModuleState>,
+        ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            self.state = Some(host);
+            Ok(())
+        }
+
+        fn call(
+            &mut self,
+            _op_length: i32,
+            _msg_length: i32,
+        ) -> std::result::Result<i32, Box<dyn std::error::Error>> {
+            let state = self.state.as_ref().unwrap();
+            match (self.guest)(state) {
+                TRAP => Err("wasm trap: wasm `unreachable` instruction executed".into()),
+                result => Ok(result),
+            }
+        }
+
+        fn replace(&mut self, _bytes: &[u8]) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            Err("replace is not supported by the mock engine".into())
+        }
+
+        fn unload(&mut self) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            self.state = None;
+            Ok(())
+        }
+
+        fn reset(&mut self) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            Ok(())
+        }
+
+        fn call_export(
+            &mut self,
+            name: &str,
+            params: &[host_function::Val],
+        ) -> std::result::Result<Vec<host_function::Val>, Box<dyn std::error::Error>> {
+            use host_function::Val;
+            match (name, params) {
+                ("add", [Val::I32(a), Val::I32(b)]) => Ok(vec![Val::I32(a + b)]),
+                _ => Err(format!("No export {} taking {:?}", name, params).into()),
+            }
+        }
+
+        fn global(
+            &self,
+            name: &str,
+        ) -> std::result::Result<host_function::Val, Box<dyn std::error::Error>> {
+            match name {
+                "version" => Ok(host_function::Val::I32(3)),
+                _ => Err("No such global".into()),
+            }
+        }
+
+        fn with_memory(
+            &self,
+            f: &mut dyn FnMut(&[u8]),
+        ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            f(&self.memory);
+            Ok(())
+        }
+
+        fn with_memory_mut(
+            &mut self,
+            f: &mut dyn FnMut(&mut [u8]),
+        ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+            f(&mut self.memory);
+            Ok(())
+        }
+    }
+
+    /// A guest that responds with the payload it was given
+    pub(crate) fn echo_guest(state: &ModuleState) -> i32 {
+        state.set_guest_response(state.get_guest_request().unwrap().msg.to_vec());
+        1
+    }
+
+    /// A guest that forwards its request to the host and relays the host's error, if any
+    fn relaying_guest(state: &ModuleState) -> i32 {
+        let inv = state.get_guest_request().unwrap();
+        match state.do_host_call("default", "test", &inv.operation, &inv.msg) {
+            Ok(1) => {
+                state.set_guest_response(state.get_host_response().unwrap());
+                1
+            }
+            _ => {
+                state.set_guest_error(state.get_host_error().unwrap());
+                0
+            }
+        }
+    }
+
+    #[test]
+    fn host_error_round_trips_through_guest() {
+        let host = WapcHost::new(MockEngine::boxed(relaying_guest), |_, _, _, op, _| {
+            Err(format!("no such key: {} (code 404)", op).into())
+        })
+        .unwrap();
+
+        let err = host.call("missing", b"").unwrap_err();
+        match err.kind() {
+            errors::ErrorKind::GuestCallFailure(msg) => {
+                assert_eq!(msg, "no such key: missing (code 404)")
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn host_response_round_trips_through_guest() {
+        let host = WapcHost::new(MockEngine::boxed(relaying_guest), |_, _, _, _, payload| {
+            Ok(payload.iter().rev().cloned().collect())
+        })
+        .unwrap();
+
+        assert_eq!(host.call("reverse", b"abc").unwrap(), b"cba");
+    }
+
+    #[test]
+    fn caller_supplied_id_is_reported_in_errors() {
+        let host = WapcHostBuilder::new()
+            .id(4242)
+            .build(MockEngine::boxed(|_| 0))
+            .unwrap();
+
+        assert_eq!(host.id(), 4242);
+        let err = host.call("anything", b"").unwrap_err();
+        assert_eq!(err.module_id(), Some(4242));
+        assert!(err.to_string().starts_with("[module 4242] "));
+    }
+
+    #[test]
+    fn string_ids_name_the_module_in_errors() {
+        let build = || {
+            WapcHostBuilder::new()
+                .string_id("tenant-a/billing")
+                .build(MockEngine::boxed(|_| 0))
+                .unwrap()
+        };
+        let (host, again) = (build(), build());
+
+        assert_ne!(host.id(), again.id());
+        assert_eq!(host.string_id(), Some("tenant-a/billing"));
+        assert_eq!(again.string_id(), Some("tenant-a/billing"));
+        let err = host.call("anything", b"").unwrap_err();
+        assert_eq!(err.module_id(), Some(host.id()));
+        assert_eq!(err.module_name(), Some("tenant-a/billing"));
+        assert!(err.to_string().starts_with("[module tenant-a/billing] "));
+
+        let id = host.id();
+        drop(host);
+        assert_eq!(errors::module_name(id), None);
+        assert!(again.call("anything", b"").unwrap_err().module_name().is_some());
+    }
+
+    #[test]
+    fn guest_memory_is_accessible_between_calls() {
+        let host = WapcHostBuilder::new().build(MockEngine::boxed(|_| 1)).unwrap();
+
+        host.with_memory_mut(|mem| mem[8..11].copy_from_slice(b"abc"))
+            .unwrap();
+        let read = host.with_memory(|mem| mem[8..11].to_vec()).unwrap();
+        assert_eq!(read, b"abc");
+    }
+
+    struct Tag(u8);
+
+    impl middleware::CallMiddleware for Tag {
+        fn before_call(&self, _: u64, _: &mut String, payload: &mut Vec<u8>) -> Result<()> {
+            payload.push(self.0);
+            Ok(())
+        }
+
+        fn after_call(&self, _: u64, _: &str, _: &[u8], result: &mut Result<Vec<u8>>) {
+            if let Ok(ref mut r) = result {
+                r.push(self.0.to_ascii_uppercase());
+            }
+        }
+    }
+
+    #[test]
+    fn middleware_runs_as_nested_layers() {
+        let host = WapcHostBuilder::new()
+            .middleware(Arc::new(Tag(b'a')))
+            .middleware(Arc::new(Tag(b'b')))
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+
+        assert_eq!(host.call("echo", b"x").unwrap(), b"xabBA");
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn call_serde_round_trips_msgpack() {
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let host = WapcHostBuilder::new().build(MockEngine::boxed(echo_guest)).unwrap();
+        let p: Point = host.call_serde("echo", &Point { x: 1, y: -2 }).unwrap();
+        assert_eq!(p, Point { x: 1, y: -2 });
+
+        let err = host.call_serde::<_, String>("echo", &42u8).unwrap_err();
+        assert!(matches!(err.kind(), errors::ErrorKind::Serialization(_)));
+    }
+
+    #[test]
+    fn health_check_falls_back_to_memory_probe() {
+        let unsupported = |state: &ModuleState| {
+            state.set_guest_error("[unsupported_operation] __wapc_health".to_string());
+            0
+        };
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(unsupported))
+            .unwrap();
+        let report = host.health_check(std::time::Duration::from_secs(5));
+        assert!(report.healthy);
+        assert_eq!(report.probe, health::HealthProbe::Memory);
+
+        let host = WapcHostBuilder::new().build(MockEngine::boxed(echo_guest)).unwrap();
+        let report = host.health_check(std::time::Duration::from_secs(5));
+        assert!(report.healthy);
+        assert_eq!(report.probe, health::HealthProbe::Operation);
+    }
+
+    #[test]
+    fn health_check_reports_guest_failures() {
+        let failing = |state: &ModuleState| {
+            state.set_guest_error("database unreachable".to_string());
+            0
+        };
+        let host = WapcHostBuilder::new()
+            .collect_stats()
+            .build(MockEngine::boxed(failing))
+            .unwrap();
+        let report = host.health_check(std::time::Duration::from_secs(5));
+        assert!(!report.healthy);
+        assert_eq!(report.probe, health::HealthProbe::Operation);
+        assert!(report.detail.unwrap().contains("database unreachable"));
+        assert!(host.stats().unwrap().snapshot().operations.is_empty());
+    }
+
+    /// A guest that defers its first call and completes when resumed
+    fn deferring_guest(state: &ModuleState) -> i32 {
+        let inv = state.get_guest_request().unwrap();
+        match inv.route().namespace {
+            Some(deferred::RESUME_NAMESPACE) => state.set_guest_response(inv.msg.to_vec()),
+            _ => state.set_guest_deferred("job-1".to_string()),
+        }
+        1
+    }
+
+    #[test]
+    fn deferred_calls_complete_when_resumed() {
+        use deferred::CallOutcome;
+
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(deferring_guest))
+            .unwrap();
+
+        let outcome = host.call_deferrable("start", b"").unwrap();
+        assert_eq!(outcome, CallOutcome::Deferred("job-1".to_string()));
+        assert_eq!(host.pending_deferred(), vec!["job-1".to_string()]);
+
+        let outcome = host.resume("job-1", b"done").unwrap();
+        assert_eq!(outcome, CallOutcome::Complete(b"done".to_vec()));
+        assert!(host.pending_deferred().is_empty());
+        assert!(host.resume("job-1", b"again").is_err());
+    }
+
+    #[test]
+    fn deferred_calls_expire() {
+        let host = WapcHostBuilder::new()
+            .deferred_timeout(std::time::Duration::from_millis(0))
+            .build(MockEngine::boxed(deferring_guest))
+            .unwrap();
+
+        host.call_deferrable("start", b"").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(host.expire_deferred(), vec!["job-1".to_string()]);
+        assert!(host.resume("job-1", b"").is_err());
+    }
+
+    /// A guest that handles batches by echoing each payload, failing operations named "fail"
+    fn batching_guest(state: &ModuleState) -> i32 {
+        let inv = state.get_guest_request().unwrap();
+        if &*inv.operation != batch::BATCH_OPERATION {
+            return echo_guest(state);
+        }
+        let responses: Vec<_> = batch::decode_requests(&inv.msg)
+            .unwrap()
+            .into_iter()
+            .map(|(op, payload)| match op.as_str() {
+                "fail" => Err("failed".to_string()),
+                _ => Ok(payload),
+            })
+            .collect();
+        state.set_guest_response(batch::encode_responses(&responses));
+        1
+    }
+
+    #[test]
+    fn call_batch_uses_single_guest_call_when_supported() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(move |state| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                batching_guest(state)
+            }))
+            .unwrap();
+
+        let results = host.call_batch(&[("a", b"1"), ("fail", b""), ("b", b"2")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(results[0].as_ref().unwrap(), b"1");
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), b"2");
+    }
+
+    #[test]
+    fn failed_batches_are_not_rerun_once_batching_is_confirmed() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(move |state| {
+                // The second batch traps partway through
+                if counter.fetch_add(1, Ordering::SeqCst) == 1 {
+                    return TRAP;
+                }
+                batching_guest(state)
+            }))
+            .unwrap();
+
+        assert!(host.call_batch(&[("a", b"1")]).iter().all(Result::is_ok));
+        let results = host.call_batch(&[("a", b"1"), ("b", b"2")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(results.iter().all(Result::is_err));
+    }
+
+    #[test]
+    fn call_batch_falls_back_to_sequential_calls() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(move |state| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                if &*state.get_guest_request().unwrap().operation == batch::BATCH_OPERATION {
+                    return 0;
+                }
+                echo_guest(state)
+            }))
+            .unwrap();
+
+        let results = host.call_batch(&[("a", b"1"), ("b", b"2")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(results[1].as_ref().unwrap(), b"2");
+
+        host.call_batch(&[("a", b"1"), ("b", b"2")]);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+
+    type Collected = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+    struct Collector(Collected);
+
+    struct CollectorWriter {
+        name: String,
+        buf: Vec<u8>,
+        out: Collected,
+    }
+
+    impl stream::StreamSink for Collector {
+        fn open(
+            &self,
+            _: u64,
+            name: &str,
+        ) -> std::result::Result<Box<dyn stream::StreamWriter>, Box<dyn Error + Send + Sync>>
+        {
+            Ok(Box::new(CollectorWriter {
+                name: name.to_string(),
+                buf: Vec::new(),
+                out: self.0.clone(),
+            }))
+        }
+    }
+
+    impl stream::StreamWriter for CollectorWriter {
+        fn write(&mut self, chunk: &[u8]) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+            self.buf.extend_from_slice(chunk);
+            Ok(())
+        }
+
+        fn close(self: Box<Self>) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+            self.out.lock().unwrap().push((self.name, self.buf));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn guest_streams_are_delivered_to_sink() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let host = WapcHostBuilder::new()
+            .stream_sink(Arc::new(Collector(out.clone())))
+            .build(MockEngine::boxed(|state| {
+                let id = state.do_stream_open("report");
+                assert_eq!(state.do_stream_write(id, b"hello "), 1);
+                assert_eq!(state.do_stream_write(id, b"world"), 1);
+                assert_eq!(state.do_stream_close(id), 1);
+                assert_eq!(state.do_stream_write(id, b"!"), 0);
+                assert!(state.get_host_error().is_some());
+                state.set_guest_response(vec![]);
+                1
+            }))
+            .unwrap();
+
+        host.call("stream", b"").unwrap();
+        assert_eq!(
+            *out.lock().unwrap(),
+            vec![("report".to_string(), b"hello world".to_vec())]
+        );
+    }
+
+    #[derive(Default)]
+    struct Lifecycle(Mutex<Vec<String>>);
+
+    impl plugin::RuntimePlugin for Lifecycle {
+        fn name(&self) -> &str {
+            "lifecycle"
+        }
+
+        fn on_host_created(&self, id: u64) {
+            self.0.lock().unwrap().push(format!("created {}", id));
+        }
+
+        fn on_host_dropped(&self, id: u64) {
+            self.0.lock().unwrap().push(format!("dropped {}", id));
+        }
+
+        fn on_guest_call(&self, _: u64, op: &str, error: Option<&str>, _: std::time::Duration) {
+            self.0.lock().unwrap().push(format!("call {} {:?}", op, error));
+        }
+
+        fn on_host_call(
+            &self,
+            ctx: &HostCallContext,
+            op: &str,
+            error: Option<&str>,
+            _: std::time::Duration,
+        ) {
+            let event = format!("host_call {}:{} {:?}", ctx.namespace, op, error);
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn plugins_observe_host_lifecycle() {
+        let plugin = Arc::new(Lifecycle::default());
+        let host = WapcHostBuilder::new()
+            .id(7)
+            .host_callback(|_, _, _, _, _| Ok(vec![]))
+            .plugin(plugin.clone())
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        host.call("op", b"").unwrap();
+        drop(host);
+
+        assert_eq!(
+            *plugin.0.lock().unwrap(),
+            vec![
+                "created 7",
+                "host_call test:op None",
+                "call op None",
+                "dropped 7"
+            ]
+        );
+    }
+
+    #[test]
+    fn events_flow_in_both_directions() {
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(|state| {
+                state
+                    .do_host_call("", events::EVENTS_NAMESPACE, events::POLL_OPERATION, &[])
+                    .unwrap();
+                for e in events::decode(&state.get_host_response().unwrap()).unwrap() {
+                    state.do_emit_event(&format!("ack.{}", e.topic), &e.payload);
+                }
+                state.set_guest_response(vec![]);
+                1
+            }))
+            .unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        host.subscribe(Some("ack.config"), move |_, topic, payload| {
+            sink.lock().unwrap().push((topic.to_string(), payload.to_vec()))
+        });
+
+        host.publish_to_guest("config", b"v2");
+        host.publish_to_guest("ignored", b"");
+        host.call("tick", b"").unwrap();
+        host.call("tick", b"").unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![("ack.config".to_string(), b"v2".to_vec())]
+        );
+    }
+
+    #[test]
+    fn closed_host_rejects_calls() {
+        let host = WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![])).unwrap();
+        let handle = host.shutdown_handle();
+        assert!(!handle.is_closed());
+
+        assert!(handle.shutdown(std::time::Duration::from_millis(10)));
+        match host.call("echo", b"hi").unwrap_err().kind() {
+            errors::ErrorKind::HostClosed => {}
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shutdown_drains_a_health_check_in_flight() {
+        let started = Arc::new(AtomicBool::new(false));
+        let release = Arc::new(AtomicBool::new(false));
+        let (entered, released) = (started.clone(), release.clone());
+        let guest = move |state: &ModuleState| {
+            entered.store(true, Ordering::SeqCst);
+            while !released.load(Ordering::SeqCst) {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            echo_guest(state)
+        };
+        let host = WapcHost::new(MockEngine::boxed(guest), |_, _, _, _, _| Ok(vec![])).unwrap();
+        let handle = host.shutdown_handle();
+
+        let closer = std::thread::spawn(move || {
+            while !started.load(Ordering::SeqCst) {
+                std::thread::yield_now();
+            }
+            let drained = handle.shutdown(std::time::Duration::from_millis(20));
+            release.store(true, Ordering::SeqCst);
+            drained
+        });
+        assert!(host.health_check(std::time::Duration::from_secs(5)).healthy);
+        assert!(!closer.join().unwrap());
+    }
+
+    #[test]
+    fn recorded_trace_replays() {
+        let path = std::env::temp_dir().join(format!("wapc-trace-{}.jsonl", std::process::id()));
+        let host = WapcHost::new(MockEngine::boxed(relaying_guest), |_, _, _, op, payload| {
+            Ok([op.as_bytes(), payload].concat())
+        })
+        .unwrap();
+        host.record_to(&path).unwrap();
+        host.call("get", b"1").unwrap();
+        host.call("put", b"2").unwrap();
+        host.stop_recording();
+
+        let trace = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(replay(&trace[..], &host).unwrap().is_match());
+
+        let other = WapcHost::new(MockEngine::boxed(relaying_guest), |_, _, _, _, payload| {
+            Ok(payload.to_vec())
+        })
+        .unwrap();
+        let report = replay(&trace[..], &other).unwrap();
+        assert_eq!(report.calls, 2);
+        assert_eq!(report.mismatches[1].actual, recorder::Outcome::Response(b"2".to_vec()));
+    }
+
+    #[test]
+    fn deterministic_mode_withholds_time_imports() {
+        let host = WapcHostBuilder::new()
+            .enable_time_imports()
+            .deterministic()
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+        assert!(host.state.engine_settings().deterministic);
+        assert!(!host.state.time_imports_enabled());
+    }
+
+    #[test]
+    fn host_call_status_carries_callback_code() {
+        let state = ModuleState::new(
+            Some(Arc::new(|_: u64, _: &str, _: &str, op: &str, _: &[u8]| match op {
+                "missing" => Err(Box::new(status::StatusError::new(404, "no such key")) as _),
+                "broken" => Err("disk on fire".into()),
+                _ => Ok(vec![]),
+            })),
+            1,
+        );
+        let call = |op| state.do_host_call_status("default", "kv", op, b"").unwrap();
+        assert_eq!(call("present"), status::STATUS_OK);
+        assert_eq!(call("missing"), 404);
+        assert_eq!(state.get_host_error().unwrap(), "no such key");
+        assert_eq!(call("broken"), status::STATUS_ERROR);
+    }
+
+    #[test]
+    fn capability_provider_handles_its_namespace() {
+        struct Upper(Mutex<Vec<String>>);
+        impl capability::CapabilityProvider for Upper {
+            fn namespace(&self) -> &str {
+                "test"
+            }
+            fn configure(
+                &self,
+                module_id: u64,
+                config: &capability::CapabilityConfig,
+            ) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+                let event = format!("configure {} {}", module_id, config["mode"]);
+                self.0.lock().unwrap().push(event);
+                Ok(())
+            }
+            fn remove_module(&self, module_id: u64) {
+                self.0.lock().unwrap().push(format!("remove {}", module_id));
+            }
+            fn handle_call(
+                &self,
+                _ctx: &HostCallContext,
+                _operation: &str,
+                payload: &[u8],
+            ) -> std::result::Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+                Ok(payload.to_ascii_uppercase())
+            }
+        }
+
+        let provider = Arc::new(Upper(Mutex::new(Vec::new())));
+        let config = [("mode".to_string(), "upper".to_string())].iter().cloned().collect();
+        let host = WapcHostBuilder::new()
+            .id(7)
+            .capability(provider.clone(), config)
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        assert_eq!(host.call("shout", b"hi").unwrap(), b"HI");
+        drop(host);
+
+        assert_eq!(*provider.0.lock().unwrap(), ["configure 7 upper", "remove 7"]);
+    }
+
+    #[test]
+    fn resource_report_accounts_for_calls_and_host_calls() {
+        let host = WapcHost::new(MockEngine::boxed(relaying_guest), |_, _, _, _, _| {
+            Ok(b"pong!".to_vec())
+        })
+        .unwrap();
+        host.call("ping", b"abc").unwrap();
+        host.call("ping", b"").unwrap();
+
+        let report = host.resource_report();
+        assert_eq!(report.module_id, host.id());
+        assert_eq!(report.calls, 2);
+        assert_eq!(report.host_calls, 2);
+        assert_eq!(report.bytes_in, 3 + 5 + 5);
+        assert_eq!(report.bytes_out, 3 + 5 + 5);
+        assert_eq!(report.memory_pages, Some(0));
+        assert_eq!(report.table_sizes, None);
+        assert!(serde_json::to_string(&report).unwrap().contains("\"host_calls\":2"));
+    }
+
+    #[test]
+    fn threaded_guests_only_make_host_calls_from_the_calling_thread() {
+        let host = WapcHostBuilder::new()
+            .host_callback(|_, _, _, _, _| Ok(b"pong!".to_vec()))
+            .threads()
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                let worker = std::thread::scope(|s| {
+                    s.spawn(|| state.do_host_call("default", "test", "ping", b"").is_err())
+                        .join()
+                        .unwrap()
+                });
+                assert!(worker);
+                relaying_guest(state)
+            }))
+            .unwrap();
+        assert_eq!(host.call("ping", b"").unwrap(), b"pong!");
+        assert!(host.state.do_host_call("default", "test", "ping", b"").is_err());
+
+        let conflicting = WapcHostBuilder::new()
+            .threads()
+            .deterministic()
+            .build(MockEngine::boxed(echo_guest));
+        assert!(conflicting.is_err());
+    }
+
+    #[test]
+    fn interface_is_negotiated_with_describe() {
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                let inv = state.get_guest_request().unwrap();
+                if &*inv.operation == metadata::DESCRIBE_OPERATION {
+                    let interface = r#"{"operations":[{"name":"echo","input":"json"}]}"#;
+                    state.set_guest_response(interface.as_bytes().to_vec());
+                    1
+                } else {
+                    echo_guest(state)
+                }
+            }))
+            .unwrap();
+        let interface = host.interface().unwrap();
+        assert_eq!(interface.operation("echo").unwrap().input.as_deref(), Some("json"));
+
+        #[cfg(feature = "msgpack")]
+        match host.call_serde::<_, String>("echo", "hi").unwrap_err().kind() {
+            errors::ErrorKind::OperationSignatureMismatch { expected, .. } => {
+                assert_eq!(expected, "json")
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        let undescribed = WapcHostBuilder::new().build(MockEngine::boxed(|_| 0)).unwrap();
+        assert!(undescribed.interface().is_none());
+    }
+
+    #[test]
+    fn pooling_allocation_limits_reach_the_engine() {
+        let pooling = PoolingAllocation::new(1000).max_memory_size(1 << 20);
+        let host = WapcHostBuilder::new()
+            .pooling_allocation(pooling)
+            .build(MockEngine::boxed(|_| 0))
+            .unwrap();
+        let settings = host.state.engine_settings().pooling_allocation.unwrap();
+        assert_eq!(settings.total_instances, 1000);
+        assert_eq!(settings.max_memory_size, Some(1 << 20));
+        assert_eq!(settings.table_elements, None);
+
+        let empty = WapcHostBuilder::new()
+            .pooling_allocation(PoolingAllocation::new(0))
+            .build(MockEngine::boxed(|_| 0));
+        assert!(matches!(empty.err().unwrap().kind(), errors::ErrorKind::WasmMisc(_)));
+    }
+
+    #[test]
+    fn data_memory_is_selected_by_export_name() {
+        let host = WapcHostBuilder::new()
+            .multi_memory()
+            .memory_export("data")
+            .build(MockEngine::boxed(|_| 0))
+            .unwrap();
+        assert!(host.state.engine_settings().multi_memory);
+        assert_eq!(host.state.engine_settings().memory_export, "data");
+        assert_eq!(EngineSettings::default().memory_export, "memory");
+
+        let unnamed = WapcHostBuilder::new()
+            .memory_export("")
+            .build(MockEngine::boxed(|_| 0));
+        assert!(matches!(unnamed.err().unwrap().kind(), errors::ErrorKind::WasmMisc(_)));
+    }
+
+    #[test]
+    fn replacing_an_unstarted_lazy_guest_does_not_instantiate_it() {
+        struct Swapping(Vec<u8>, std::rc::Rc<RefCell<Vec<Vec<u8>>>>);
+        impl WebAssemblyEngineProvider for Swapping {
+            fn init(&mut self, _: Arc<ModuleState>) -> std::result::Result<(), Box<dyn Error>> {
+                self.1.borrow_mut().push(self.0.clone());
+                Ok(())
+            }
+            fn call(&mut self, _: i32, _: i32) -> std::result::Result<i32, Box<dyn Error>> {
+                Ok(1)
+            }
+            fn replace(&mut self, bytes: &[u8]) -> std::result::Result<(), Box<dyn Error>> {
+                self.0 = bytes.to_vec();
+                self.1.borrow_mut().push(self.0.clone());
+                Ok(())
+            }
+            fn set_module(&mut self, bytes: &[u8]) -> std::result::Result<(), Box<dyn Error>> {
+                self.0 = bytes.to_vec();
+                Ok(())
+            }
+        }
+        let instantiated = std::rc::Rc::new(RefCell::new(Vec::new()));
+        let engine = Swapping(b"old".to_vec(), instantiated.clone());
+        let host = WapcHostBuilder::new().lazy().build(Box::new(engine)).unwrap();
+
+        host.replace_module(b"new").unwrap();
+        assert!(!host.is_initialized());
+        assert!(instantiated.borrow().is_empty());
+        host.ensure_initialized().unwrap();
+        assert_eq!(*instantiated.borrow(), vec![b"new".to_vec()]);
+
+        // Once started, the running guest is replaced
+        host.replace_module(b"newer").unwrap();
+        assert_eq!(instantiated.borrow().last().unwrap(), b"newer");
+    }
+
+    #[cfg(feature = "validate")]
+    #[test]
+    fn wasi_profiles_are_checked_at_build_time() {
+        struct Retaining(Vec<u8>);
+        impl WebAssemblyEngineProvider for Retaining {
+            fn init(&mut self, _: Arc<ModuleState>) -> std::result::Result<(), Box<dyn Error>> {
+                Ok(())
+            }
+            fn call(&mut self, _: i32, _: i32) -> std::result::Result<i32, Box<dyn Error>> {
+                Ok(1)
+            }
+            fn replace(&mut self, _: &[u8]) -> std::result::Result<(), Box<dyn Error>> {
+                Ok(())
+            }
+            fn module_bytes(&self) -> Option<&[u8]> {
+                Some(&self.0)
+            }
+        }
+        // A module importing wasi_snapshot_preview1.fd_write
+        let mut module = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        module.extend_from_slice(&[0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x02, 0x23, 0x01, 0x16]);
+        module.extend_from_slice(b"wasi_snapshot_preview1");
+        module.push(0x08);
+        module.extend_from_slice(b"fd_write");
+        module.extend_from_slice(&[0x00, 0x00]);
+
+        let refused = WapcHostBuilder::new()
+            .wasi_profile(wasi::WasiProfile::None)
+            .build(Box::new(Retaining(module.clone())));
+        let refused = refused.err().unwrap();
+        assert!(matches!(refused.kind(), errors::ErrorKind::WasiConfiguration(_)));
+        assert!(WapcHostBuilder::new()
+            .wasi_profile(wasi::WasiProfile::PureCompute)
+            .build(Box::new(Retaining(module.clone())))
+            .is_ok());
+        let opaque = WapcHostBuilder::new()
+            .wasi_profile(wasi::WasiProfile::Full)
+            .build(MockEngine::boxed(echo_guest));
+        assert!(opaque.err().unwrap().to_string().contains("use build_from_file"));
+
+        let path = std::env::temp_dir().join(format!("wapc-wasi-{}.wasm", std::process::id()));
+        std::fs::write(&path, &module).unwrap();
+        let from_file = WapcHostBuilder::new()
+            .wasi_profile(wasi::WasiProfile::None)
+            .build_from_file(&path, |_| MockEngine::boxed(echo_guest));
+        std::fs::remove_file(&path).unwrap();
+        let from_file = from_file.err().unwrap();
+        assert!(matches!(from_file.kind(), errors::ErrorKind::WasiConfiguration(_)));
+    }
+
+    #[test]
+    fn host_call_context_is_debuggable() {
+        let extensions = extensions::Extensions::default();
+        let ctx = HostCallContext {
+            module_id: 7,
+            binding: "default",
+            namespace: "kv",
+            extensions: &extensions,
+            trace: None,
+        };
+        let debug = format!("{:?}", ctx);
+        assert!(debug.starts_with(r#"HostCallContext { module_id: 7, binding: "default""#));
+        assert!(debug.ends_with("trace: None, .. }"));
+    }
+
+    #[test]
+    fn stack_exhaustion_traps_are_reported_as_overflows() {
+        let host = WapcHostBuilder::new()
+            .max_wasm_stack(64 * 1024)
+            .build(MockEngine::boxed(|_| 0))
+            .unwrap();
+        assert_eq!(host.state.engine_settings().max_wasm_stack, Some(64 * 1024));
+
+        let trap: Box<dyn std::error::Error> = "wasm trap: call stack exhausted".into();
+        assert!(is_stack_overflow(trap.as_ref()));
+        let trap: Box<dyn std::error::Error> = "wasm trap: unreachable".into();
+        assert!(!is_stack_overflow(trap.as_ref()));
+        let reported = errors::new(errors::ErrorKind::GuestStackOverflow("deep".to_string()));
+        assert!(is_stack_overflow(&reported));
+    }
+
+    #[test]
+    fn host_callback_panics_become_host_errors() {
+        let host = WapcHost::new(MockEngine::boxed(relaying_guest), |_, _, _, op, _| {
+            if op == "explode" {
+                panic!("kaboom");
+            }
+            Ok(b"fine".to_vec())
+        })
+        .unwrap();
+        let err = host.call("explode", b"").unwrap_err();
+        assert!(err.to_string().contains("Host callback panicked: kaboom"));
+        assert_eq!(host.call("ok", b"").unwrap(), b"fine");
+    }
+
+    #[test]
+    fn guest_error_bytes_fall_back_to_lossy_strings() {
+        let state = ModuleState::new(None, 0);
+        state.set_guest_error_bytes(b"plain message\0");
+        assert_eq!(state.guest_error.read().unwrap().as_deref(), Some("plain message"));
+        state.set_guest_error_bytes(&[0x82, b'o', b'k']);
+        assert_eq!(state.guest_error.read().unwrap().as_deref(), Some("\u{fffd}ok"));
+    }
+
+    #[test]
+    fn guests_can_list_host_namespaces() {
+        use providers::keyvalue::{KeyValueProvider, MemoryStore, KEYVALUE_NAMESPACE};
+        let host = WapcHostBuilder::new()
+            .advertise("test", &["ping"])
+            .capability(
+                Arc::new(KeyValueProvider::new(MemoryStore::default())),
+                Default::default(),
+            )
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                let ns = introspect::INTROSPECT_NAMESPACE;
+                assert_eq!(state.do_host_call("default", ns, "list", b"").unwrap(), 1);
+                state.set_guest_response(state.get_host_response().unwrap());
+                1
+            }))
+            .unwrap();
+        let listed: introspect::Capabilities =
+            serde_json::from_slice(&host.call("introspect", b"").unwrap()).unwrap();
+        let keyvalue = listed.namespace(KEYVALUE_NAMESPACE).unwrap();
+        assert!(keyvalue.operations.iter().any(|op| op == "get"));
+        assert_eq!(listed.namespace("test").unwrap().operations, vec!["ping"]);
+        assert!(listed.namespace(events::EVENTS_NAMESPACE).is_some());
+    }
+
+    #[cfg(feature = "audit")]
+    #[test]
+    fn host_calls_are_audited_with_policy_decisions() {
+        let sink = Arc::new(audit::MemorySink::new(8));
+        let host = WapcHostBuilder::new()
+            .host_callback(|_, _, _, _, _| Ok(b"ok".to_vec()))
+            .audit_sink(sink.clone())
+            .host_call_policy(Arc::new(|_: &HostCallContext, op: &str| {
+                if op == "secret" {
+                    Err("tenant may not read secrets".to_string())
+                } else {
+                    Ok(())
+                }
+            }))
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        assert_eq!(host.call("ping", b"abc").unwrap(), b"ok");
+        let denied = host.call("secret", b"").unwrap_err();
+        assert!(denied.to_string().contains("tenant may not read secrets"));
+
+        let records = sink.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].decision, audit::Decision::Allowed);
+        assert_eq!(
+            records[0].payload_sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(matches!(records[1].decision, audit::Decision::Denied(_)));
+        assert!(records[1].error.is_some());
+    }
+
+    #[test]
+    fn trace_context_reaches_host_calls() {
+        struct TraceEcho;
+        impl HostHandler for TraceEcho {
+            fn handle(
+                &self,
+                ctx: &HostCallContext,
+                _operation: &str,
+                _payload: &[u8],
+            ) -> std::result::Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+                Ok(ctx.trace.map(|t| t.trace_id().to_string()).unwrap_or_default().into_bytes())
+            }
+        }
+        let host = WapcHostBuilder::new()
+            .handler(Arc::new(TraceEcho))
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = trace::TraceContext::parse(header).unwrap();
+        let traced = host.call_with_context("op", b"", context).unwrap();
+        assert_eq!(traced, b"4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(host.call("op", b"").unwrap().is_empty());
+
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                state.do_host_call("default", trace::TRACE_NAMESPACE, "context", b"").unwrap();
+                state.set_guest_response(state.get_host_response().unwrap());
+                1
+            }))
+            .unwrap();
+        let context = trace::TraceContext::parse(header).unwrap();
+        assert_eq!(host.call_with_context("op", b"", context).unwrap(), header.as_bytes());
+    }
+
+    #[test]
+    fn scoped_hosts_clean_up_on_panic() {
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+        let id = host.id();
+        for tag in ["first", "second"] {
+            let released = released.clone();
+            host.on_drop(move |module_id| released.lock().unwrap().push((tag, module_id)));
+        }
+        host.on_drop(|_| panic!("hook failed"));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            host.scope(|host| {
+                host.call("op", b"").unwrap();
+                panic!("embedder bug");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(*released.lock().unwrap(), vec![("second", id), ("first", id)]);
+    }
+
+    #[test]
+    fn warmup_invokes_operations_and_tolerates_guest_errors() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let calls = seen.clone();
+        let host = WapcHostBuilder::new()
+            .warmup(&["render", "strict"])
+            .collect_stats()
+            .build(MockEngine::boxed(move |state: &ModuleState| {
+                let inv = state.get_guest_request().unwrap();
+                calls.lock().unwrap().push(inv.operation.to_string());
+                if &*inv.operation == "strict" {
+                    state.set_guest_error("empty payload".to_string());
+                    return 0;
+                }
+                1
+            }))
+            .unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec!["render", "strict"]);
+        assert!(host.stats().unwrap().snapshot().operations.is_empty());
+
+        let trapping = WapcHostBuilder::new()
+            .warmup(&["render"])
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                // A guest that records an error and then traps, e.g. through an abort shim
+                state.set_guest_error("abort".to_string());
+                TRAP
+            }));
+        assert!(trapping.is_err());
+    }
+
+    #[test]
+    fn lazy_hosts_initialize_on_first_call() {
+        let warmed = Arc::new(AtomicUsize::new(0));
+        let counter = warmed.clone();
+        let host = WapcHostBuilder::new()
+            .lazy()
+            .warmup(&["prime"])
+            .build(MockEngine::boxed(move |state: &ModuleState| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                echo_guest(state)
+            }))
+            .unwrap();
+        assert!(!host.is_initialized());
+        assert_eq!(warmed.load(Ordering::SeqCst), 0);
+
+        assert_eq!(host.call("echo", b"hi").unwrap(), b"hi");
+        assert!(host.is_initialized());
+        assert_eq!(warmed.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn unloaded_hosts_reinstantiate_on_next_call() {
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+        assert_eq!(host.call("echo", b"one").unwrap(), b"one");
+        host.unload().unwrap();
+        assert!(!host.is_initialized());
+        host.unload().unwrap();
+
+        assert_eq!(host.call("echo", b"two").unwrap(), b"two");
+        assert!(host.is_initialized());
+    }
+
+    #[test]
+    fn transient_host_call_failures_are_retried() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let host = WapcHostBuilder::new()
+            .host_callback(move |_, _, _, _, _| {
+                if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err("connection reset".into())
+                } else {
+                    Ok(b"ok".to_vec())
+                }
+            })
+            .retry_host_calls("test", retry::RetryPolicy::new(3))
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        assert_eq!(host.call("fetch", b"").unwrap(), b"ok");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn open_circuits_fail_host_calls_fast() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let counter = handled.clone();
+        let cool_down = std::time::Duration::from_secs(60);
+        let breaker = Arc::new(circuit::CircuitBreaker::new(1.0, 2, cool_down));
+        let host = WapcHostBuilder::new()
+            .host_callback(move |_, _, _, _, _| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Err("downstream unavailable".into())
+            })
+            .circuit_breaker("test", breaker.clone())
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        for _ in 0..2 {
+            assert!(host.call("fetch", b"").is_err());
+        }
+        let err = host.call("fetch", b"").unwrap_err();
+        assert!(err.to_string().contains("Circuit open for namespace test"));
+        assert_eq!(handled.load(Ordering::SeqCst), 2);
+        assert_eq!(breaker.state(), circuit::CircuitState::Open);
+    }
+
+    #[cfg(feature = "audit")]
+    #[test]
+    fn denied_host_calls_do_not_count_against_the_circuit() {
+        let cool_down = std::time::Duration::from_secs(60);
+        let breaker = Arc::new(circuit::CircuitBreaker::new(1.0, 1, cool_down));
+        let deny = |_: &HostCallContext, op: &str| match op {
+            "forbidden" => Err("not allowed".to_string()),
+            _ => Ok(()),
+        };
+        let host = WapcHostBuilder::new()
+            .host_callback(|_, _, _, _, _| Ok(b"ok".to_vec()))
+            .host_call_policy(Arc::new(deny))
+            .circuit_breaker("test", breaker.clone())
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+
+        // The policy runs before the circuit breaker, so a denied call is neither let through
+        // by it nor recorded as a failure
+        for _ in 0..3 {
+            let err = host.call("forbidden", b"").unwrap_err();
+            assert!(err.to_string().contains("denied by policy"));
+        }
+        assert_eq!(breaker.state(), circuit::CircuitState::Closed);
+        assert_eq!(host.call("fetch", b"").unwrap(), b"ok");
+    }
+
+    #[test]
+    fn oversized_responses_are_rejected() {
+        let host = WapcHostBuilder::new()
+            .max_response_size(4)
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                let ns = limits::LIMITS_NAMESPACE;
+                state.do_host_call("default", ns, "max_response_size", b"").unwrap();
+                assert_eq!(state.get_host_response().unwrap(), 4u64.to_be_bytes());
+                echo_guest(state)
+            }))
+            .unwrap();
+        assert_eq!(host.call("echo", b"tiny").unwrap(), b"tiny");
+        let err = host.call("echo", b"too big").unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            errors::ErrorKind::ResponseTooLarge { size: 7, limit: 4 }
+        ));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn call_bytes_accepts_any_buf() {
+        use bytes::Buf;
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+        let payload = bytes::Bytes::from_static(b"hello");
+        assert_eq!(host.call_bytes("echo", payload).unwrap(), "hello");
+        let chained = (&b"split "[..]).chain(&b"payload"[..]);
+        assert_eq!(host.call_bytes("echo", chained).unwrap(), "split payload");
+    }
+
+    #[test]
+    fn engine_info_comes_from_the_provider() {
+        struct Described(Box<dyn WebAssemblyEngineProvider>);
+        impl WebAssemblyEngineProvider for Described {
+            fn init(
+                &mut self,
+                host: Arc<ModuleState>,
+            ) -> std::result::Result<(), Box<dyn std::error::Error>> {
+                self.0.init(host)
+            }
+            fn call(
+                &mut self,
+                op_length: i32,
+                msg_length: i32,
+            ) -> std::result::Result<i32, Box<dyn std::error::Error>> {
+                self.0.call(op_length, msg_length)
+            }
+            fn replace(&mut self, bytes: &[u8]) -> std::result::Result<(), Box<dyn Error>> {
+                self.0.replace(bytes)
+            }
+            fn engine_info(&self) -> Option<EngineInfo> {
+                Some(EngineInfo {
+                    name: "mock".to_string(),
+                    version: "1.0.0".to_string(),
+                    features: vec!["fuel".to_string()],
+                })
+            }
+        }
+        let plain = WapcHostBuilder::new()
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+        assert!(plain.engine_info().is_none());
+        let described = WapcHostBuilder::new()
+            .build(Box::new(Described(MockEngine::boxed(echo_guest))))
+            .unwrap();
+        assert_eq!(described.engine_info().unwrap().version, "1.0.0");
+    }
+
+    #[test]
+    fn linked_modules_keep_registration_order() {
+        let host = WapcHostBuilder::new()
+            .link_module("strings", b"\0asm")
+            .link_module("utils", b"\0asm")
+            .build(MockEngine::boxed(|state: &ModuleState| {
+                let names: Vec<_> = state.linked_modules().iter().map(|m| m.name()).collect();
+                state.set_guest_response(names.join(",").into_bytes());
+                1
+            }))
+            .unwrap();
+        assert_eq!(host.call("list", b"").unwrap(), b"strings,utils");
+
+        let duplicate = WapcHostBuilder::new()
+            .link_module("utils", b"")
+            .link_module("utils", b"")
+            .build(MockEngine::boxed(echo_guest));
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn raw_exports_and_globals_are_reachable() {
+        use host_function::Val;
+        let host = WapcHostBuilder::new()
+            .build(MockEngine::boxed(echo_guest))
+            .unwrap();
+        let sum = host.call_raw_export("add", &[Val::I32(2), Val::I32(5)]).unwrap();
+        assert_eq!(sum, vec![Val::I32(7)]);
+        assert_eq!(host.global("version").unwrap(), Val::I32(3));
+        let missing = host.global("build").unwrap_err();
+        assert!(missing.to_string().contains("Failed to read global build"));
+    }
+
+    #[test]
+    fn scrubbed_hosts_leave_no_data_behind() {
+        let host = WapcHostBuilder::new()
+            .host_callback(|_, _, _, _, payload| Ok(payload.to_vec()))
+            .scrub_memory(MemoryScrub::ZeroAndReinstantiate)
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        host.with_memory_mut(|mem| mem.copy_from_slice(&[0x5a; 64])).unwrap();
+        assert_eq!(host.call("echo", b"secret").unwrap(), b"secret");
+
+        assert!(host.with_memory(|mem| mem.iter().all(|b| *b == 0)).unwrap());
+        assert!(host.state.get_host_response().is_none());
+        assert!(host.state.get_guest_request().is_none());
+    }
+
+    #[test]
+    fn scrubbing_covers_every_call_path_and_resets_once() {
+        struct Counting(Box<dyn WebAssemblyEngineProvider>, std::rc::Rc<Cell<u32>>);
+        impl WebAssemblyEngineProvider for Counting {
+            fn init(&mut self, host: Arc<ModuleState>) -> std::result::Result<(), Box<dyn Error>> {
+                self.0.init(host)
+            }
+            fn call(&mut self, op: i32, msg: i32) -> std::result::Result<i32, Box<dyn Error>> {
+                self.0.call(op, msg)
+            }
+            fn replace(&mut self, bytes: &[u8]) -> std::result::Result<(), Box<dyn Error>> {
+                self.0.replace(bytes)
+            }
+            fn reset(&mut self) -> std::result::Result<(), Box<dyn Error>> {
+                self.1.set(self.1.get() + 1);
+                self.0.reset()
+            }
+        }
+        let resets = std::rc::Rc::new(Cell::new(0));
+        let engine = Counting(MockEngine::boxed(deferring_guest), resets.clone());
+        let host = WapcHostBuilder::new()
+            .scrub_memory(MemoryScrub::Reinstantiate)
+            .recycle_after_calls(1)
+            .build(Box::new(engine))
+            .unwrap();
+
+        let outcome = host.call_deferrable("work", b"secret").unwrap();
+        assert!(matches!(outcome, deferred::CallOutcome::Deferred(_)));
+        assert!(host.state.get_guest_request().is_none());
+        assert_eq!(resets.get(), 1);
+
+        // A call that is also due for recycling resets the guest only once
+        host.call("work", b"secret").unwrap_err();
+        assert_eq!(resets.get(), 2);
+    }
+
+    #[test]
+    fn debug_state_is_readable_mid_call() {
+        let (entered_tx, entered_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let (entered_tx, release_rx) = (Mutex::new(entered_tx), Mutex::new(release_rx));
+        let host = WapcHostBuilder::new()
+            .host_callback(move |_, _, _, _, _| {
+                entered_tx.lock().unwrap().send(()).unwrap();
+                release_rx.lock().unwrap().recv().unwrap();
+                Ok(vec![])
+            })
+            .build(MockEngine::boxed(relaying_guest))
+            .unwrap();
+        let probe = host.debug_probe();
+        let watcher = std::thread::spawn(move || {
+            entered_rx.recv().unwrap();
+            let state = probe.snapshot();
+            release_tx.send(()).unwrap();
+            state
+        });
+        host.call("stuck", b"").unwrap();
+        let during = watcher.join().unwrap();
+        assert_eq!(during.operation.as_deref(), Some("stuck"));
+        let host_call = during.last_host_call.unwrap();
+        assert!(host_call.in_flight && host_call.namespace == "test");
+
+        let after = host.debug_state();
+        assert!(after.operation.is_none() && !after.last_host_call.unwrap().in_flight);
+        assert_eq!((after.calls, after.host_calls), (1, 1));
+    }
+
+    #[test]
+    fn traps_write_coredumps_and_carry_backtraces() {
+        struct Trapping;
+        impl WebAssemblyEngineProvider for Trapping {
+            fn init(&mut self, host: Arc<ModuleState>) -> std::result::Result<(), Box<dyn Error>> {
+                assert!(host.engine_settings().coredump_on_trap);
+                Ok(())
+            }
+            fn call(&mut self, _: i32, _: i32) -> std::result::Result<i32, Box<dyn Error>> {
+                Err("wasm trap: unreachable".into())
+            }
+            fn replace(&mut self, _: &[u8]) -> std::result::Result<(), Box<dyn Error>> {
+                Ok(())
+            }
+            fn take_coredump(&mut self) -> Option<Vec<u8>> {
+                Some(b"\0asm\x01\0\0\0".to_vec())
+            }
+            fn take_backtrace(&mut self) -> Option<Vec<errors::GuestFrame>> {
+                Some(vec![errors::GuestFrame {
+                    func_index: 7,
+                    function: Some("guest::handle".to_string()),
+                    file: Some("src/lib.rs".to_string()),
+                    line: Some(42),
+                    column: Some(5),
+                }])
+            }
         }
+        let dir = std::env::temp_dir().join(format!("wapc-coredumps-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let host = WapcHostBuilder::new()
+            .coredump_dir(&dir)
+            .build(Box::new(Trapping))
+            .unwrap();
+        let error = host.call("crash", b"").unwrap_err();
+        let frame = &error.backtrace().unwrap()[0];
+        assert_eq!(frame.to_string(), "guest::handle at src/lib.rs:42:5");
+        let path = host.debug_state().last_coredump.unwrap();
+        assert!(path.starts_with(&dir));
+        assert_eq!(std::fs::read(&path).unwrap(), b"\0asm\x01\0\0\0");
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,28 @@
+//! Negotiating the size of guest responses before they are copied out of guest memory.
+//!
+//! A host built with
+//! [WapcHostBuilder::max_response_size](../struct.WapcHostBuilder.html#method.max_response_size)
+//! fails calls whose guest response exceeds the limit with a
+//! [ResponseTooLarge](../errors/enum.ErrorKind.html#variant.ResponseTooLarge) error. Engine
+//! providers check the length a guest passes to `__guest_response` with
+//! [ModuleState::accept_guest_response](../struct.ModuleState.html#method.accept_guest_response)
+//! before copying the response, so an oversized response is never copied at all.
+//!
+//! Guests that can produce large responses learn the limit up front by making a host call to
+//! the `max_response_size` operation in the `wapc:limits` namespace. The host answers it
+//! itself with the limit in bytes as a big-endian u64, or with an empty response when there is
+//! no limit.
+
+/// The namespace of host calls answered by the host's limit negotiation
+pub const LIMITS_NAMESPACE: &str = "wapc:limits";
+
+/// The operation a guest invokes in the `wapc:limits` namespace to read the largest response
+/// the host accepts
+pub const MAX_RESPONSE_SIZE_OPERATION: &str = "max_response_size";
+
+/// Encodes a limit as the response to a `max_response_size` host call
+pub(crate) fn encode(limit: Option<usize>) -> Vec<u8> {
+    limit
+        .map(|l| (l as u64).to_be_bytes().to_vec())
+        .unwrap_or_default()
+}
@@ -0,0 +1,67 @@
+//! Auxiliary library modules that a guest imports from, for guests that factor shared code
+//! into a separate WebAssembly module.
+//!
+//! Library modules are registered by name with
+//! [WapcHostBuilder::link_module](../struct.WapcHostBuilder.html#method.link_module) and are
+//! available to the engine provider through
+//! [ModuleState::linked_modules](../struct.ModuleState.html#method.linked_modules). Engine
+//! providers must instantiate them in registration order, defining each instance's exports
+//! under its name, and instantiate the main guest last:
+//!
+//! * a library may import from the libraries registered before it, from the host functions
+//!   linked with [link_host_function](../struct.WapcHostBuilder.html#method.link_host_function)
+//!   and from the waPC imports, but not from the main guest or later libraries
+//! * the main guest may import from every library
+//! * the waPC protocol reads and writes only the `memory` exported by the main guest. A
+//!   library that shares memory with the guest must export its memory for the main guest to
+//!   import and export again; pointers into a library's private memory mean nothing to the host
+//! * resetting or unloading the guest re-instantiates its libraries too, so library state is
+//!   never shared between guest instances
+//!
+//! Replacing the main module keeps its libraries.
+
+use std::sync::Arc;
+
+use crate::errors::{self, ErrorKind};
+use crate::Result;
+
+/// A library module linked into the guest under a name. See the [linking](index.html) module.
+#[derive(Debug, Clone)]
+pub struct LinkedModule {
+    name: String,
+    bytes: Arc<[u8]>,
+}
+
+impl LinkedModule {
+    pub(crate) fn new(name: &str, bytes: &[u8]) -> LinkedModule {
+        LinkedModule {
+            name: name.to_string(),
+            bytes: bytes.into(),
+        }
+    }
+
+    /// The import module name under which the library's exports are defined
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The library's WebAssembly bytes
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Rejects library names that are used twice or that would shadow the waPC imports
+pub(crate) fn check_names(modules: &[LinkedModule]) -> Result<()> {
+    for (i, module) in modules.iter().enumerate() {
+        let reason = if module.name == "wapc" || module.name.is_empty() {
+            format!("{:?} cannot be used as a linked module name", module.name)
+        } else if modules[..i].iter().any(|m| m.name == module.name) {
+            format!("More than one module is linked as {}", module.name)
+        } else {
+            continue;
+        };
+        return Err(errors::new(ErrorKind::WasmMisc(reason)));
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Copying data into guest linear memory, for engine providers that implement the waPC
+//! imports themselves.
+//!
+//! [write_bytes_to_memory](fn.write_bytes_to_memory.html) copies a payload, such as the
+//! operation and message for `__guest_request` or the response for `__host_response`, to the
+//! address the guest passed. It copies the whole payload at once and checks the range first,
+//! so a guest passing a bad address gets an error, which the provider turns into a trap,
+//! rather than panicking the host.
+
+use crate::errors::{self, ErrorKind};
+use crate::Result;
+
+/// Copies `data` into `memory`, the guest's linear memory, at `offset`. Fails with
+/// [MemoryOutOfBounds](../errors/enum.ErrorKind.html#variant.MemoryOutOfBounds), without
+/// writing anything, if any of it would land past the end of memory.
+pub fn write_bytes_to_memory(memory: &mut [u8], offset: u32, data: &[u8]) -> Result<()> {
+    let start = offset as usize;
+    let target = start
+        .checked_add(data.len())
+        .and_then(|end| memory.get_mut(start..end));
+    match target {
+        Some(target) => {
+            target.copy_from_slice(data);
+            Ok(())
+        }
+        None => Err(errors::new(ErrorKind::MemoryOutOfBounds {
+            offset: offset.into(),
+            len: data.len() as u64,
+            size: memory.len() as u64,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_are_bounds_checked() {
+        let mut memory = vec![0; 16];
+        write_bytes_to_memory(&mut memory, 12, b"abcd").unwrap();
+        assert_eq!(&memory[12..], b"abcd");
+
+        for (offset, len) in [(13, 4), (16, 1), (u32::MAX, 2)] {
+            let err = write_bytes_to_memory(&mut memory, offset, &vec![1; len]).unwrap_err();
+            assert!(matches!(err.kind(), ErrorKind::MemoryOutOfBounds { size: 16, .. }));
+        }
+        assert_eq!(&memory[12..], b"abcd");
+    }
+}
@@ -0,0 +1,203 @@
+//! Metadata that guests ship inside their module as custom sections, read with
+//! [extract](fn.extract.html) or [WapcHost::metadata](../struct.WapcHost.html#method.metadata).
+//!
+//! | Section          | Contents                                                    |
+//! |------------------|-------------------------------------------------------------|
+//! | `wapc_claims`    | The module's signed claims token, as UTF-8                  |
+//! | `wapc_interface` | An [InterfaceDescriptor](struct.InterfaceDescriptor.html) as JSON |
+//! | `wapc_build`     | A [BuildInfo](struct.BuildInfo.html) as JSON                |
+//!
+//! Every section is optional, and sections with other names are listed but not decoded.
+//!
+//! Guests that cannot add custom sections may instead answer the `__wapc_describe` operation
+//! with their interface descriptor. The host uses the descriptor, from either source, to check
+//! that [call_serde](../struct.WapcHost.html#method.call_serde) encodes each operation's
+//! payload and response the way the guest expects, catching encoding drift between host and
+//! guest releases.
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, ErrorKind};
+use crate::Result;
+
+/// The custom section holding the module's claims token
+pub const CLAIMS_SECTION: &str = "wapc_claims";
+
+/// The custom section holding the module's interface descriptor
+pub const INTERFACE_SECTION: &str = "wapc_interface";
+
+/// The custom section holding information about how the module was built
+pub const BUILD_INFO_SECTION: &str = "wapc_build";
+
+/// The operation a guest may answer with its interface descriptor as JSON
+pub const DESCRIBE_OPERATION: &str = "__wapc_describe";
+
+/// The encoding name used by [call_serde](../struct.WapcHost.html#method.call_serde)
+pub const MSGPACK_ENCODING: &str = "msgpack";
+
+/// The metadata found in a module's custom sections
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleMetadata {
+    pub claims: Option<String>,
+    pub interface: Option<InterfaceDescriptor>,
+    pub build_info: Option<BuildInfo>,
+    /// The names of every custom section in the module, in order
+    pub custom_sections: Vec<String>,
+}
+
+/// Describes the operations a module exports
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InterfaceDescriptor {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub operations: Vec<OperationDescriptor>,
+}
+
+/// Describes a single operation a module exports
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OperationDescriptor {
+    pub name: String,
+    /// The encoding the operation expects its payload in, such as `json` or `msgpack`
+    pub input: Option<String>,
+    /// The encoding of the operation's response
+    pub output: Option<String>,
+}
+
+/// Describes how a module was built
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildInfo {
+    pub language: Option<String>,
+    pub toolchain: Option<String>,
+    pub version: Option<String>,
+    pub commit: Option<String>,
+}
+
+impl InterfaceDescriptor {
+    /// Returns the descriptor of the named operation, if the interface lists it
+    pub fn operation(&self, name: &str) -> Option<&OperationDescriptor> {
+        self.operations.iter().find(|op| op.name == name)
+    }
+}
+
+/// Reads the well-known custom sections of a WebAssembly module. Returns an error if the bytes
+/// are not a module or a well-known section cannot be decoded.
+pub fn extract(bytes: &[u8]) -> Result<ModuleMetadata> {
+    let mut metadata = ModuleMetadata::default();
+    for (name, data) in custom_sections(bytes)? {
+        match name {
+            CLAIMS_SECTION => {
+                metadata.claims = Some(
+                    String::from_utf8(data.to_vec())
+                        .map_err(|_| malformed("The claims section is not valid UTF-8"))?,
+                )
+            }
+            INTERFACE_SECTION => metadata.interface = Some(decode(name, data)?),
+            BUILD_INFO_SECTION => metadata.build_info = Some(decode(name, data)?),
+            _ => {}
+        }
+        metadata.custom_sections.push(name.to_string());
+    }
+    Ok(metadata)
+}
+
+/// Returns the name and contents of each custom section in a module, in order
+fn custom_sections(bytes: &[u8]) -> Result<Vec<(&str, &[u8])>> {
+    if bytes.len() < 8 || bytes[..4] != *b"\0asm" || bytes[4..8] != [1, 0, 0, 0] {
+        return Err(malformed("Not a WebAssembly module"));
+    }
+    let mut buf = &bytes[8..];
+    let mut sections = Vec::new();
+    while let Some((&id, rest)) = buf.split_first() {
+        buf = rest;
+        let len = read_u32(&mut buf)? as usize;
+        if buf.len() < len {
+            return Err(malformed("Truncated section"));
+        }
+        let (mut section, rest) = buf.split_at(len);
+        buf = rest;
+        if id == 0 {
+            let name_len = read_u32(&mut section)? as usize;
+            if section.len() < name_len {
+                return Err(malformed("Truncated custom section name"));
+            }
+            let (name, data) = section.split_at(name_len);
+            let name = std::str::from_utf8(name)
+                .map_err(|_| malformed("Custom section name is not valid UTF-8"))?;
+            sections.push((name, data));
+        }
+    }
+    Ok(sections)
+}
+
+fn read_u32(buf: &mut &[u8]) -> Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or_else(|| malformed("Truncated section length"))?;
+        *buf = rest;
+        value |= u32::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(malformed("Section length is too long"))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(name: &str, data: &[u8]) -> Result<T> {
+    serde_json::from_slice(data).map_err(|e| {
+        errors::new(ErrorKind::Serialization(format!(
+            "Unable to decode the {} section: {}",
+            name, e
+        )))
+    })
+}
+
+fn malformed(reason: &str) -> errors::Error {
+    errors::new(ErrorKind::WasmMisc(reason.to_string()))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Builds an empty module with the given custom sections
+    pub(crate) fn module_with_sections(sections: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        for (name, data) in sections {
+            let len = 1 + name.len() + data.len();
+            assert!(len < 128 && name.len() < 128);
+            module.extend_from_slice(&[0, len as u8, name.len() as u8]);
+            module.extend_from_slice(name.as_bytes());
+            module.extend_from_slice(data);
+        }
+        module
+    }
+
+    #[test]
+    fn reads_well_known_sections() {
+        let module = module_with_sections(&[
+            ("producers", b"\0"),
+            (CLAIMS_SECTION, b"token"),
+            (INTERFACE_SECTION, br#"{"version":"1.2.0","operations":[{"name":"echo"}]}"#),
+        ]);
+        let metadata = extract(&module).unwrap();
+        assert_eq!(metadata.claims.as_deref(), Some("token"));
+        let interface = metadata.interface.unwrap();
+        assert_eq!(interface.version.as_deref(), Some("1.2.0"));
+        assert!(interface.operation("echo").is_some());
+        assert_eq!(metadata.build_info, None);
+        assert_eq!(metadata.custom_sections, vec!["producers", CLAIMS_SECTION, INTERFACE_SECTION]);
+    }
+
+    #[test]
+    fn malformed_modules_are_rejected() {
+        assert!(extract(b"not wasm").is_err());
+        let module = module_with_sections(&[(BUILD_INFO_SECTION, b"{")]);
+        assert!(extract(&module).is_err());
+        assert!(extract(&module[..module.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Interceptors that run around every guest call made through a `WapcHost`
+
+use crate::Result;
+
+/// An interceptor that can inspect and modify the operation and payload of every guest call
+/// before it is made, and the response or error afterward. Middleware is registered with
+/// [WapcHostBuilder::middleware](../struct.WapcHostBuilder.html#method.middleware) and is useful
+/// for cross-cutting concerns like compression, encryption, and audit logging that should not
+/// require changes to the guest.
+///
+/// `before_call` hooks run in registration order and `after_call` hooks run in reverse
+/// registration order, so the first middleware registered is the outermost layer.
+pub trait CallMiddleware: Send + Sync {
+    /// Invoked before the guest call. The operation and payload may be rewritten. Returning an
+    /// error aborts the call; in that case only the middleware whose `before_call` already
+    /// succeeded will see the error in `after_call`.
+    fn before_call(
+        &self,
+        _module_id: u64,
+        _operation: &mut String,
+        _payload: &mut Vec<u8>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Invoked after `before_call` succeeds. Returning a result answers the call without
+    /// invoking the guest or any middleware registered after this one; `after_call` still runs
+    /// for this middleware and those registered before it.
+    fn short_circuit(
+        &self,
+        _module_id: u64,
+        _operation: &str,
+        _payload: &[u8],
+    ) -> Option<Result<Vec<u8>>> {
+        None
+    }
+
+    /// Invoked after the guest call with the result, which may be rewritten, and the operation
+    /// and payload as this middleware's `short_circuit` saw them
+    fn after_call(
+        &self,
+        _module_id: u64,
+        _operation: &str,
+        _payload: &[u8],
+        _result: &mut Result<Vec<u8>>,
+    ) {
+    }
+}
@@ -0,0 +1,100 @@
+//! A programmable stand-in for a waPC host, for unit testing code that embeds waPC
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::errors::{self, ErrorKind};
+use crate::{Result, WapcCaller};
+
+enum Reply {
+    Response(Vec<u8>),
+    Failure(String),
+}
+
+/// A [WapcCaller](../trait.WapcCaller.html) that returns canned replies keyed by operation
+/// name and records every call it receives
+///
+/// ```
+/// use wapc::WapcCaller;
+/// use wapc::mock::MockWapcHost;
+///
+/// let host = MockWapcHost::new(1)
+///     .respond("wapc:sample!Hello", b"hello world!")
+///     .fail("wapc:sample!Explode", "boom");
+///
+/// assert_eq!(host.call("wapc:sample!Hello", b"hi").unwrap(), b"hello world!");
+/// assert!(host.call("wapc:sample!Explode", b"").is_err());
+/// assert_eq!(host.calls().len(), 2);
+/// ```
+pub struct MockWapcHost {
+    id: u64,
+    replies: HashMap<String, Reply>,
+    calls: Mutex<Vec<(String, Vec<u8>)>>,
+    replacements: Mutex<Vec<Vec<u8>>>,
+}
+
+impl MockWapcHost {
+    /// Creates a mock host with the given module ID and no canned replies. Operations without
+    /// a canned reply fail with a guest call failure.
+    pub fn new(id: u64) -> MockWapcHost {
+        MockWapcHost {
+            id,
+            replies: HashMap::new(),
+            calls: Mutex::new(Vec::new()),
+            replacements: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Responds to every call of the given operation with the given payload
+    pub fn respond(mut self, op: &str, response: &[u8]) -> Self {
+        self.replies
+            .insert(op.to_string(), Reply::Response(response.to_vec()));
+        self
+    }
+
+    /// Fails every call of the given operation with a guest call failure carrying the given
+    /// message
+    pub fn fail(mut self, op: &str, message: &str) -> Self {
+        self.replies
+            .insert(op.to_string(), Reply::Failure(message.to_string()));
+        self
+    }
+
+    /// Returns the operation and payload of every call received so far, in order
+    pub fn calls(&self) -> Vec<(String, Vec<u8>)> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Returns the module bytes of every `replace_module` call received so far, in order
+    pub fn replacements(&self) -> Vec<Vec<u8>> {
+        self.replacements.lock().unwrap().clone()
+    }
+}
+
+impl WapcCaller for MockWapcHost {
+    fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((op.to_string(), payload.to_vec()));
+        match self.replies.get(op) {
+            Some(Reply::Response(r)) => Ok(r.clone()),
+            Some(Reply::Failure(m)) => Err(errors::new(ErrorKind::GuestCallFailure(m.clone()))
+                .with_module(self.id)),
+            None => Err(errors::new(ErrorKind::GuestCallFailure(format!(
+                "No canned reply for operation '{}'",
+                op
+            )))
+            .with_module(self.id)),
+        }
+    }
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn replace_module(&self, module: &[u8]) -> Result<()> {
+        self.replacements.lock().unwrap().push(module.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! An extension point for third-party crates (capability providers, policy engines,
+//! observability vendors) to hook into the runtime without changes to this crate
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::host_function::HostFunction;
+use crate::middleware::CallMiddleware;
+use crate::HostCallContext;
+
+/// A bundle of hooks into the lifecycle of every host it is registered with. Register plugins
+/// with [WapcHostBuilder::plugin](../struct.WapcHostBuilder.html#method.plugin). Every hook has
+/// a no-op default, so plugins only implement what they need.
+pub trait RuntimePlugin: Send + Sync {
+    /// A short name identifying the plugin in logs
+    fn name(&self) -> &str;
+
+    /// Middleware this plugin contributes to every guest call. Plugin middleware runs inside
+    /// any middleware registered directly on the builder before the plugin.
+    fn middleware(&self) -> Option<Arc<dyn CallMiddleware>> {
+        None
+    }
+
+    /// Additional host functions this plugin links into the guest's imports
+    fn host_functions(&self) -> Vec<HostFunction> {
+        Vec::new()
+    }
+
+    /// Called once the host has been created and the guest module initialized
+    fn on_host_created(&self, _module_id: u64) {}
+
+    /// Called when the host is dropped
+    fn on_host_dropped(&self, _module_id: u64) {}
+
+    /// Called after every guest call completes, with the error message if it failed
+    fn on_guest_call(
+        &self,
+        _module_id: u64,
+        _operation: &str,
+        _error: Option<&str>,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Called after every host call made by the guest completes, with the error message if it
+    /// failed
+    fn on_host_call(
+        &self,
+        _ctx: &HostCallContext,
+        _operation: &str,
+        _error: Option<&str>,
+        _elapsed: Duration,
+    ) {
+    }
+}
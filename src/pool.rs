@@ -0,0 +1,849 @@
+//! A fixed-size pool of hosts for serving calls from many threads.
+//!
+//! A `WapcHost` cannot be moved between threads, so each host in a
+//! [WapcHostPool](struct.WapcHostPool.html) lives on its own worker thread, created there by a
+//! factory. All the hosts are instantiated at once, one per worker, so a pool of 64 hosts
+//! starts in roughly the time it takes to instantiate one. Engine providers that can compile a
+//! module once and instantiate it many times should share the compiled module between the
+//! factory's invocations.
+//!
+//! Calls made with [call](struct.WapcHostPool.html#method.call) go to the least loaded host,
+//! judged by its [load](../struct.WapcHost.html#method.load), which for pooled hosts includes
+//! the calls queued for it. A caller that needs several calls to reach the same instance
+//! [checks out](struct.WapcHostPool.html#method.checkout) a host, which the pool routes no
+//! other calls to until it is checked back in.
+//!
+//! A pool created [with eviction](struct.WapcHostPool.html#method.with_eviction)
+//! [unloads](../struct.WapcHost.html#method.unload) the guest instances of idle hosts according
+//! to an [EvictionPolicy](struct.EvictionPolicy.html), releasing their memory until their next
+//! call instantiates them again. Checked out hosts are never evicted.
+//!
+//! Operations can be placed in
+//! [concurrency groups](struct.WapcHostPool.html#method.limit_concurrency) that bound how many
+//! calls to them run at once across the whole pool, e.g. a single `ReindexAll` at a time next
+//! to any number of `Lookup` calls. Calls beyond the limit wait for a running one to finish.
+//!
+//! A pool with [fair scheduling](struct.WapcHostPool.html#method.fair_scheduling) runs at most
+//! a fixed number of guest calls at once, handing the execution slots out in turn to the
+//! tenants named with [call_as](struct.WapcHostPool.html#method.call_as). Hosts built with a
+//! [time slice](../struct.WapcHostBuilder.html#method.time_slice) give up their slot whenever
+//! their slice ends and another tenant is waiting, so one tenant's long calls cannot keep the
+//! others from running. The pool should have more hosts than slots, so that a waiting tenant
+//! has a host to run on.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::errors::{self, ErrorKind};
+use crate::{BusyGuard, ModuleState, Result, WapcCaller, WapcHost};
+
+type Job = Box<dyn FnOnce(&WapcHost) + Send>;
+
+type Veto = dyn Fn(u64) -> bool + Send + Sync;
+
+/// When a [WapcHostPool](struct.WapcHostPool.html) unloads the guest instances of its hosts.
+/// The default policy never does.
+#[derive(Clone, Default)]
+pub struct EvictionPolicy {
+    max_idle: Option<Duration>,
+    max_resident: Option<usize>,
+    veto: Option<Arc<Veto>>,
+}
+
+impl EvictionPolicy {
+    pub fn new() -> EvictionPolicy {
+        EvictionPolicy::default()
+    }
+
+    /// Unloads a host's guest once it has received no calls for `idle`
+    pub fn max_idle(mut self, idle: Duration) -> Self {
+        self.max_idle = Some(idle);
+        self
+    }
+
+    /// Keeps at most `instances` guests instantiated, unloading the least recently used idle
+    /// host when a call leaves more than that
+    pub fn max_resident(mut self, instances: usize) -> Self {
+        self.max_resident = Some(instances);
+        self
+    }
+
+    /// Consults `veto` with a host's module ID before evicting it; returning true keeps the
+    /// host's guest instantiated, e.g. for modules pinned by the embedder
+    pub fn veto(mut self, veto: impl Fn(u64) -> bool + Send + Sync + 'static) -> Self {
+        self.veto = Some(Arc::new(veto));
+        self
+    }
+}
+
+/// What the pool knows about the guest instance of each host, shared with its worker thread
+struct Residency {
+    resident: AtomicBool,
+    checked_out: AtomicBool,
+    last_used: Mutex<Instant>,
+    policy: Arc<EvictionPolicy>,
+}
+
+impl Residency {
+    fn touch(&self, host: &WapcHost) {
+        *self.last_used.lock().unwrap() = Instant::now();
+        self.resident.store(host.is_initialized(), Ordering::SeqCst);
+    }
+
+    /// Unloads the host's guest unless it is checked out or vetoed. Runs on the worker thread.
+    fn evict(&self, host: &WapcHost) {
+        let vetoed = self.policy.veto.as_ref().is_some_and(|veto| veto(host.id()));
+        if !host.is_initialized() || self.checked_out.load(Ordering::SeqCst) || vetoed {
+            return;
+        }
+        match host.unload() {
+            Ok(_) => self.resident.store(false, Ordering::SeqCst),
+            Err(e) => debug!("Could not evict guest module {}: {}", host.id(), e),
+        }
+    }
+}
+
+/// A bound on the number of calls to a group of operations running at once
+struct ConcurrencyGroup {
+    limit: usize,
+    running: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl ConcurrencyGroup {
+    fn acquire(&self) -> Permit<'_> {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.limit {
+            running = self.finished.wait(running).unwrap();
+        }
+        *running += 1;
+        Permit { group: self }
+    }
+}
+
+/// A call's place within its concurrency group, given up when the call completes
+struct Permit<'a> {
+    group: &'a ConcurrencyGroup,
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        *self.group.running.lock().unwrap() -= 1;
+        self.group.finished.notify_one();
+    }
+}
+
+/// Execution slots shared by a fairly scheduled pool, granted to tenants in turn
+struct FairScheduler {
+    slots: usize,
+    state: Mutex<FairState>,
+    turn: Condvar,
+}
+
+#[derive(Default)]
+struct FairState {
+    running: usize,
+    /// Tenants with waiting calls in the order their turns come up, each with the tickets of
+    /// its waiting calls in arrival order
+    waiting: VecDeque<(String, VecDeque<u64>)>,
+    next_ticket: u64,
+}
+
+impl FairScheduler {
+    /// Waits for `tenant`'s turn at a free slot, and takes it until the returned guard drops
+    fn acquire(&self, tenant: &str) -> Slot<'_> {
+        self.take(tenant);
+        Slot { scheduler: self }
+    }
+
+    /// Waits for `tenant`'s turn at a free slot, and takes it
+    fn take(&self, tenant: &str) {
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        match state.waiting.iter_mut().find(|(t, _)| t == tenant) {
+            Some((_, tickets)) => tickets.push_back(ticket),
+            None => state
+                .waiting
+                .push_back((tenant.to_string(), VecDeque::from(vec![ticket]))),
+        }
+        loop {
+            let first = state.waiting.front().and_then(|(_, tickets)| tickets.front());
+            if state.running < self.slots && first == Some(&ticket) {
+                break;
+            }
+            state = self.turn.wait(state).unwrap();
+        }
+        let (tenant, mut tickets) = state.waiting.pop_front().unwrap();
+        tickets.pop_front();
+        // The tenant's next call waits for every other waiting tenant to have a turn
+        if !tickets.is_empty() {
+            state.waiting.push_back((tenant, tickets));
+        }
+        state.running += 1;
+        self.turn.notify_all();
+    }
+
+    fn release(&self) {
+        self.state.lock().unwrap().running -= 1;
+        self.turn.notify_all();
+    }
+
+    /// Ends the time slice of a call by `tenant`, giving its slot to a waiting call, if any,
+    /// and waiting for another turn
+    fn yield_slot(&self, tenant: &str) {
+        if self.state.lock().unwrap().waiting.is_empty() {
+            return;
+        }
+        self.release();
+        self.take(tenant);
+    }
+}
+
+/// A call's execution slot in a fairly scheduled pool, given up when the call completes
+struct Slot<'a> {
+    scheduler: &'a FairScheduler,
+}
+
+impl<'a> Drop for Slot<'a> {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+struct Worker {
+    jobs: Sender<Job>,
+    state: Arc<ModuleState>,
+    residency: Arc<Residency>,
+    /// The tenant whose call the worker is running, for fair scheduling
+    tenant: Arc<Mutex<String>>,
+    thread: JoinHandle<()>,
+}
+
+/// A fixed-size pool of hosts, each owned by a worker thread
+pub struct WapcHostPool {
+    workers: Vec<Worker>,
+    checked_out: Mutex<Vec<bool>>,
+    checked_in: Condvar,
+    policy: Arc<EvictionPolicy>,
+    /// The concurrency group of each operation that belongs to one
+    groups: HashMap<String, Arc<ConcurrencyGroup>>,
+    scheduler: Option<Arc<FairScheduler>>,
+}
+
+/// A host checked out of a [WapcHostPool](struct.WapcHostPool.html), which receives no calls
+/// other than those made through this guard until it is dropped
+pub struct PooledHost<'a> {
+    pool: &'a WapcHostPool,
+    index: usize,
+}
+
+impl WapcHostPool {
+    /// Creates a pool of `size` hosts, calling `factory` with each host's index on that host's
+    /// worker thread. The hosts are instantiated in parallel. Returns the first error produced
+    /// by the factory, if any.
+    pub fn new(
+        size: usize,
+        factory: impl Fn(usize) -> Result<WapcHost> + Send + Sync + 'static,
+    ) -> Result<WapcHostPool> {
+        WapcHostPool::with_eviction(size, EvictionPolicy::default(), factory)
+    }
+
+    /// Creates a pool like [new](#method.new) whose idle hosts have their guest instances
+    /// unloaded according to `policy`. Engine providers that cannot unload a guest leave it
+    /// instantiated.
+    pub fn with_eviction(
+        size: usize,
+        policy: EvictionPolicy,
+        factory: impl Fn(usize) -> Result<WapcHost> + Send + Sync + 'static,
+    ) -> Result<WapcHostPool> {
+        let factory = Arc::new(factory);
+        let policy = Arc::new(policy);
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let threads: Vec<_> = (0..size.max(1))
+            .map(|index| {
+                let (jobs, rx) = mpsc::channel::<Job>();
+                let factory = factory.clone();
+                let ready = ready_tx.clone();
+                let residency = Arc::new(Residency {
+                    resident: AtomicBool::new(false),
+                    checked_out: AtomicBool::new(false),
+                    last_used: Mutex::new(Instant::now()),
+                    policy: policy.clone(),
+                });
+                let worker_residency = residency.clone();
+                let thread = thread::spawn(move || {
+                    let residency = worker_residency;
+                    let host = match factory(index) {
+                        Ok(host) => host,
+                        Err(e) => {
+                            let _ = ready.send((index, Err(e)));
+                            return;
+                        }
+                    };
+                    residency.touch(&host);
+                    let _ = ready.send((index, Ok(host.state.clone())));
+                    loop {
+                        let job = match residency.policy.max_idle {
+                            Some(idle) => match rx.recv_timeout(idle) {
+                                Ok(job) => job,
+                                Err(RecvTimeoutError::Timeout) => {
+                                    residency.evict(&host);
+                                    continue;
+                                }
+                                Err(RecvTimeoutError::Disconnected) => break,
+                            },
+                            None => match rx.recv() {
+                                Ok(job) => job,
+                                Err(_) => break,
+                            },
+                        };
+                        host.state.queued.fetch_sub(1, Ordering::SeqCst);
+                        job(&host);
+                        residency.touch(&host);
+                    }
+                });
+                (jobs, residency, thread)
+            })
+            .collect();
+
+        let mut states = vec![None; threads.len()];
+        let mut failure = None;
+        for _ in 0..threads.len() {
+            match ready_rx.recv() {
+                Ok((index, Ok(state))) => states[index] = Some(state),
+                Ok((_, Err(e))) => failure = failure.or(Some(e)),
+                Err(_) => break,
+            }
+        }
+        let failure = failure.or_else(|| {
+            if states.iter().any(Option::is_none) {
+                Some(worker_gone())
+            } else {
+                None
+            }
+        });
+        if let Some(e) = failure {
+            for (jobs, _, thread) in threads {
+                drop(jobs);
+                let _ = thread.join();
+            }
+            return Err(e);
+        }
+
+        let workers: Vec<Worker> = threads
+            .into_iter()
+            .zip(states)
+            .map(|((jobs, residency, thread), state)| Worker {
+                jobs,
+                state: state.unwrap(),
+                residency,
+                tenant: Arc::new(Mutex::new(String::new())),
+                thread,
+            })
+            .collect();
+        Ok(WapcHostPool {
+            checked_out: Mutex::new(vec![false; workers.len()]),
+            checked_in: Condvar::new(),
+            workers,
+            policy,
+            groups: HashMap::new(),
+            scheduler: None,
+        })
+    }
+
+    /// Places `operations` in a concurrency group allowing at most `limit` calls to them to run
+    /// at once across the pool, including calls made through checked out hosts. Calls beyond the
+    /// limit wait for one to finish. An operation belongs to at most one group; adding it to
+    /// another moves it. Operations in no group are not limited.
+    pub fn limit_concurrency(mut self, limit: usize, operations: &[&str]) -> Self {
+        let group = Arc::new(ConcurrencyGroup {
+            limit: limit.max(1),
+            running: Mutex::new(0),
+            finished: Condvar::new(),
+        });
+        for op in operations {
+            self.groups.insert(op.to_string(), group.clone());
+        }
+        self
+    }
+
+    /// Runs at most `slots` guest calls at once, granting the slots to tenants in turn. Calls
+    /// made without naming a tenant share one. Guests whose hosts were built with a
+    /// [time slice](../struct.WapcHostBuilder.html#method.time_slice) hand their slot to a waiting
+    /// tenant whenever their slice ends, and carry on when their turn comes round again.
+    pub fn fair_scheduling(mut self, slots: usize) -> Self {
+        let scheduler = Arc::new(FairScheduler {
+            slots: slots.max(1),
+            state: Mutex::new(FairState::default()),
+            turn: Condvar::new(),
+        });
+        for worker in self.workers.iter() {
+            let (scheduler, tenant) = (scheduler.clone(), worker.tenant.clone());
+            worker.state.set_time_slicer(Arc::new(move || {
+                let tenant = tenant.lock().unwrap().clone();
+                scheduler.yield_slot(&tenant);
+            }));
+        }
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Waits until a call to `op` may run within its concurrency group, if it has one
+    fn permit(&self, op: &str) -> Option<Permit<'_>> {
+        self.groups.get(op).map(|group| group.acquire())
+    }
+
+    /// The number of hosts in the pool
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// The number of hosts whose guest is currently instantiated
+    pub fn resident(&self) -> usize {
+        self.workers
+            .iter()
+            .filter(|w| w.residency.resident.load(Ordering::SeqCst))
+            .count()
+    }
+
+    /// Invokes an operation on the least loaded host that is not checked out, waiting for a
+    /// host to be checked in if all of them are
+    pub fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        self.call_as("", op, payload)
+    }
+
+    /// Invokes an operation like [call](#method.call) on behalf of `tenant`, whose turn it must
+    /// be to run when the pool is [fairly scheduled](#method.fair_scheduling)
+    pub fn call_as(&self, tenant: &str, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        let _permit = self.permit(op);
+        self.dispatch(self.pick(false), tenant, op, payload)
+    }
+
+    /// Runs a call on the host at `index`, in a scheduler slot if the pool has a scheduler
+    fn dispatch(&self, index: usize, tenant: &str, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        let (op, payload) = (op.to_string(), payload.to_vec());
+        let scheduler = self.scheduler.clone();
+        let (tenant, current) = (tenant.to_string(), self.workers[index].tenant.clone());
+        self.run(index, move |host| match scheduler {
+            Some(scheduler) => {
+                // The worker is busy while it waits for a slot, so that calls are not routed
+                // to it as if it were idle
+                let _busy = BusyGuard::new(&host.state.busy);
+                let _slot = scheduler.acquire(&tenant);
+                *current.lock().unwrap() = tenant;
+                host.call(&op, &payload)
+            }
+            None => host.call(&op, &payload),
+        })?
+    }
+
+    /// Runs `f` with the least loaded host that is not checked out, on that host's thread,
+    /// waiting for a host to be checked in if all of them are
+    pub fn execute<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WapcHost) -> R + Send + 'static,
+    ) -> Result<R> {
+        self.run(self.pick(false), f)
+    }
+
+    /// Replaces the guest module of every host in the pool, including checked out ones, once
+    /// the calls already queued for each have run. Returns the first error, after trying every
+    /// host.
+    pub fn replace_module(&self, module: &[u8]) -> Result<()> {
+        let module: Arc<[u8]> = module.into();
+        let mut result = Ok(());
+        for index in 0..self.workers.len() {
+            let module = module.clone();
+            let replaced = self
+                .run(index, move |host| host.replace_module(&module))
+                .and_then(|r| r);
+            result = result.and(replaced);
+        }
+        result
+    }
+
+    /// Checks out the least loaded host, waiting for one to be checked in if all of them are
+    /// checked out
+    pub fn checkout(&self) -> PooledHost<'_> {
+        PooledHost {
+            pool: self,
+            index: self.pick(true),
+        }
+    }
+
+    /// Checks out the least loaded host, or returns `None` if all of them are checked out
+    pub fn try_checkout(&self) -> Option<PooledHost<'_>> {
+        let mut checked_out = self.checked_out.lock().unwrap();
+        let index = self.least_loaded(&checked_out)?;
+        checked_out[index] = true;
+        self.workers[index]
+            .residency
+            .checked_out
+            .store(true, Ordering::SeqCst);
+        Some(PooledHost { pool: self, index })
+    }
+
+    fn pick(&self, checkout: bool) -> usize {
+        let mut checked_out = self.checked_out.lock().unwrap();
+        loop {
+            if let Some(index) = self.least_loaded(&checked_out) {
+                if checkout {
+                    checked_out[index] = true;
+                    self.workers[index]
+                        .residency
+                        .checked_out
+                        .store(true, Ordering::SeqCst);
+                }
+                return index;
+            }
+            checked_out = self.checked_in.wait(checked_out).unwrap();
+        }
+    }
+
+    fn least_loaded(&self, checked_out: &[bool]) -> Option<usize> {
+        self.workers
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !checked_out[*index])
+            .min_by_key(|(_, worker)| {
+                let load = worker.state.load();
+                (load.queue_depth, load.busy)
+            })
+            .map(|(index, _)| index)
+    }
+
+    fn run<R: Send + 'static>(
+        &self,
+        index: usize,
+        f: impl FnOnce(&WapcHost) -> R + Send + 'static,
+    ) -> Result<R> {
+        let worker = &self.workers[index];
+        let (tx, rx) = mpsc::channel();
+        worker.state.queued.fetch_add(1, Ordering::SeqCst);
+        // A panic is caught on the worker so that it keeps serving calls, and is reported to the
+        // caller whose job panicked
+        let job: Job = Box::new(move |host| {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(host)));
+            let _ = tx.send(result.map_err(|panic| crate::panic_message(panic.as_ref())));
+        });
+        if worker.jobs.send(job).is_err() {
+            return Err(worker_gone());
+        }
+        let result = match rx.recv() {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(message)) => Err(errors::new(ErrorKind::GuestCallFailure(format!(
+                "Pool job panicked: {}",
+                message
+            )))
+            .with_module(worker.state.id)),
+            Err(_) => Err(worker_gone()),
+        };
+        self.evict_excess(index);
+        result
+    }
+
+    /// Asks the least recently used idle hosts to unload their guests while more than the
+    /// policy's maximum are resident, sparing the host that was just called
+    fn evict_excess(&self, called: usize) {
+        let max = match self.policy.max_resident {
+            Some(max) => max,
+            None => return,
+        };
+        let mut candidates: Vec<(Instant, usize)> = self
+            .workers
+            .iter()
+            .enumerate()
+            .filter(|(index, w)| {
+                let load = w.state.load();
+                *index != called
+                    && w.residency.resident.load(Ordering::SeqCst)
+                    && !w.residency.checked_out.load(Ordering::SeqCst)
+                    && !load.busy
+                    && load.queue_depth == 0
+            })
+            .map(|(index, w)| (*w.residency.last_used.lock().unwrap(), index))
+            .collect();
+        candidates.sort();
+        let excess = self.resident().saturating_sub(max);
+        for (_, index) in candidates.into_iter().take(excess) {
+            let worker = &self.workers[index];
+            let residency = worker.residency.clone();
+            worker.state.queued.fetch_add(1, Ordering::SeqCst);
+            let _ = worker.jobs.send(Box::new(move |host| residency.evict(host)));
+        }
+    }
+}
+
+impl Drop for WapcHostPool {
+    fn drop(&mut self) {
+        for worker in std::mem::take(&mut self.workers) {
+            // Closing the job channel stops the worker once its queue is drained
+            drop(worker.jobs);
+            let _ = worker.thread.join();
+        }
+    }
+}
+
+impl<'a> PooledHost<'a> {
+    /// Invokes an operation on the checked out host
+    pub fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        let _permit = self.pool.permit(op);
+        self.pool.dispatch(self.index, "", op, payload)
+    }
+
+    /// Runs `f` with the checked out host, on that host's thread
+    pub fn execute<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&WapcHost) -> R + Send + 'static,
+    ) -> Result<R> {
+        self.pool.run(self.index, f)
+    }
+}
+
+impl<'a> WapcCaller for PooledHost<'a> {
+    fn call(&self, op: &str, payload: &[u8]) -> Result<Vec<u8>> {
+        PooledHost::call(self, op, payload)
+    }
+
+    fn id(&self) -> u64 {
+        self.pool.workers[self.index].state.id
+    }
+
+    fn replace_module(&self, module: &[u8]) -> Result<()> {
+        let module = module.to_vec();
+        self.pool
+            .run(self.index, move |host| host.replace_module(&module))?
+    }
+}
+
+impl<'a> Drop for PooledHost<'a> {
+    fn drop(&mut self) {
+        self.pool.checked_out.lock().unwrap()[self.index] = false;
+        self.pool.workers[self.index]
+            .residency
+            .checked_out
+            .store(false, Ordering::SeqCst);
+        self.pool.checked_in.notify_all();
+    }
+}
+
+fn worker_gone() -> errors::Error {
+    errors::new(ErrorKind::GuestCallFailure(
+        "Pool worker is no longer running".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{echo_guest, MockEngine};
+    use crate::WapcHostBuilder;
+
+    fn pool(size: usize) -> WapcHostPool {
+        WapcHostPool::new(size, |_| {
+            WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn calls_are_spread_across_hosts() {
+        let pool = Arc::new(pool(4));
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let pool = pool.clone();
+                thread::spawn(move || pool.call("echo", &[i]).unwrap())
+            })
+            .collect();
+        for (i, t) in threads.into_iter().enumerate() {
+            assert_eq!(t.join().unwrap(), vec![i as u8]);
+        }
+        assert_eq!(pool.size(), 4);
+    }
+
+    #[test]
+    fn checked_out_hosts_are_exclusive() {
+        let pool = pool(2);
+        let first = pool.checkout();
+        let second = pool.checkout();
+        assert_ne!(WapcCaller::id(&first), WapcCaller::id(&second));
+        assert!(pool.try_checkout().is_none());
+        assert_eq!(first.call("echo", b"hi").unwrap(), b"hi");
+        drop(second);
+        assert!(pool.try_checkout().is_some());
+    }
+
+    #[test]
+    fn panicking_jobs_do_not_take_down_their_worker() {
+        let pool = pool(1);
+        let err = pool.execute(|_| panic!("boom")).err().unwrap();
+        assert!(err.to_string().contains("boom"));
+        assert_eq!(pool.call("echo", b"hi").unwrap(), b"hi");
+    }
+
+    #[test]
+    fn concurrency_groups_bound_calls_across_the_pool() {
+        use std::sync::atomic::AtomicUsize;
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (counter, highest) = (running.clone(), peak.clone());
+        let pool = WapcHostPool::new(4, move |_| {
+            let (running, peak) = (counter.clone(), highest.clone());
+            let guest = move |state: &ModuleState| {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+                echo_guest(state)
+            };
+            WapcHost::new(MockEngine::boxed(guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap()
+        .limit_concurrency(1, &["ReindexAll"]);
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| pool.call("ReindexAll", b"").unwrap());
+            }
+            let checked_out = pool.checkout();
+            checked_out.call("ReindexAll", b"").unwrap();
+        });
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn fair_scheduling_lets_waiting_tenants_run_between_time_slices() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let heavy_started = Arc::new(AtomicBool::new(false));
+        let (events, started) = (log.clone(), heavy_started.clone());
+        let pool = WapcHostPool::new(2, move |_| {
+            let (events, started) = (events.clone(), started.clone());
+            let guest = move |state: &ModuleState| {
+                let op = state.get_guest_request().unwrap().operation;
+                if &*op == "heavy" {
+                    started.store(true, Ordering::SeqCst);
+                    for _ in 0..50 {
+                        thread::sleep(Duration::from_millis(2));
+                        state.yield_time_slice();
+                    }
+                }
+                events.lock().unwrap().push(op.to_string());
+                echo_guest(state)
+            };
+            WapcHostBuilder::new()
+                .time_slice(1)
+                .build(MockEngine::boxed(guest))
+        })
+        .unwrap()
+        .fair_scheduling(1);
+
+        thread::scope(|scope| {
+            scope.spawn(|| pool.call_as("batch", "heavy", b"").unwrap());
+            while !heavy_started.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+            pool.call_as("interactive", "light", b"").unwrap();
+        });
+        assert_eq!(*log.lock().unwrap(), vec!["light", "heavy"]);
+    }
+
+    #[test]
+    fn calls_waiting_for_a_fair_share_slot_keep_their_host_busy() {
+        let release = Arc::new(AtomicBool::new(false));
+        let held = release.clone();
+        let pool = WapcHostPool::new(2, move |_| {
+            let held = held.clone();
+            let guest = move |state: &ModuleState| {
+                match &*state.get_guest_request().unwrap().operation {
+                    "hold" => {
+                        while !held.load(Ordering::SeqCst) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+                    "panic" => panic!("guest panicked"),
+                    _ => {}
+                }
+                echo_guest(state)
+            };
+            WapcHost::new(MockEngine::boxed(guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap()
+        .fair_scheduling(1);
+
+        thread::scope(|scope| {
+            scope.spawn(|| pool.call_as("a", "hold", b"").unwrap());
+            assert!(wait_until(|| pool.workers[0].state.load().busy));
+            scope.spawn(|| pool.call_as("b", "echo", b"").unwrap());
+            assert!(wait_until(|| pool.workers[1].state.load().busy));
+            release.store(true, Ordering::SeqCst);
+        });
+
+        // A call that panics gives its slot back, so later calls still get one
+        assert!(pool.call("panic", b"").is_err());
+        assert_eq!(pool.call("echo", b"hi").unwrap(), b"hi");
+    }
+
+    #[test]
+    fn factory_errors_are_returned() {
+        let result = WapcHostPool::new(3, |index| {
+            if index == 1 {
+                Err(errors::new(ErrorKind::WasmMisc("boom".to_string())))
+            } else {
+                WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![]))
+            }
+        });
+        assert!(result.is_err());
+    }
+
+    fn wait_until(mut done: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !done() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        true
+    }
+
+    #[test]
+    fn idle_hosts_are_evicted() {
+        let factory = |_| WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![]));
+        let policy = EvictionPolicy::new().max_idle(Duration::from_millis(20));
+        let pool = WapcHostPool::with_eviction(2, policy, factory).unwrap();
+        assert!(wait_until(|| pool.resident() == 0));
+        assert_eq!(pool.call("echo", b"back").unwrap(), b"back");
+
+        let pinned = WapcHostPool::with_eviction(
+            1,
+            EvictionPolicy::new()
+                .max_idle(Duration::from_millis(5))
+                .veto(|_| true),
+            factory,
+        )
+        .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(pinned.resident(), 1);
+    }
+
+    #[test]
+    fn least_recently_used_hosts_are_evicted_beyond_the_limit() {
+        let policy = EvictionPolicy::new().max_resident(1);
+        let pool = WapcHostPool::with_eviction(2, policy, |_| {
+            WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap();
+        let first = pool.checkout();
+        let second = pool.checkout();
+        first.call("echo", b"").unwrap();
+        drop(first);
+        second.call("echo", b"").unwrap();
+        assert!(wait_until(|| pool.resident() == 1));
+        assert_eq!(second.call("echo", b"still here").unwrap(), b"still here");
+    }
+}
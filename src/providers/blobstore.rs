@@ -0,0 +1,263 @@
+//! A blob storage capability backed by a host directory, answering host calls to the
+//! `wapc:blobstore` namespace. It gives guests structured access to files in cases where raw
+//! WASI file APIs are too permissive.
+//!
+//! Each module is configured with a `root` directory, which must lie within one of the
+//! directories the provider allows; [from_wasi](struct.BlobstoreProvider.html#method.from_wasi)
+//! allows exactly the directories preopened for the module under WASI. Containers are
+//! subdirectories of the root and blobs are files within them. Container and blob names may not
+//! contain path separators or be `.` or `..`, and containers and blobs that are symbolic links
+//! are refused, so guests cannot escape their root.
+//!
+//! | Operation          | Payload frames         | Response                 |
+//! |--------------------|------------------------|--------------------------|
+//! | `create_container` | container              | empty                    |
+//! | `remove_container` | container              | empty                    |
+//! | `list_blobs`       | container              | one frame per blob name  |
+//! | `put_blob`         | container, blob, data  | empty                    |
+//! | `get_blob`         | container, blob        | the blob's contents      |
+//! | `remove_blob`      | container, blob        | empty                    |
+//!
+//! Frames use the encoding of the [batch](../../batch/index.html) module. Operations on a
+//! missing container or blob fail with status [NOT_FOUND](constant.NOT_FOUND.html) (see the
+//! [status](../../status/index.html) module).
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::batch::{read_frame, write_frame};
+use crate::capability::{CapabilityConfig, CapabilityProvider};
+use crate::status::StatusError;
+use crate::{HostCallContext, WasiParams};
+
+/// The namespace of host calls answered by the blobstore provider
+pub const BLOBSTORE_NAMESPACE: &str = "wapc:blobstore";
+
+/// The configuration key naming a module's root directory
+pub const ROOT_CONFIG_KEY: &str = "root";
+
+/// The status code of an operation on a missing container or blob
+pub const NOT_FOUND: i32 = 404;
+
+type BlobResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// The blobstore capability provider. See the [blobstore](index.html) module.
+pub struct BlobstoreProvider {
+    allowed: Vec<PathBuf>,
+    roots: RwLock<HashMap<u64, PathBuf>>,
+}
+
+impl BlobstoreProvider {
+    /// Creates a provider whose modules may use roots within any of the given directories
+    pub fn new(allowed: Vec<PathBuf>) -> BlobstoreProvider {
+        BlobstoreProvider {
+            allowed: allowed
+                .into_iter()
+                .map(|p| p.canonicalize().unwrap_or(p))
+                .collect(),
+            roots: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a provider allowing the host directories preopened by the given WASI parameters
+    pub fn from_wasi(params: &WasiParams) -> BlobstoreProvider {
+        let preopened = params.preopened_dirs.iter().map(PathBuf::from);
+        let mapped = params.map_dirs.iter().map(|(_, host)| PathBuf::from(host));
+        BlobstoreProvider::new(preopened.chain(mapped).collect())
+    }
+
+    fn root(&self, module_id: u64) -> BlobResult<PathBuf> {
+        self.roots
+            .read()
+            .unwrap()
+            .get(&module_id)
+            .cloned()
+            .ok_or_else(|| "Blobstore is not configured for this module".into())
+    }
+}
+
+fn name(frame: &[u8]) -> BlobResult<&str> {
+    let name = std::str::from_utf8(frame).map_err(|_| "Name is not valid UTF-8")?;
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("Invalid container or blob name: {:?}", name).into());
+    }
+    Ok(name)
+}
+
+/// Refuses a container or blob that is a symbolic link, e.g. one planted through WASI access
+/// to the same directory, which could otherwise lead outside the root
+fn no_symlink(path: &Path) -> BlobResult<&Path> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            Err(format!("{} is a symbolic link", path.display()).into())
+        }
+        _ => Ok(path),
+    }
+}
+
+fn not_found(e: io::Error, what: &Path) -> Box<dyn Error + Send + Sync> {
+    if e.kind() == io::ErrorKind::NotFound {
+        Box::new(StatusError::new(NOT_FOUND, format!("Not found: {}", what.display())))
+    } else {
+        Box::new(e)
+    }
+}
+
+impl CapabilityProvider for BlobstoreProvider {
+    fn namespace(&self) -> &str {
+        BLOBSTORE_NAMESPACE
+    }
+
+    fn configure(&self, module_id: u64, config: &CapabilityConfig) -> BlobResult<()> {
+        let root = config
+            .get(ROOT_CONFIG_KEY)
+            .ok_or("Missing 'root' configuration")?;
+        let root = Path::new(root).canonicalize()?;
+        if !self.allowed.iter().any(|a| root.starts_with(a)) {
+            return Err(format!("{} is not within a preopened directory", root.display()).into());
+        }
+        self.roots.write().unwrap().insert(module_id, root);
+        Ok(())
+    }
+
+    fn remove_module(&self, module_id: u64) {
+        self.roots.write().unwrap().remove(&module_id);
+    }
+
+    fn operations(&self) -> Vec<String> {
+        [
+            "create_container",
+            "remove_container",
+            "list_blobs",
+            "put_blob",
+            "get_blob",
+            "remove_blob",
+        ]
+            .iter()
+            .map(|op| op.to_string())
+            .collect()
+    }
+
+    fn handle_call(
+        &self,
+        ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> BlobResult<Vec<u8>> {
+        let mut buf = payload;
+        let container = self.root(ctx.module_id)?.join(name(read_frame(&mut buf)?)?);
+        no_symlink(&container)?;
+        match operation {
+            "create_container" => {
+                fs::create_dir_all(&container)?;
+                Ok(vec![])
+            }
+            "remove_container" => {
+                fs::remove_dir_all(&container).map_err(|e| not_found(e, &container))?;
+                Ok(vec![])
+            }
+            "list_blobs" => {
+                let mut names = Vec::new();
+                for entry in fs::read_dir(&container).map_err(|e| not_found(e, &container))? {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() {
+                        names.push(entry.file_name().to_string_lossy().into_owned());
+                    }
+                }
+                names.sort();
+                let mut response = Vec::new();
+                for n in names {
+                    write_frame(&mut response, n.as_bytes());
+                }
+                Ok(response)
+            }
+            "put_blob" | "get_blob" | "remove_blob" => {
+                let blob = container.join(name(read_frame(&mut buf)?)?);
+                no_symlink(&blob)?;
+                match operation {
+                    "put_blob" => {
+                        fs::write(&blob, read_frame(&mut buf)?)
+                            .map_err(|e| not_found(e, &container))?;
+                        Ok(vec![])
+                    }
+                    "get_blob" => Ok(fs::read(&blob).map_err(|e| not_found(e, &blob))?),
+                    _ => {
+                        fs::remove_file(&blob).map_err(|e| not_found(e, &blob))?;
+                        Ok(vec![])
+                    }
+                }
+            }
+            _ => Err(format!("Unsupported blobstore operation: {}", operation).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::Extensions;
+
+    fn frames(parts: &[&[u8]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for p in parts {
+            write_frame(&mut buf, p);
+        }
+        buf
+    }
+
+    #[test]
+    fn blobs_stay_within_the_module_root() {
+        let dir = std::env::temp_dir().join(format!("wapc-blobstore-{}", std::process::id()));
+        let root = dir.join("module");
+        fs::create_dir_all(&root).unwrap();
+        let provider = BlobstoreProvider::new(vec![dir.clone()]);
+        let config = [(ROOT_CONFIG_KEY.to_string(), root.display().to_string())]
+            .iter()
+            .cloned()
+            .collect();
+        provider.configure(1, &config).unwrap();
+        let outside = [(ROOT_CONFIG_KEY.to_string(), "/".to_string())].iter().cloned().collect();
+        assert!(provider.configure(2, &outside).is_err());
+
+        let extensions = Extensions::default();
+        let ctx = HostCallContext {
+            module_id: 1,
+            binding: "default",
+            namespace: BLOBSTORE_NAMESPACE,
+            extensions: &extensions,
+            trace: None,
+        };
+        let call = |op, payload: Vec<u8>| provider.handle_call(&ctx, op, &payload);
+        call("create_container", frames(&[b"photos"])).unwrap();
+        call("put_blob", frames(&[b"photos", b"cat.png", b"meow"])).unwrap();
+        assert_eq!(call("get_blob", frames(&[b"photos", b"cat.png"])).unwrap(), b"meow");
+        assert_eq!(call("list_blobs", frames(&[b"photos"])).unwrap(), frames(&[b"cat.png"]));
+        assert!(call("get_blob", frames(&[b"..", b"secret"])).is_err());
+
+        let missing = call("get_blob", frames(&[b"photos", b"dog.png"])).unwrap_err();
+        assert_eq!(missing.downcast_ref::<StatusError>().unwrap().code(), NOT_FOUND);
+
+        #[cfg(unix)]
+        {
+            let outside = dir.join("outside");
+            fs::create_dir_all(&outside).unwrap();
+            fs::write(outside.join("secret"), b"hidden").unwrap();
+            std::os::unix::fs::symlink(&outside, root.join("linked")).unwrap();
+            std::os::unix::fs::symlink(outside.join("secret"), root.join("photos/leak")).unwrap();
+            std::os::unix::fs::symlink(outside.join("new"), root.join("photos/plant")).unwrap();
+            assert!(call("get_blob", frames(&[b"linked", b"secret"])).is_err());
+            assert!(call("put_blob", frames(&[b"linked", b"x", b"data"])).is_err());
+            assert!(call("get_blob", frames(&[b"photos", b"leak"])).is_err());
+            assert!(call("put_blob", frames(&[b"photos", b"leak", b"data"])).is_err());
+            assert!(call("put_blob", frames(&[b"photos", b"plant", b"data"])).is_err());
+            assert_eq!(fs::read(outside.join("secret")).unwrap(), b"hidden");
+            assert!(!outside.join("new").exists());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,151 @@
+//! Conditional host calls, letting guests skip copying responses they already hold.
+//!
+//! [ConditionalProvider](struct.ConditionalProvider.html) wraps another capability provider and
+//! tags each response with an entity tag, the SHA-256 hash of its content, which stays the same
+//! across host upgrades. A guest that keeps the tag
+//! from an earlier response can send it back, like HTTP's `If-None-Match`; when the response
+//! is unchanged the host fails the call with status [NOT_MODIFIED](constant.NOT_MODIFIED.html)
+//! instead of copying the payload into guest memory again.
+//!
+//! The wrapped provider keeps its namespace, and calls to it are framed using the encoding of
+//! the [batch](../../batch/index.html) module:
+//!
+//! | Direction | Encoding                                                          |
+//! |-----------|-------------------------------------------------------------------|
+//! | request   | a tag frame, empty for an unconditional call, then a payload frame |
+//! | response  | a tag frame followed by a response frame                           |
+//!
+//! A call answered with [NOT_MODIFIED](constant.NOT_MODIFIED.html) reports it through
+//! `__host_call_status` (see the [status](../../status/index.html) module), and its error
+//! message is the unchanged tag. The wrapped provider is still called, so this saves guest
+//! memory traffic rather than the provider's own work.
+
+use std::error::Error;
+
+use sha2::{Digest, Sha256};
+
+use crate::batch::{read_frame, write_frame};
+use crate::capability::{CapabilityConfig, CapabilityProvider};
+use crate::status::StatusError;
+use crate::HostCallContext;
+
+/// The status code of a conditional call whose response has not changed
+pub const NOT_MODIFIED: i32 = 304;
+
+type ProviderResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Returns the entity tag of a response: the SHA-256 hash of its content, as 64 lowercase hex
+/// digits
+pub fn entity_tag(response: &[u8]) -> String {
+    Sha256::digest(response)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A provider answering conditional calls on behalf of another. See the
+/// [conditional](index.html) module.
+pub struct ConditionalProvider<P> {
+    inner: P,
+}
+
+impl<P: CapabilityProvider> ConditionalProvider<P> {
+    pub fn new(inner: P) -> ConditionalProvider<P> {
+        ConditionalProvider { inner }
+    }
+
+    /// Returns the wrapped provider
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+}
+
+impl<P: CapabilityProvider> CapabilityProvider for ConditionalProvider<P> {
+    fn namespace(&self) -> &str {
+        self.inner.namespace()
+    }
+
+    fn configure(&self, module_id: u64, config: &CapabilityConfig) -> ProviderResult<()> {
+        self.inner.configure(module_id, config)
+    }
+
+    fn remove_module(&self, module_id: u64) {
+        self.inner.remove_module(module_id);
+    }
+
+    fn operations(&self) -> Vec<String> {
+        self.inner.operations()
+    }
+
+    fn handle_call(
+        &self,
+        ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> ProviderResult<Vec<u8>> {
+        let mut buf = payload;
+        let token = read_frame(&mut buf)?;
+        let payload = read_frame(&mut buf)?;
+        let response = self.inner.handle_call(ctx, operation, payload)?;
+        let tag = entity_tag(&response);
+        if !token.is_empty() && token == tag.as_bytes() {
+            return Err(Box::new(StatusError::new(NOT_MODIFIED, tag)));
+        }
+        let mut framed = Vec::with_capacity(response.len() + tag.len() + 8);
+        write_frame(&mut framed, tag.as_bytes());
+        write_frame(&mut framed, &response);
+        Ok(framed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::Extensions;
+    use crate::providers::keyvalue::{KeyValueProvider, MemoryStore, KEYVALUE_NAMESPACE};
+
+    #[test]
+    fn unchanged_responses_are_not_modified() {
+        let provider = ConditionalProvider::new(KeyValueProvider::<MemoryStore>::default());
+        let extensions = Extensions::default();
+        let ctx = HostCallContext {
+            module_id: 1,
+            binding: "default",
+            namespace: KEYVALUE_NAMESPACE,
+            extensions: &extensions,
+            trace: None,
+        };
+        let call = |op, token: &[u8], payload: &[u8]| {
+            let mut request = Vec::new();
+            write_frame(&mut request, token);
+            write_frame(&mut request, payload);
+            provider.handle_call(&ctx, op, &request)
+        };
+        let mut set = Vec::new();
+        write_frame(&mut set, b"config");
+        write_frame(&mut set, b"v1");
+        call("set", b"", &set).unwrap();
+
+        let response = call("get", b"", b"config").unwrap();
+        let mut buf = &response[..];
+        let tag = read_frame(&mut buf).unwrap().to_vec();
+        assert_eq!(read_frame(&mut buf).unwrap(), b"v1");
+
+        let unchanged = call("get", &tag, b"config").unwrap_err();
+        assert_eq!(unchanged.downcast_ref::<StatusError>().unwrap().code(), NOT_MODIFIED);
+
+        let mut set = Vec::new();
+        write_frame(&mut set, b"config");
+        write_frame(&mut set, b"v2");
+        call("set", b"", &set).unwrap();
+        assert!(call("get", &tag, b"config").is_ok());
+    }
+
+    #[test]
+    fn entity_tags_are_stable() {
+        assert_eq!(
+            entity_tag(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
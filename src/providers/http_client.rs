@@ -0,0 +1,189 @@
+//! An outbound HTTP capability, answering host calls to the `wapc:http_client` namespace.
+//! Requires the `http-client` feature.
+//!
+//! The `request` operation takes a JSON-encoded [HttpRequest](struct.HttpRequest.html) and
+//! responds with a JSON-encoded [HttpResponse](struct.HttpResponse.html). Non-2xx responses are
+//! returned like any other; only transport failures and policy violations fail the host call.
+//!
+//! The policy is enforced by the host, not the guest: requests may only go to hosts on the
+//! provider's allowlist, redirects are not followed (they could lead off the allowlist), and
+//! request bodies, response bodies and request durations are capped.
+
+use std::error::Error;
+use std::io::Read;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::capability::CapabilityProvider;
+use crate::HostCallContext;
+
+/// The namespace of host calls answered by the HTTP client provider
+pub const HTTP_CLIENT_NAMESPACE: &str = "wapc:http_client";
+
+/// The operation a guest invokes to make a request
+pub const REQUEST_OPERATION: &str = "request";
+
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+type HttpResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A request made by a guest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Vec<u8>,
+}
+
+/// The response returned to a guest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The HTTP client capability provider. See the [http_client](index.html) module.
+pub struct HttpClientProvider {
+    allowed_hosts: Vec<String>,
+    max_request_bytes: usize,
+    max_response_bytes: usize,
+    agent: ureq::Agent,
+}
+
+impl HttpClientProvider {
+    /// Creates a provider allowing requests only to the given hosts, with 1MiB body limits and
+    /// a 30 second timeout
+    pub fn new(allowed_hosts: &[&str]) -> HttpClientProvider {
+        HttpClientProvider {
+            allowed_hosts: allowed_hosts.iter().map(|h| h.to_ascii_lowercase()).collect(),
+            max_request_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_response_bytes: DEFAULT_MAX_BODY_BYTES,
+            agent: agent(DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// Sets the largest request body a guest may send
+    pub fn max_request_bytes(mut self, max: usize) -> Self {
+        self.max_request_bytes = max;
+        self
+    }
+
+    /// Sets the largest response body returned to a guest; larger responses fail the call
+    pub fn max_response_bytes(mut self, max: usize) -> Self {
+        self.max_response_bytes = max;
+        self
+    }
+
+    /// Sets the time limit for a whole request, from connecting to reading the response
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.agent = agent(timeout);
+        self
+    }
+
+    fn send(&self, request: HttpRequest) -> HttpResult<HttpResponse> {
+        if request.body.len() > self.max_request_bytes {
+            return Err(format!(
+                "Request body of {} bytes exceeds the {} byte limit",
+                request.body.len(),
+                self.max_request_bytes
+            )
+            .into());
+        }
+        let mut req = self.agent.request(&request.method, &request.url);
+        let host = req.request_url()?.host().to_ascii_lowercase();
+        if !self.allowed_hosts.contains(&host) {
+            return Err(format!("Host {} is not on the allowlist", host).into());
+        }
+        for (name, value) in request.headers.iter() {
+            req = req.set(name, value);
+        }
+        let response = match req.send_bytes(&request.body) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(self.max_response_bytes as u64 + 1)
+            .read_to_end(&mut body)?;
+        if body.len() > self.max_response_bytes {
+            return Err(format!(
+                "Response body exceeds the {} byte limit",
+                self.max_response_bytes
+            )
+            .into());
+        }
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+fn agent(timeout: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(timeout)
+        .redirects(0)
+        .build()
+}
+
+impl CapabilityProvider for HttpClientProvider {
+    fn namespace(&self) -> &str {
+        HTTP_CLIENT_NAMESPACE
+    }
+
+    fn operations(&self) -> Vec<String> {
+        vec![REQUEST_OPERATION.to_string()]
+    }
+
+    fn handle_call(
+        &self,
+        _ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> HttpResult<Vec<u8>> {
+        if operation != REQUEST_OPERATION {
+            return Err(format!("Unsupported HTTP client operation: {}", operation).into());
+        }
+        let request: HttpRequest = serde_json::from_slice(payload)?;
+        Ok(serde_json::to_vec(&self.send(request)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_is_enforced_before_sending() {
+        let provider = HttpClientProvider::new(&["api.example.com"]).max_request_bytes(4);
+        let request = |url: &str, body: &[u8]| HttpRequest {
+            method: "POST".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: body.to_vec(),
+        };
+
+        let err = provider.send(request("http://evil.example.com/", b"")).unwrap_err();
+        assert!(err.to_string().contains("not on the allowlist"));
+        let err = provider.send(request("http://api.example.com/", b"12345")).unwrap_err();
+        assert!(err.to_string().contains("exceeds"));
+    }
+}
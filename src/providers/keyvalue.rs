@@ -0,0 +1,202 @@
+//! A key-value store capability, answering host calls to the `wapc:keyvalue` namespace.
+//!
+//! | Operation | Payload                                   | Response                        |
+//! |-----------|-------------------------------------------|---------------------------------|
+//! | `get`     | the key                                   | the value                       |
+//! | `set`     | a key frame followed by a value frame     | empty                           |
+//! | `del`     | the key                                   | empty                           |
+//! | `keys`    | a key prefix, empty for all keys          | one frame per key, in key order |
+//!
+//! Keys are UTF-8 and frames use the encoding of the [batch](../../batch/index.html) module.
+//! A `get` for a missing key fails with status [NOT_FOUND](constant.NOT_FOUND.html) (see the
+//! [status](../../status/index.html) module). Each module sees only its own keys.
+//!
+//! Storage is pluggable through the [KeyValueStore](trait.KeyValueStore.html) trait, so a
+//! Redis- or sled-backed store can replace the default [MemoryStore](struct.MemoryStore.html).
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::sync::RwLock;
+
+use crate::batch::{read_frame, write_frame};
+use crate::capability::CapabilityProvider;
+use crate::status::StatusError;
+use crate::HostCallContext;
+
+/// The namespace of host calls answered by the key-value provider
+pub const KEYVALUE_NAMESPACE: &str = "wapc:keyvalue";
+
+/// The status code of a `get` for a missing key
+pub const NOT_FOUND: i32 = 404;
+
+type StoreResult<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Storage for the key-value provider. Every method is scoped to a module, and stores must keep
+/// the keys of different modules separate.
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, module_id: u64, key: &str) -> StoreResult<Option<Vec<u8>>>;
+    fn set(&self, module_id: u64, key: &str, value: Vec<u8>) -> StoreResult<()>;
+    fn del(&self, module_id: u64, key: &str) -> StoreResult<()>;
+    /// Returns the module's keys starting with `prefix`, in order
+    fn keys(&self, module_id: u64, prefix: &str) -> StoreResult<Vec<String>>;
+    /// Called when a module's host is dropped. Persistent stores may keep the module's data.
+    fn remove_module(&self, _module_id: u64) {}
+}
+
+/// An in-memory store whose data lives only as long as the module's host
+#[derive(Default)]
+pub struct MemoryStore {
+    modules: RwLock<HashMap<u64, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, module_id: u64, key: &str) -> StoreResult<Option<Vec<u8>>> {
+        let modules = self.modules.read().unwrap();
+        Ok(modules.get(&module_id).and_then(|m| m.get(key)).cloned())
+    }
+
+    fn set(&self, module_id: u64, key: &str, value: Vec<u8>) -> StoreResult<()> {
+        let mut modules = self.modules.write().unwrap();
+        modules
+            .entry(module_id)
+            .or_default()
+            .insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn del(&self, module_id: u64, key: &str) -> StoreResult<()> {
+        if let Some(m) = self.modules.write().unwrap().get_mut(&module_id) {
+            m.remove(key);
+        }
+        Ok(())
+    }
+
+    fn keys(&self, module_id: u64, prefix: &str) -> StoreResult<Vec<String>> {
+        let modules = self.modules.read().unwrap();
+        Ok(modules
+            .get(&module_id)
+            .map(|m| {
+                m.range(prefix.to_string()..)
+                    .map(|(k, _)| k)
+                    .take_while(|k| k.starts_with(prefix))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn remove_module(&self, module_id: u64) {
+        self.modules.write().unwrap().remove(&module_id);
+    }
+}
+
+/// The key-value capability provider. See the [keyvalue](index.html) module.
+#[derive(Default)]
+pub struct KeyValueProvider<S = MemoryStore> {
+    store: S,
+}
+
+impl<S: KeyValueStore> KeyValueProvider<S> {
+    pub fn new(store: S) -> KeyValueProvider<S> {
+        KeyValueProvider { store }
+    }
+}
+
+fn utf8(bytes: &[u8]) -> StoreResult<&str> {
+    std::str::from_utf8(bytes).map_err(|_| "Key is not valid UTF-8".into())
+}
+
+impl<S: KeyValueStore> CapabilityProvider for KeyValueProvider<S> {
+    fn namespace(&self) -> &str {
+        KEYVALUE_NAMESPACE
+    }
+
+    fn remove_module(&self, module_id: u64) {
+        self.store.remove_module(module_id);
+    }
+
+    fn operations(&self) -> Vec<String> {
+        ["get", "set", "del", "keys"]
+            .iter()
+            .map(|op| op.to_string())
+            .collect()
+    }
+
+    fn handle_call(
+        &self,
+        ctx: &HostCallContext,
+        operation: &str,
+        payload: &[u8],
+    ) -> StoreResult<Vec<u8>> {
+        let id = ctx.module_id;
+        match operation {
+            "get" => {
+                let key = utf8(payload)?;
+                match self.store.get(id, key)? {
+                    Some(value) => Ok(value),
+                    None => Err(Box::new(StatusError::new(
+                        NOT_FOUND,
+                        format!("No such key: {}", key),
+                    ))),
+                }
+            }
+            "set" => {
+                let mut buf = payload;
+                let key = utf8(read_frame(&mut buf)?)?;
+                let value = read_frame(&mut buf)?;
+                self.store.set(id, key, value.to_vec())?;
+                Ok(vec![])
+            }
+            "del" => {
+                self.store.del(id, utf8(payload)?)?;
+                Ok(vec![])
+            }
+            "keys" => {
+                let mut buf = Vec::new();
+                for key in self.store.keys(id, utf8(payload)?)? {
+                    write_frame(&mut buf, key.as_bytes());
+                }
+                Ok(buf)
+            }
+            _ => Err(format!("Unsupported key-value operation: {}", operation).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::Extensions;
+
+    #[test]
+    fn operations_are_isolated_per_module() {
+        let provider = KeyValueProvider::<MemoryStore>::default();
+        let extensions = Extensions::default();
+        let call = |module_id, op, payload: &[u8]| {
+            let ctx = HostCallContext {
+                module_id,
+                binding: "default",
+                namespace: KEYVALUE_NAMESPACE,
+                extensions: &extensions,
+                trace: None,
+            };
+            provider.handle_call(&ctx, op, payload)
+        };
+        let mut set = Vec::new();
+        write_frame(&mut set, b"user:1");
+        write_frame(&mut set, b"alice");
+        call(1, "set", &set).unwrap();
+
+        assert_eq!(call(1, "get", b"user:1").unwrap(), b"alice");
+        let missing = call(2, "get", b"user:1").unwrap_err();
+        assert_eq!(missing.downcast_ref::<StatusError>().unwrap().code(), NOT_FOUND);
+
+        let keys = call(1, "keys", b"user:").unwrap();
+        let mut buf = &keys[..];
+        assert_eq!(read_frame(&mut buf).unwrap(), b"user:1");
+        assert!(buf.is_empty());
+
+        call(1, "del", b"user:1").unwrap();
+        assert!(call(1, "get", b"user:1").is_err());
+    }
+}
@@ -0,0 +1,8 @@
+//! Reference [capability providers](../capability/index.html) that let guests use common
+//! capabilities without the embedder writing a host callback
+
+pub mod blobstore;
+pub mod conditional;
+#[cfg(feature = "http-client")]
+pub mod http_client;
+pub mod keyvalue;
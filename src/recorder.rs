@@ -0,0 +1,142 @@
+//! Recording of invocation traces and replaying them against another build of a module.
+//!
+//! [WapcHost::record_to](../struct.WapcHost.html#method.record_to) appends one JSON object per
+//! line to a trace file for every guest call, carrying the operation, payload, response or
+//! error, and the host calls the guest made while handling it. [replay](fn.replay.html) reads
+//! such a trace, re-drives each call through any [WapcCaller](../trait.WapcCaller.html) and
+//! reports the calls whose outcome differs from the recording.
+
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{self, ErrorKind};
+use crate::{Result, WapcCaller};
+
+/// A host call made by the guest while handling a recorded call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostCallRecord {
+    pub binding: String,
+    pub namespace: String,
+    pub operation: String,
+    pub payload: Vec<u8>,
+    pub outcome: Outcome,
+}
+
+/// A recorded guest call, one per line of a trace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallRecord {
+    pub operation: String,
+    pub payload: Vec<u8>,
+    pub outcome: Outcome,
+    pub host_calls: Vec<HostCallRecord>,
+}
+
+/// The response or error message produced by a call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Response(Vec<u8>),
+    Error(String),
+}
+
+impl Outcome {
+    pub(crate) fn of<E: std::fmt::Display>(result: &std::result::Result<Vec<u8>, E>) -> Outcome {
+        match result {
+            Ok(response) => Outcome::Response(response.clone()),
+            Err(e) => Outcome::Error(e.to_string()),
+        }
+    }
+}
+
+pub(crate) struct Recorder {
+    out: BufWriter<File>,
+    host_calls: Vec<HostCallRecord>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path) -> Result<Recorder> {
+        Ok(Recorder {
+            out: BufWriter::new(File::create(path)?),
+            host_calls: Vec::new(),
+        })
+    }
+
+    pub(crate) fn host_call(&mut self, record: HostCallRecord) {
+        self.host_calls.push(record);
+    }
+
+    /// Writes the completed call, with the host calls made since the previous one
+    pub(crate) fn call(&mut self, operation: &str, payload: &[u8], outcome: Outcome) -> Result<()> {
+        let record = CallRecord {
+            operation: operation.to_string(),
+            payload: payload.to_vec(),
+            outcome,
+            host_calls: std::mem::take(&mut self.host_calls),
+        };
+        serde_json::to_writer(&mut self.out, &record)
+            .map_err(|e| errors::new(ErrorKind::Serialization(e.to_string())))?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// A recorded call whose replayed outcome differs from the recording
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The position of the call in the trace, starting at 0
+    pub index: usize,
+    pub operation: String,
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+/// The result of replaying a trace
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of calls replayed
+    pub calls: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReplayReport {
+    /// Whether every replayed call matched its recording
+    pub fn is_match(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Replays every call in a trace against the given host, in order. The host's own host
+/// callback services any host calls the guest makes; recorded host calls are informational.
+/// Error messages are compared as recorded, including the `[module N]` prefix, so replay against
+/// a host with the same module ID when errors are expected.
+pub fn replay(trace: impl BufRead, host: &dyn WapcCaller) -> Result<ReplayReport> {
+    let mut report = ReplayReport::default();
+    for (index, line) in trace.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: CallRecord = serde_json::from_str(&line).map_err(|e| {
+            errors::new(ErrorKind::Serialization(format!(
+                "Invalid trace record on line {}: {}",
+                index + 1,
+                e
+            )))
+        })?;
+        let actual = Outcome::of(&host.call(&record.operation, &record.payload));
+        if actual != record.outcome {
+            report.mismatches.push(Mismatch {
+                index: report.calls,
+                operation: record.operation,
+                expected: record.outcome,
+                actual,
+            });
+        }
+        report.calls += 1;
+    }
+    Ok(report)
+}
@@ -0,0 +1,142 @@
+//! Retrying host calls that fail transiently, so a flaky provider does not surface as a guest
+//! call failure.
+//!
+//! A [RetryPolicy](struct.RetryPolicy.html) is registered for a namespace with
+//! [WapcHostBuilder::retry_host_calls](../struct.WapcHostBuilder.html#method.retry_host_calls).
+//! A host call to that namespace whose handler fails with an error the policy considers
+//! retryable is made again, after a backoff that doubles with each attempt, until it succeeds
+//! or the attempts run out; only then is the last error returned to the guest. The guest call
+//! is blocked while the host waits, so backoffs should be short. Handlers that panic are not
+//! retried. Unless the policy says otherwise, a
+//! [StatusError](../status/struct.StatusError.html) such as a missing key or an unchanged
+//! response is an answer rather than a transient failure, and is not retried either.
+
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::status::StatusError;
+
+type HostCallError = Box<dyn Error + Send + Sync>;
+
+type Classifier = dyn Fn(&(dyn Error + Send + Sync + 'static)) -> bool + Send + Sync;
+
+/// How often, and after which errors, a host call is retried. See the [retry](index.html)
+/// module.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retryable: Option<Arc<Classifier>>,
+}
+
+impl RetryPolicy {
+    /// Creates a policy making up to `max_attempts` attempts in total, retrying every error
+    /// other than a [StatusError](../status/struct.StatusError.html) immediately
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            retryable: None,
+        }
+    }
+
+    /// Waits `initial` before the first retry, doubling the wait for each retry after that up
+    /// to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Retries only the errors for which `classifier` returns true, e.g. timeouts but not a
+    /// [StatusError](../status/struct.StatusError.html) reporting a missing key
+    pub fn retry_if(
+        mut self,
+        classifier: impl Fn(&(dyn Error + Send + Sync + 'static)) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.retryable = Some(Arc::new(classifier));
+        self
+    }
+
+    /// The wait before retry number `retry`, counting from zero
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Makes the call, retrying it as the policy allows
+    pub(crate) fn run(
+        &self,
+        mut call: impl FnMut() -> Result<Vec<u8>, HostCallError>,
+    ) -> Result<Vec<u8>, HostCallError> {
+        let mut attempt = 1;
+        loop {
+            let error = match call() {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+            let retryable = match self.retryable {
+                Some(ref classifier) => classifier(error.as_ref()),
+                None => error.downcast_ref::<StatusError>().is_none(),
+            };
+            if attempt >= self.max_attempts || !retryable {
+                return Err(error);
+            }
+            let delay = self.delay(attempt - 1);
+            debug!("Retrying host call after {:?}: {}", delay, error);
+            if !delay.is_zero() {
+                std::thread::sleep(delay);
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_until_success_or_a_permanent_error() {
+        let policy = RetryPolicy::new(5)
+            .backoff(Duration::from_millis(1), Duration::from_millis(3))
+            .retry_if(|e| e.downcast_ref::<StatusError>().is_none());
+        assert_eq!(policy.delay(0), Duration::from_millis(1));
+        assert_eq!(policy.delay(4), Duration::from_millis(3));
+
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err("connection reset".into())
+            } else {
+                Ok(b"ok".to_vec())
+            }
+        });
+        assert_eq!(result.unwrap(), b"ok");
+
+        let mut attempts = 0;
+        let result = policy.run(|| {
+            attempts += 1;
+            Err(Box::new(StatusError::new(404, "missing")))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn status_replies_are_not_retried_by_default() {
+        let mut attempts = 0;
+        let result = RetryPolicy::new(3).run(|| {
+            attempts += 1;
+            Err(Box::new(StatusError::new(304, "unchanged")))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
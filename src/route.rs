@@ -0,0 +1,68 @@
+//! Helpers for the `namespace!Operation` convention used to name waPC operations, e.g.
+//! `wapc:sample!Hello`
+
+use std::fmt;
+
+/// The character separating a namespace from the operation within it
+pub const ROUTE_SEPARATOR: char = '!';
+
+/// An operation name split into an optional namespace and the operation within it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Route<'a> {
+    /// The namespace, if the operation name contained a separator
+    pub namespace: Option<&'a str>,
+    /// The operation within the namespace
+    pub operation: &'a str,
+}
+
+impl<'a> Route<'a> {
+    /// Creates a route for an operation within a namespace
+    pub fn new(namespace: &'a str, operation: &'a str) -> Route<'a> {
+        Route {
+            namespace: Some(namespace),
+            operation,
+        }
+    }
+
+    /// Splits an operation name at the first separator. A name without a separator is
+    /// treated as an operation with no namespace.
+    pub fn parse(name: &'a str) -> Route<'a> {
+        match name.find(ROUTE_SEPARATOR) {
+            Some(i) => Route {
+                namespace: Some(&name[..i]),
+                operation: &name[i + ROUTE_SEPARATOR.len_utf8()..],
+            },
+            None => Route {
+                namespace: None,
+                operation: name,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Route<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.namespace {
+            Some(ns) => write!(f, "{}{}{}", ns, ROUTE_SEPARATOR, self.operation),
+            None => write!(f, "{}", self.operation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Route;
+
+    #[test]
+    fn parses_and_formats_routes() {
+        let r = Route::parse("wapc:sample!Hello");
+        assert_eq!(r, Route::new("wapc:sample", "Hello"));
+        assert_eq!(r.to_string(), "wapc:sample!Hello");
+
+        let r = Route::parse("Hello");
+        assert_eq!(r.namespace, None);
+        assert_eq!(r.to_string(), "Hello");
+
+        assert_eq!(Route::parse("a!b!c"), Route::new("a", "b!c"));
+    }
+}
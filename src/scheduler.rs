@@ -0,0 +1,237 @@
+//! Periodic invocation of guest operations, so embedders don't each have to write the same
+//! "call this operation every N seconds" thread.
+//!
+//! A [Scheduler](struct.Scheduler.html) can either be pumped by the embedder with
+//! [run_pending](struct.Scheduler.html#method.run_pending), or started on a background thread
+//! with [start](struct.Scheduler.html#method.start). Because a `WapcHost` cannot be moved
+//! between threads, `start` takes a factory that creates the host on the scheduler thread.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{Result, WapcCaller};
+
+/// What to do when one or more ticks of a schedule were missed, for example because a previous
+/// guest call took longer than the interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissedTickPolicy {
+    /// Run once and drop the missed ticks, keeping to the original tick boundaries
+    Skip,
+    /// Run once for every missed tick, as quickly as possible, until caught up
+    Burst,
+    /// Run once and restart the interval from now
+    Delay,
+}
+
+type ResultCallback = dyn FnMut(&str, &Result<Vec<u8>>) + Send;
+
+struct Schedule {
+    operation: String,
+    payload: Vec<u8>,
+    interval: Duration,
+    policy: MissedTickPolicy,
+    next_due: Instant,
+}
+
+impl Schedule {
+    fn advance(&mut self, now: Instant) {
+        match self.policy {
+            MissedTickPolicy::Burst => self.next_due += self.interval,
+            MissedTickPolicy::Delay => self.next_due = now + self.interval,
+            MissedTickPolicy::Skip => {
+                while self.next_due <= now {
+                    self.next_due += self.interval;
+                }
+            }
+        }
+    }
+}
+
+/// A set of operations to invoke on a guest at fixed intervals
+pub struct Scheduler {
+    schedules: Vec<Schedule>,
+    on_result: Option<Box<ResultCallback>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler with no schedules
+    pub fn new() -> Scheduler {
+        Scheduler {
+            schedules: Vec::new(),
+            on_result: None,
+        }
+    }
+
+    /// Invokes the operation with the given payload every `interval`, skipping missed ticks.
+    /// The first invocation happens one interval after the schedule is added.
+    pub fn every(self, interval: Duration, operation: &str, payload: &[u8]) -> Self {
+        self.every_with_policy(interval, operation, payload, MissedTickPolicy::Skip)
+    }
+
+    /// Invokes the operation with the given payload every `interval`, handling missed ticks
+    /// according to the given policy
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero
+    pub fn every_with_policy(
+        mut self,
+        interval: Duration,
+        operation: &str,
+        payload: &[u8],
+        policy: MissedTickPolicy,
+    ) -> Self {
+        assert!(interval > Duration::from_secs(0), "interval must be non-zero");
+        self.schedules.push(Schedule {
+            operation: operation.to_string(),
+            payload: payload.to_vec(),
+            interval,
+            policy,
+            next_due: Instant::now() + interval,
+        });
+        self
+    }
+
+    /// Sets a callback that receives the operation name and result of every scheduled call.
+    /// Without one, failed calls are logged at the `warn` level.
+    pub fn on_result(mut self, f: impl FnMut(&str, &Result<Vec<u8>>) + Send + 'static) -> Self {
+        self.on_result = Some(Box::new(f));
+        self
+    }
+
+    /// Invokes every operation that is due, returning the number of calls made
+    pub fn run_pending(&mut self, host: &dyn WapcCaller) -> usize {
+        self.run_due(Instant::now(), host)
+    }
+
+    /// Returns the time until the next operation is due, or `None` if there are no schedules
+    pub fn time_until_next(&self) -> Option<Duration> {
+        self.schedules
+            .iter()
+            .map(|s| s.next_due)
+            .min()
+            .map(|due| due.saturating_duration_since(Instant::now()))
+    }
+
+    fn run_due(&mut self, now: Instant, host: &dyn WapcCaller) -> usize {
+        let mut calls = 0;
+        for schedule in self.schedules.iter_mut() {
+            if schedule.next_due > now {
+                continue;
+            }
+            let result = host.call(&schedule.operation, &schedule.payload);
+            match self.on_result {
+                Some(ref mut f) => f(&schedule.operation, &result),
+                None => {
+                    if let Err(ref e) = result {
+                        warn!("Scheduled call to '{}' failed: {}", schedule.operation, e);
+                    }
+                }
+            }
+            schedule.advance(now);
+            calls += 1;
+        }
+        calls
+    }
+
+    /// Runs the scheduler on a background thread until the returned handle is stopped or
+    /// dropped. The host is created on that thread by the given factory; if the factory fails,
+    /// the error is returned when the handle is stopped.
+    pub fn start<C, F>(mut self, factory: F) -> SchedulerHandle
+    where
+        C: WapcCaller,
+        F: FnOnce() -> Result<C> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            let host = factory()?;
+            loop {
+                self.run_pending(&host);
+                let wait = self
+                    .time_until_next()
+                    .unwrap_or_else(|| Duration::from_secs(3600));
+                match rx.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => return Ok(()),
+                }
+            }
+        });
+        SchedulerHandle {
+            stop: Some(tx),
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Controls a scheduler running on a background thread. Dropping the handle stops the
+/// scheduler without waiting for it to finish.
+pub struct SchedulerHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl SchedulerHandle {
+    /// Stops the scheduler, waiting for any in-flight call to complete. Returns the error
+    /// produced by the host factory, if it failed.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop.take();
+        match self.thread.take().map(|t| t.join()) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(crate::errors::new(
+                crate::errors::ErrorKind::GuestCallFailure(
+                    "Scheduler thread panicked".to_string(),
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockWapcHost;
+
+    #[test]
+    fn missed_ticks_follow_policy() {
+        let host = MockWapcHost::new(1).respond("tick", b"");
+        let interval = Duration::from_secs(10);
+        let mut scheduler = Scheduler::new()
+            .every_with_policy(interval, "tick", b"", MissedTickPolicy::Skip)
+            .every_with_policy(interval, "tick", b"", MissedTickPolicy::Burst)
+            .every_with_policy(interval, "tick", b"", MissedTickPolicy::Delay);
+        let starts: Vec<_> = scheduler.schedules.iter().map(|s| s.next_due).collect();
+        let late = starts[2] + Duration::from_secs(35);
+
+        assert_eq!(scheduler.run_due(late, &host), 3);
+        assert_eq!(scheduler.schedules[0].next_due, starts[0] + Duration::from_secs(40));
+        assert_eq!(scheduler.schedules[1].next_due, starts[1] + interval);
+        assert_eq!(scheduler.schedules[2].next_due, late + interval);
+
+        // only the burst schedule is still behind
+        assert_eq!(scheduler.run_due(late, &host), 1);
+    }
+
+    #[test]
+    fn background_scheduler_invokes_host() {
+        let (tx, rx) = mpsc::channel();
+        let handle = Scheduler::new()
+            .every(Duration::from_millis(5), "tick", b"")
+            .on_result(move |op, r| {
+                let _ = tx.send((op.to_string(), r.is_ok()));
+            })
+            .start(|| Ok(MockWapcHost::new(1).respond("tick", b"ok")));
+
+        let (op, ok) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(op, "tick");
+        assert!(ok);
+        handle.stop().unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! A gRPC service that lets orchestrators in any language invoke guests across process
+//! boundaries. Requires the `grpc` feature.
+//!
+//! The service, `wapc.v1.WapcHost`, is defined in `proto/wapc/v1/wapc.proto` and has three
+//! methods: `Call` invokes an operation, `Replace` swaps the guest module and `Health` runs a
+//! [health check](../../struct.WapcHost.html#method.health_check). One server can serve several
+//! modules, each from its own [WapcHostPool](../../struct.WapcHostPool.html); requests name the
+//! module they are for in the [MODULE_METADATA](constant.MODULE_METADATA.html) entry, which a
+//! client usually attaches to every request on its connection with an interceptor. A server with
+//! a single module also accepts requests that name none.
+//!
+//! Guest errors are mapped to a status code using their
+//! [class](../../guest_error/index.html), e.g. `INVALID_ARGUMENT` for bad requests, and any
+//! other failure is `INTERNAL`; the status message is the error message.
+//!
+//! ```ignore
+//! let mut modules = HashMap::new();
+//! modules.insert("echo".to_string(), WapcHostPool::new(4, |_| {
+//!     WapcHost::new(Box::new(MyEngineProvider::new(&module)), host_callback)
+//! })?);
+//! let server = wapc::server::grpc::serve("0.0.0.0:50051", modules)?;
+//! ```
+//!
+//! The generated message and service types are checked in, so building the crate does not need
+//! `protoc`, and are exported from [proto](proto/index.html) for Rust clients.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tonic::metadata::MetadataMap;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+
+use crate::errors::{self, ErrorKind};
+use crate::guest_error::GuestErrorClass;
+use crate::{Result, WapcHostPool};
+
+/// The message and service types generated from `wapc.proto`
+pub mod proto {
+    #![allow(clippy::all)]
+    include!("wapc.v1.rs");
+}
+
+use proto::wapc_host_server::WapcHostServer;
+use proto::{
+    CallRequest, CallResponse, HealthRequest, HealthResponse, ReplaceRequest, ReplaceResponse,
+};
+
+/// The metadata entry naming the module a request is for
+pub const MODULE_METADATA: &str = "wapc-module";
+
+/// How long a guest may take to respond to a health check that does not set a timeout
+pub const DEFAULT_HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A running gRPC server, which stops when [stop](#method.stop) is called
+pub struct GrpcServer {
+    runtime: Runtime,
+    shutdown: oneshot::Sender<()>,
+    server: tokio::task::JoinHandle<std::result::Result<(), tonic::transport::Error>>,
+    local_addr: SocketAddr,
+}
+
+/// Starts a server listening on `addr` that serves each of `modules` by name. Returns an error
+/// if the address cannot be bound.
+pub fn serve(addr: &str, modules: HashMap<String, WapcHostPool>) -> Result<GrpcServer> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| errors::new(ErrorKind::IO(e)))?;
+    let listener = runtime
+        .block_on(tokio::net::TcpListener::bind(addr))
+        .map_err(|e| errors::new(ErrorKind::IO(e)))?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|e| errors::new(ErrorKind::IO(e)))?;
+    let service = Modules {
+        modules: modules
+            .into_iter()
+            .map(|(name, pool)| (name, Arc::new(pool)))
+            .collect(),
+    };
+    let incoming = {
+        let _runtime = runtime.enter();
+        TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| errors::new(ErrorKind::IO(std::io::Error::other(e))))?
+    };
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    let server = runtime.spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(WapcHostServer::new(service))
+            .serve_with_incoming_shutdown(incoming, async {
+                let _ = stopped.await;
+            })
+            .await
+    });
+    Ok(GrpcServer {
+        runtime,
+        shutdown,
+        server,
+        local_addr,
+    })
+}
+
+impl GrpcServer {
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting requests, waits for requests in progress to complete, and drops the
+    /// modules' host pools
+    pub fn stop(self) {
+        let _ = self.shutdown.send(());
+        match self.runtime.block_on(self.server) {
+            Ok(Err(e)) => warn!("gRPC server failed: {}", e),
+            Err(e) => warn!("gRPC server task failed: {}", e),
+            Ok(Ok(_)) => {}
+        }
+    }
+}
+
+struct Modules {
+    modules: HashMap<String, Arc<WapcHostPool>>,
+}
+
+impl Modules {
+    /// Returns the pool of the module named by the request's metadata, or of the only module
+    // Status is large, but it is what every method of the service returns
+    #[allow(clippy::result_large_err)]
+    fn select(&self, metadata: &MetadataMap) -> std::result::Result<Arc<WapcHostPool>, Status> {
+        let name = match metadata.get(MODULE_METADATA) {
+            Some(name) => name
+                .to_str()
+                .map_err(|_| Status::invalid_argument("The module name is not valid text"))?,
+            None if self.modules.len() == 1 => {
+                return Ok(self.modules.values().next().unwrap().clone())
+            }
+            None => {
+                return Err(Status::invalid_argument(format!(
+                    "Requests must name a module in the {} metadata entry",
+                    MODULE_METADATA
+                )))
+            }
+        };
+        self.modules
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("No module named {}", name)))
+    }
+}
+
+#[tonic::async_trait]
+impl proto::wapc_host_server::WapcHost for Modules {
+    async fn call(
+        &self,
+        request: Request<CallRequest>,
+    ) -> std::result::Result<Response<CallResponse>, Status> {
+        let pool = self.select(request.metadata())?;
+        let CallRequest { operation, payload } = request.into_inner();
+        let payload = blocking(move || pool.call(&operation, &payload)).await?;
+        Ok(Response::new(CallResponse { payload }))
+    }
+
+    async fn replace(
+        &self,
+        request: Request<ReplaceRequest>,
+    ) -> std::result::Result<Response<ReplaceResponse>, Status> {
+        let pool = self.select(request.metadata())?;
+        let module = request.into_inner().module;
+        blocking(move || pool.replace_module(&module)).await?;
+        Ok(Response::new(ReplaceResponse {}))
+    }
+
+    async fn health(
+        &self,
+        request: Request<HealthRequest>,
+    ) -> std::result::Result<Response<HealthResponse>, Status> {
+        let pool = self.select(request.metadata())?;
+        let timeout = match request.into_inner().timeout_ms {
+            0 => DEFAULT_HEALTH_TIMEOUT,
+            ms => Duration::from_millis(ms.into()),
+        };
+        let report = blocking(move || pool.execute(move |host| host.health_check(timeout))).await?;
+        Ok(Response::new(HealthResponse {
+            healthy: report.healthy,
+            detail: report.detail.unwrap_or_default(),
+            latency_us: report.latency.as_micros() as u64,
+        }))
+    }
+}
+
+/// Runs `f`, which waits for a guest, on a thread where blocking is allowed
+async fn blocking<R: Send + 'static>(
+    f: impl FnOnce() -> Result<R> + Send + 'static,
+) -> std::result::Result<R, Status> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result.map_err(|e| status(&e)),
+        Err(e) => Err(Status::internal(e.to_string())),
+    }
+}
+
+/// Maps a failed call to a gRPC status
+fn status(e: &errors::Error) -> Status {
+    let code = match (e.kind(), e.guest_error().map(|g| g.class)) {
+        (ErrorKind::NoSuchFunction(_), _) => Code::Unimplemented,
+        (ErrorKind::QueueFull, _) => Code::ResourceExhausted,
+        (_, Some(GuestErrorClass::BadRequest)) => Code::InvalidArgument,
+        (_, Some(GuestErrorClass::UserError)) => Code::FailedPrecondition,
+        (_, Some(GuestErrorClass::UnsupportedOperation)) => Code::Unimplemented,
+        _ => Code::Internal,
+    };
+    Status::new(code, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::wapc_host_client::WapcHostClient;
+    use super::*;
+    use crate::tests::{echo_guest, MockEngine};
+    use crate::{ModuleState, WapcHost};
+
+    fn pool() -> WapcHostPool {
+        WapcHostPool::new(2, |_| {
+            let guest = |state: &ModuleState| {
+                match &*state.get_guest_request().unwrap().operation {
+                    "validate" => state.set_guest_error("[bad_request] no name".to_string()),
+                    _ => return echo_guest(state),
+                }
+                0
+            };
+            WapcHost::new(MockEngine::boxed(guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap()
+    }
+
+    fn request<T>(module: &str, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert(MODULE_METADATA, module.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn modules_are_served_by_name() {
+        let mut modules = HashMap::new();
+        modules.insert("echo".to_string(), pool());
+        modules.insert("other".to_string(), pool());
+        let server = serve("127.0.0.1:0", modules).unwrap();
+        let url = format!("http://{}", server.local_addr());
+
+        let client = tokio::runtime::Runtime::new().unwrap();
+        client.block_on(async {
+            let mut client = WapcHostClient::connect(url).await.unwrap();
+            let call = |operation: &str| CallRequest {
+                operation: operation.to_string(),
+                payload: b"hello".to_vec(),
+            };
+            let response = client.call(request("echo", call("echo"))).await.unwrap();
+            assert_eq!(response.into_inner().payload, b"hello");
+
+            let failed = client.call(request("echo", call("validate"))).await;
+            assert_eq!(failed.unwrap_err().code(), Code::InvalidArgument);
+            let unknown = client.call(request("missing", call("echo"))).await;
+            assert_eq!(unknown.unwrap_err().code(), Code::NotFound);
+            let unnamed = client.call(Request::new(call("echo"))).await;
+            assert_eq!(unnamed.unwrap_err().code(), Code::InvalidArgument);
+
+            let health = client
+                .health(request("other", HealthRequest { timeout_ms: 0 }))
+                .await
+                .unwrap();
+            assert!(health.into_inner().healthy);
+            // The mock engine cannot replace its guest, so the failure comes back from the pool
+            let replace = ReplaceRequest { module: Vec::new() };
+            let replaced = client.replace(request("other", replace)).await;
+            assert_eq!(replaced.unwrap_err().code(), Code::Internal);
+        });
+        server.stop();
+    }
+}
@@ -0,0 +1,247 @@
+//! An HTTP frontend that turns a waPC module into a microservice. Requires the `http-server`
+//! feature.
+//!
+//! Every `POST /call/{operation}` request invokes `operation` with the request body as its
+//! payload and responds with the guest's response. The operation is percent-decoded, and any
+//! query string is ignored. Guest errors are mapped to a status code
+//! using their [class](../../guest_error/index.html), and any other failure is a 500; the error
+//! message is the response body.
+//!
+//! ```ignore
+//! let server = wapc::server::http::serve("0.0.0.0:8080", 4, || {
+//!     WapcHost::new(Box::new(MyEngineProvider::new(&module)), host_callback)
+//! })?;
+//! ```
+//!
+//! Because a `WapcHost` cannot be shared between threads, the server runs a pool of worker
+//! threads, each owning a host created by the factory, which take turns accepting requests.
+
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use tiny_http::{Method, Request, Response, Server};
+
+use crate::errors::{self, ErrorKind};
+use crate::{Result, WapcHost};
+
+/// The path prefix under which operations are invoked
+pub const CALL_PATH: &str = "/call/";
+
+/// The largest request body accepted, in bytes
+pub const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// A running HTTP server, which stops when [stop](#method.stop) is called
+pub struct HttpServer {
+    server: Arc<Server>,
+    stopping: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Starts a server listening on `addr` with `workers` worker threads, each of which creates its
+/// own host with `factory`. Returns an error if the address cannot be bound or any worker fails
+/// to create its host.
+pub fn serve(
+    addr: &str,
+    workers: usize,
+    factory: impl Fn() -> Result<WapcHost> + Send + Sync + 'static,
+) -> Result<HttpServer> {
+    let server = Arc::new(Server::http(addr).map_err(|e| {
+        errors::new(ErrorKind::IO(std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            e.to_string(),
+        )))
+    })?);
+    let stopping = Arc::new(AtomicBool::new(false));
+    let factory = Arc::new(factory);
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+
+    let handles = (0..workers.max(1))
+        .map(|_| {
+            let server = server.clone();
+            let stopping = stopping.clone();
+            let factory = factory.clone();
+            let ready = ready_tx.clone();
+            thread::spawn(move || {
+                let host = match factory() {
+                    Ok(host) => {
+                        let _ = ready.send(Ok(()));
+                        host
+                    }
+                    Err(e) => {
+                        let _ = ready.send(Err(e));
+                        return;
+                    }
+                };
+                while !stopping.load(Ordering::SeqCst) {
+                    match server.recv() {
+                        Ok(request) => handle(&host, request),
+                        Err(e) => {
+                            error!("HTTP server failed to accept a request: {}", e);
+                            break;
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    let http = HttpServer {
+        server,
+        stopping,
+        workers: handles,
+    };
+    for _ in 0..http.workers.len() {
+        if let Ok(Err(e)) = ready_rx.recv() {
+            http.stop();
+            return Err(e);
+        }
+    }
+    Ok(http)
+}
+
+impl HttpServer {
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Stops accepting requests, waits for requests in progress to complete, and drops the
+    /// workers' hosts
+    pub fn stop(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        for _ in self.workers.iter() {
+            self.server.unblock();
+        }
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn handle(host: &WapcHost, mut request: Request) {
+    let operation = match operation(request.url()) {
+        Some(op) => op,
+        None => return respond(request, 404, b"Not found".to_vec()),
+    };
+    if *request.method() != Method::Post {
+        return respond(request, 405, b"Use POST to invoke an operation".to_vec());
+    }
+    if request.body_length().unwrap_or(0) > MAX_BODY_BYTES {
+        return respond(request, 413, b"Request body too large".to_vec());
+    }
+    let mut body = Vec::new();
+    if let Err(e) = request
+        .as_reader()
+        .take(MAX_BODY_BYTES as u64 + 1)
+        .read_to_end(&mut body)
+    {
+        return respond(request, 400, e.to_string().into_bytes());
+    }
+    if body.len() > MAX_BODY_BYTES {
+        return respond(request, 413, b"Request body too large".to_vec());
+    }
+
+    match host.call(&operation, &body) {
+        Ok(response) => respond(request, 200, response),
+        Err(e) => {
+            let status = e.guest_error().map_or(500, |g| g.class.http_status());
+            respond(request, status, e.to_string().into_bytes())
+        }
+    }
+}
+
+/// Extracts the operation named by a request URL, or `None` if the URL does not name exactly one
+/// non-empty, validly encoded path segment under [CALL_PATH](constant.CALL_PATH.html)
+fn operation(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let segment = path.strip_prefix(CALL_PATH)?;
+    let op = percent_decode(segment)?;
+    if op.is_empty() || op.contains('/') {
+        return None;
+    }
+    Some(op)
+}
+
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut input = segment.bytes();
+    while let Some(b) = input.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+        let hex = [input.next()?, input.next()?];
+        let hex = std::str::from_utf8(&hex).ok()?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn respond(request: Request, status: u16, body: Vec<u8>) {
+    if let Err(e) = request.respond(Response::from_data(body).with_status_code(status)) {
+        warn!("Failed to send HTTP response: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{echo_guest, MockEngine};
+    use std::io::Write;
+    use std::net::TcpStream;
+
+    fn post(addr: SocketAddr, path: &str, body: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            path,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn maps_requests_to_guest_calls() {
+        let server = serve("127.0.0.1:0", 2, || {
+            WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let ok = post(addr, "/call/echo", b"hello");
+        assert!(ok.starts_with("HTTP/1.1 200"));
+        assert!(ok.ends_with("hello"));
+        assert!(post(addr, "/other", b"").starts_with("HTTP/1.1 404"));
+
+        server.stop();
+    }
+
+    #[test]
+    fn operations_are_decoded_without_the_query_string() {
+        let server = serve("127.0.0.1:0", 1, || {
+            let named = |state: &crate::ModuleState| {
+                let op = state.get_guest_request().unwrap().operation;
+                state.set_guest_response(op.as_bytes().to_vec());
+                1
+            };
+            WapcHost::new(MockEngine::boxed(named), |_, _, _, _, _| Ok(vec![]))
+        })
+        .unwrap();
+        let addr = server.local_addr().unwrap();
+
+        assert!(post(addr, "/call/echo?trace=1", b"").ends_with("\r\n\r\necho"));
+        assert!(post(addr, "/call/say%20hi", b"").ends_with("\r\n\r\nsay hi"));
+        for path in ["/call/", "/call/?trace=1", "/call/a/b", "/call/a%2Fb", "/call/%zz"] {
+            assert!(post(addr, path, b"").starts_with("HTTP/1.1 404"), "{}", path);
+        }
+
+        server.stop();
+    }
+}
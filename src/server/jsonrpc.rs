@@ -0,0 +1,187 @@
+//! A line-delimited JSON-RPC 2.0 frontend, for driving a host from scripts and CI jobs over
+//! stdin and stdout. Requires the `json-rpc` feature.
+//!
+//! Each line of input is a request to the `call` method, whose params name the operation and
+//! carry the payload as base64:
+//!
+//! ```text
+//! {"jsonrpc":"2.0","id":1,"method":"call","params":{"operation":"echo","payload":"aGk="}}
+//! ```
+//!
+//! and each request is answered with one line of output, either a result holding the base64
+//! response or an error:
+//!
+//! ```text
+//! {"jsonrpc":"2.0","id":1,"result":{"payload":"aGk="}}
+//! {"jsonrpc":"2.0","id":2,"error":{"code":-32000,"message":"Guest call failure: ..."}}
+//! ```
+//!
+//! Failed guest calls use the [CALL_FAILED](constant.CALL_FAILED.html) code; the other codes
+//! are the standard JSON-RPC ones. Notifications, which have no `id`, are invoked but never
+//! answered.
+//!
+//! The `wapc-run` binary, built with the `wapc-run` feature, serves a module file this way
+//! using a bundled interpreter: `wapc-run guest.wasm < requests.jsonl`. Embedders who want the
+//! same runner with an engine provider of their choosing call [run_file](fn.run_file.html)
+//! from a `main` of their own.
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::errors::{self, ErrorKind};
+use crate::{WapcHost, WapcHostBuilder, WebAssemblyEngineProvider};
+
+/// The method that invokes a guest operation
+pub const CALL_METHOD: &str = "call";
+
+/// The error code of a request that is not valid JSON
+pub const PARSE_ERROR: i64 = -32700;
+
+/// The error code of a request that is not a valid JSON-RPC request
+pub const INVALID_REQUEST: i64 = -32600;
+
+/// The error code of a request for a method other than `call`
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The error code of a request whose params are missing or malformed
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// The error code of a request whose guest call failed
+pub const CALL_FAILED: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    id: Option<Value>,
+    method: String,
+    params: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct CallParams {
+    operation: String,
+    #[serde(default)]
+    payload: String,
+}
+
+/// Serves requests read from stdin, writing responses to stdout, until stdin is closed
+pub fn serve_stdio(host: &WapcHost) -> std::io::Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    serve(host, stdin.lock(), stdout.lock())
+}
+
+/// Loads the module at `path` into a host whose engine provider `engine` creates from the
+/// module's bytes, and serves requests for it over stdin and stdout until stdin is closed. The
+/// host has no host callback, so host calls made by the guest fail.
+pub fn run_file(
+    path: impl AsRef<Path>,
+    engine: impl FnOnce(&[u8]) -> Box<dyn WebAssemblyEngineProvider>,
+) -> crate::Result<()> {
+    let host = WapcHostBuilder::new().build_from_file(path, engine)?;
+    serve_stdio(&host).map_err(|e| errors::new(ErrorKind::IO(e)))
+}
+
+/// Serves requests read from `input`, writing responses to `output`, until `input` is exhausted
+pub fn serve(host: &WapcHost, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle(host, &line) {
+            serde_json::to_writer(&mut output, &response)?;
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn handle(host: &WapcHost, line: &str) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return Some(error(Value::Null, PARSE_ERROR, e.to_string())),
+    };
+    let request: Request = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => return Some(error(Value::Null, INVALID_REQUEST, e.to_string())),
+    };
+    let result = call(host, &request);
+    let id = request.id?;
+    Some(match result {
+        Ok(payload) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": { "payload": STANDARD.encode(payload) },
+        }),
+        Err((code, message)) => error(id, code, message),
+    })
+}
+
+fn call(host: &WapcHost, request: &Request) -> Result<Vec<u8>, (i64, String)> {
+    if request.jsonrpc != "2.0" {
+        return Err((INVALID_REQUEST, "Only JSON-RPC 2.0 is supported".to_string()));
+    }
+    if request.method != CALL_METHOD {
+        return Err((METHOD_NOT_FOUND, format!("No such method: {}", request.method)));
+    }
+    let params: CallParams = request
+        .params
+        .clone()
+        .ok_or_else(|| "Missing params".to_string())
+        .and_then(|p| serde_json::from_value(p).map_err(|e| e.to_string()))
+        .map_err(|e| (INVALID_PARAMS, e))?;
+    let payload = STANDARD
+        .decode(&params.payload)
+        .map_err(|e| (INVALID_PARAMS, format!("Payload is not valid base64: {}", e)))?;
+    host.call(&params.operation, &payload)
+        .map_err(|e| (CALL_FAILED, e.to_string()))
+}
+
+fn error(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{echo_guest, MockEngine};
+
+    #[test]
+    fn answers_each_request_line() {
+        let host =
+            WapcHost::new(MockEngine::boxed(echo_guest), |_, _, _, _, _| Ok(vec![])).unwrap();
+        let input = concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"call","#,
+            r#""params":{"operation":"echo","payload":"aGk="}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","method":"call","params":{"operation":"echo"}}"#,
+            "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"load"}"#,
+            "\nnot json\n",
+        );
+        let mut output = Vec::new();
+        serve(&host, input.as_bytes(), &mut output).unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["result"]["payload"], "aGk=");
+        assert_eq!(responses[1]["id"], 2);
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["error"]["code"], PARSE_ERROR);
+    }
+}
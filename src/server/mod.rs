@@ -0,0 +1,13 @@
+//! Frontends that expose guest operations to other processes
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+#[cfg(feature = "http-server")]
+pub mod http;
+
+#[cfg(feature = "json-rpc")]
+pub mod jsonrpc;
+
+#[cfg(feature = "nats")]
+pub mod nats;
@@ -0,0 +1,211 @@
+//! A bridge that serves guest operations to NATS requests. Requires the `nats` feature.
+//!
+//! The bridge subscribes to `wapc.{module}.*` and invokes the operation named by the last token
+//! of each message's subject, so a message published to `wapc.echo.say_hello` calls `say_hello` on
+//! the `echo` module with the message payload. The response is published to the message's reply
+//! subject, if it has one. Failures are reported the way NATS services report them, with an
+//! empty body and the `Nats-Service-Error` and `Nats-Service-Error-Code` headers; the code is
+//! the HTTP status of the guest error's [class](../../guest_error/index.html), or 500.
+//!
+//! Passing a queue group lets several bridges, in one process or many, share the load of a
+//! module's subjects, with each message delivered to only one of them.
+//!
+//! ```ignore
+//! let bridge = wapc::server::nats::serve("nats://localhost:4222", "echo", Some("echo"), 4, || {
+//!     WapcHost::new(Box::new(MyEngineProvider::new(&module)), host_callback)
+//! })?;
+//! ```
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use async_nats::{Client, HeaderMap, Message};
+use futures::StreamExt;
+use tokio::runtime::Runtime;
+
+use crate::errors::{self, ErrorKind};
+use crate::{Result, WapcHost};
+
+/// The root of every subject served by a bridge
+pub const SUBJECT_ROOT: &str = "wapc";
+
+/// The header carrying the error message of a failed call
+pub const ERROR_HEADER: &str = "Nats-Service-Error";
+
+/// The header carrying the status code of a failed call
+pub const ERROR_CODE_HEADER: &str = "Nats-Service-Error-Code";
+
+/// A running bridge, which stops when [stop](#method.stop) is called
+pub struct NatsBridge {
+    runtime: Runtime,
+    forwarder: tokio::task::JoinHandle<()>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Connects to the NATS server at `url` and serves the operations of `module` with `workers`
+/// worker threads, each of which creates its own host with `factory`. When `queue_group` is
+/// given the bridge joins that queue group instead of receiving every message.
+pub fn serve(
+    url: &str,
+    module: &str,
+    queue_group: Option<&str>,
+    workers: usize,
+    factory: impl Fn() -> Result<WapcHost> + Send + Sync + 'static,
+) -> Result<NatsBridge> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .map_err(|e| errors::new(ErrorKind::IO(e)))?;
+    let prefix = format!("{}.{}.", SUBJECT_ROOT, module);
+    let subject = format!("{}*", prefix);
+    let (client, mut subscriber) = runtime.block_on(async {
+        let client = async_nats::connect(url).await.map_err(io_error)?;
+        let subscriber = match queue_group {
+            Some(group) => client.queue_subscribe(subject, group.to_string()).await,
+            None => client.subscribe(subject).await,
+        }
+        .map_err(io_error)?;
+        Ok::<_, errors::Error>((client, subscriber))
+    })?;
+
+    let (tx, rx) = mpsc::channel();
+    let forwarder = runtime.spawn(async move {
+        while let Some(message) = subscriber.next().await {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let rx = Arc::new(Mutex::new(rx));
+    let factory = Arc::new(factory);
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let handles = (0..workers.max(1))
+        .map(|_| {
+            let worker = Worker {
+                rx: rx.clone(),
+                client: client.clone(),
+                runtime: runtime.handle().clone(),
+                prefix: prefix.clone(),
+            };
+            let factory = factory.clone();
+            let ready = ready_tx.clone();
+            thread::spawn(move || match factory() {
+                Ok(host) => {
+                    let _ = ready.send(Ok(()));
+                    worker.run(&host);
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                }
+            })
+        })
+        .collect();
+    let bridge = NatsBridge {
+        runtime,
+        forwarder,
+        workers: handles,
+    };
+    for _ in 0..bridge.workers.len() {
+        if let Ok(Err(e)) = ready_rx.recv() {
+            bridge.stop();
+            return Err(e);
+        }
+    }
+    Ok(bridge)
+}
+
+impl NatsBridge {
+    /// Unsubscribes, waits for messages already received to be served, and drops the workers'
+    /// hosts
+    pub fn stop(self) {
+        self.forwarder.abort();
+        // Wait for the forwarder to drop its sender, which lets the workers drain and exit
+        let _ = self.runtime.block_on(self.forwarder);
+        for worker in self.workers {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Worker {
+    rx: Arc<Mutex<Receiver<Message>>>,
+    client: Client,
+    runtime: tokio::runtime::Handle,
+    prefix: String,
+}
+
+impl Worker {
+    fn run(&self, host: &WapcHost) {
+        loop {
+            let message = match self.rx.lock().unwrap().recv() {
+                Ok(message) => message,
+                Err(_) => return,
+            };
+            let result = match operation(&message.subject, &self.prefix) {
+                Some(op) => host.call(op, &message.payload),
+                None => Err(errors::new(ErrorKind::NoSuchFunction(
+                    message.subject.to_string(),
+                ))),
+            };
+            let reply = match message.reply {
+                Some(reply) => reply,
+                None => continue,
+            };
+            let published = self.runtime.block_on(async {
+                match result {
+                    Ok(response) => self.client.publish(reply, response.into()).await,
+                    Err(e) => {
+                        let status = e.guest_error().map_or(500, |g| g.class.http_status());
+                        let mut headers = HeaderMap::new();
+                        headers.insert(ERROR_HEADER, header_value(&e.to_string()).as_str());
+                        headers.insert(ERROR_CODE_HEADER, status.to_string().as_str());
+                        let body = Vec::new().into();
+                        self.client.publish_with_headers(reply, headers, body).await
+                    }
+                }
+            });
+            if let Err(e) = published {
+                warn!("Failed to publish NATS reply: {}", e);
+            }
+        }
+    }
+}
+
+/// Returns the operation named by `subject`, which must be `prefix` followed by a single token
+fn operation<'a>(subject: &'a str, prefix: &str) -> Option<&'a str> {
+    subject
+        .strip_prefix(prefix)
+        .filter(|op| !op.is_empty() && !op.contains('.'))
+}
+
+/// Replaces the line breaks in a guest-controlled message, which would otherwise end the
+/// header and let the guest inject headers of its own
+fn header_value(message: &str) -> String {
+    message.replace(['\r', '\n'], " ")
+}
+
+fn io_error(e: impl std::fmt::Display) -> errors::Error {
+    errors::new(ErrorKind::IO(std::io::Error::other(e.to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subjects_name_operations() {
+        assert_eq!(operation("wapc.echo.say_hello", "wapc.echo."), Some("say_hello"));
+        assert_eq!(operation("wapc.echo.", "wapc.echo."), None);
+        assert_eq!(operation("wapc.echo.a.b", "wapc.echo."), None);
+        assert_eq!(operation("wapc.other.op", "wapc.echo."), None);
+    }
+
+    #[test]
+    fn error_headers_cannot_be_injected() {
+        let value = header_value("failed\r\nNats-Service-Error-Code: 200");
+        assert_eq!(value, "failed  Nats-Service-Error-Code: 200");
+    }
+}
@@ -0,0 +1,48 @@
+//! Graceful shutdown of a host, for clean service restarts.
+//!
+//! A [ShutdownHandle](struct.ShutdownHandle.html) can be sent to another thread (such as a
+//! signal handler) to close the host that owns it: subsequent calls fail with
+//! [HostClosed](../errors/enum.ErrorKind.html#variant.HostClosed), an in-flight call is given
+//! until the timeout to finish, and is then interrupted through the engine provider's
+//! [InterruptHandle](../trait.InterruptHandle.html) if it supplies one.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{InterruptHandle, ModuleState};
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A thread-safe handle for closing a host
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    pub(crate) state: Arc<ModuleState>,
+    pub(crate) interrupt: Option<Arc<dyn InterruptHandle>>,
+}
+
+impl ShutdownHandle {
+    /// Marks the host closed and waits up to `timeout` for an in-flight call to finish. If the
+    /// call is still running after the timeout, it is interrupted when the engine provider
+    /// supports it. Returns true if the host drained without being interrupted.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        self.state.closed.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        while self.state.busy.load(Ordering::SeqCst) {
+            if Instant::now() >= deadline {
+                if let Some(ref interrupt) = self.interrupt {
+                    interrupt.interrupt();
+                }
+                return false;
+            }
+            thread::sleep(DRAIN_POLL_INTERVAL);
+        }
+        true
+    }
+
+    /// Whether the host has been closed
+    pub fn is_closed(&self) -> bool {
+        self.state.closed.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,160 @@
+//! Lightweight per-operation call statistics, enabled with
+//! [WapcHostBuilder::collect_stats](../struct.WapcHostBuilder.html#method.collect_stats) and
+//! read through [WapcHost::stats](../struct.WapcHost.html#method.stats).
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The number of most recent latencies per operation used to compute percentiles
+const LATENCY_SAMPLES: usize = 1024;
+
+/// Statistics for a single operation since startup or the last reset
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OperationStats {
+    pub calls: u64,
+    pub errors: u64,
+    /// Total request payload bytes sent to the guest
+    pub bytes_in: u64,
+    /// Total response payload bytes returned by the guest
+    pub bytes_out: u64,
+    pub mean_latency: Duration,
+    /// Latency percentiles over the most recent calls
+    pub p50_latency: Duration,
+    pub p95_latency: Duration,
+    pub p99_latency: Duration,
+}
+
+/// A point-in-time copy of a host's call statistics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    /// How long statistics have been collected for
+    pub elapsed: Duration,
+    pub operations: HashMap<String, OperationStats>,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    calls: u64,
+    errors: u64,
+    bytes_in: u64,
+    bytes_out: u64,
+    total_latency: Duration,
+    samples: VecDeque<Duration>,
+}
+
+/// The statistics collected by a host
+pub struct CallStats {
+    since: Mutex<Instant>,
+    operations: Mutex<HashMap<String, Accumulator>>,
+}
+
+impl CallStats {
+    pub(crate) fn new() -> CallStats {
+        CallStats {
+            since: Mutex::new(Instant::now()),
+            operations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        op: &str,
+        bytes_in: usize,
+        bytes_out: Option<usize>,
+        latency: Duration,
+    ) {
+        let mut operations = self.operations.lock().unwrap();
+        let acc = match operations.get_mut(op) {
+            Some(acc) => acc,
+            None => operations.entry(op.to_string()).or_default(),
+        };
+        acc.calls += 1;
+        acc.bytes_in += bytes_in as u64;
+        match bytes_out {
+            Some(n) => acc.bytes_out += n as u64,
+            None => acc.errors += 1,
+        }
+        acc.total_latency += latency;
+        if acc.samples.len() == LATENCY_SAMPLES {
+            acc.samples.pop_front();
+        }
+        acc.samples.push_back(latency);
+    }
+
+    /// Returns a copy of the statistics collected so far
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let operations = self.operations.lock().unwrap();
+        StatsSnapshot {
+            elapsed: self.since.lock().unwrap().elapsed(),
+            operations: operations
+                .iter()
+                .map(|(op, acc)| {
+                    let mut sorted: Vec<Duration> = acc.samples.iter().cloned().collect();
+                    sorted.sort_unstable();
+                    let percentile = |p: usize| {
+                        sorted
+                            .get((sorted.len() * p / 100).min(sorted.len().saturating_sub(1)))
+                            .cloned()
+                            .unwrap_or_default()
+                    };
+                    let stats = OperationStats {
+                        calls: acc.calls,
+                        errors: acc.errors,
+                        bytes_in: acc.bytes_in,
+                        bytes_out: acc.bytes_out,
+                        mean_latency: mean(acc.total_latency, acc.calls),
+                        p50_latency: percentile(50),
+                        p95_latency: percentile(95),
+                        p99_latency: percentile(99),
+                    };
+                    (op.clone(), stats)
+                })
+                .collect(),
+        }
+    }
+
+    /// Discards all statistics collected so far
+    pub fn reset(&self) {
+        self.operations.lock().unwrap().clear();
+        *self.since.lock().unwrap() = Instant::now();
+    }
+}
+
+/// The mean of `calls` latencies adding up to `total`, computed in nanoseconds so that call
+/// counts beyond `u32::MAX` neither truncate nor divide by zero
+fn mean(total: Duration, calls: u64) -> Duration {
+    let nanos = total.as_nanos() / u128::from(calls.max(1));
+    Duration::from_nanos(nanos as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_per_operation() {
+        let stats = CallStats::new();
+        for ms in 1..=100 {
+            stats.record("get", 10, Some(20), Duration::from_millis(ms));
+        }
+        stats.record("put", 5, None, Duration::from_millis(7));
+
+        let snapshot = stats.snapshot();
+        let get = &snapshot.operations["get"];
+        assert_eq!((get.calls, get.errors, get.bytes_in, get.bytes_out), (100, 0, 1000, 2000));
+        assert_eq!(get.p50_latency, Duration::from_millis(51));
+        assert_eq!(get.p99_latency, Duration::from_millis(100));
+        assert_eq!(snapshot.operations["put"].errors, 1);
+
+        stats.reset();
+        assert!(stats.snapshot().operations.is_empty());
+    }
+
+    #[test]
+    fn mean_survives_call_counts_beyond_u32() {
+        let calls = 1u64 << 32;
+        assert_eq!(mean(Duration::from_secs(calls * 2), calls), Duration::from_secs(2));
+        assert_eq!(mean(Duration::from_millis(7), 0), Duration::from_millis(7));
+    }
+}
@@ -0,0 +1,56 @@
+//! Numeric status codes for host call failures.
+//!
+//! `__host_call` reports only success (1) or failure (0), leaving the guest to fetch and parse
+//! the error message to learn why a call failed. The optional `__host_call_status` export takes
+//! the same parameters but returns a status code chosen by the host callback, so guests can
+//! branch (e.g. on "not found" versus "internal error") without decoding the error. The error
+//! message remains available through `__host_error` either way.
+//!
+//! A host callback chooses the code by returning a [StatusError](struct.StatusError.html).
+//! Any other error is reported as [STATUS_ERROR](constant.STATUS_ERROR.html).
+
+use std::error::Error;
+use std::fmt;
+
+/// The status of a successful host call
+pub const STATUS_OK: i32 = 1;
+
+/// The status of a failed host call whose callback did not choose a code
+pub const STATUS_ERROR: i32 = 0;
+
+/// A host callback error carrying the status code reported to the guest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusError {
+    code: i32,
+    message: String,
+}
+
+impl StatusError {
+    /// Creates an error with the given status code. [STATUS_OK](constant.STATUS_OK.html) cannot
+    /// signal a failure, so it is reported as [STATUS_ERROR](constant.STATUS_ERROR.html) instead.
+    pub fn new(code: i32, message: impl Into<String>) -> StatusError {
+        StatusError {
+            code: if code == STATUS_OK { STATUS_ERROR } else { code },
+            message: message.into(),
+        }
+    }
+
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for StatusError {}
+
+/// The status code to report for a failed host call
+pub(crate) fn of(error: &(dyn Error + Send + Sync + 'static)) -> i32 {
+    error
+        .downcast_ref::<StatusError>()
+        .map_or(STATUS_ERROR, StatusError::code)
+}
@@ -0,0 +1,31 @@
+//! Incremental delivery of guest output to the host, for guests producing outputs too large to
+//! buffer in full before calling `__guest_response`.
+//!
+//! A guest opens a named stream with `__host_stream_open`, writes chunks with
+//! `__host_stream_write`, and finishes with `__host_stream_close`. Each stream is delivered to a
+//! [StreamWriter](trait.StreamWriter.html) created by the host's registered
+//! [StreamSink](trait.StreamSink.html). When any of these functions fails it returns 0 and the
+//! failure is available to the guest through `__host_error_len` and `__host_error`.
+
+use std::error::Error;
+
+/// Creates a writer for each stream a guest opens. Register a sink with
+/// [WapcHostBuilder::stream_sink](../struct.WapcHostBuilder.html#method.stream_sink).
+pub trait StreamSink: Send + Sync {
+    /// Called when the guest opens a stream with the given name
+    fn open(
+        &self,
+        module_id: u64,
+        name: &str,
+    ) -> std::result::Result<Box<dyn StreamWriter>, Box<dyn Error + Send + Sync>>;
+}
+
+/// Receives the chunks of a single stream. A writer that is dropped without `close` being
+/// called belongs to a stream the guest abandoned, e.g. because its call failed.
+pub trait StreamWriter: Send {
+    /// Called for every chunk the guest writes, in order
+    fn write(&mut self, chunk: &[u8]) -> std::result::Result<(), Box<dyn Error + Send + Sync>>;
+
+    /// Called when the guest closes the stream
+    fn close(self: Box<Self>) -> std::result::Result<(), Box<dyn Error + Send + Sync>>;
+}
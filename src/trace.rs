@@ -0,0 +1,129 @@
+//! Propagation of W3C [trace context](https://www.w3.org/TR/trace-context/) through guest
+//! calls, so distributed traces continue across the wasm boundary.
+//!
+//! A call made with
+//! [WapcHost::call_with_context](../struct.WapcHost.html#method.call_with_context) carries a
+//! [TraceContext](struct.TraceContext.html). While it runs, every host call the guest makes
+//! sees the context in [HostCallContext::trace](../struct.HostCallContext.html#structfield.trace),
+//! so a host callback can attach it to the outbound requests it makes on the guest's behalf.
+//!
+//! Guests read the context by making a host call to the `context` operation in the
+//! `wapc:trace` namespace. The host answers it itself with the `traceparent` header, followed
+//! by a newline and the `tracestate` header if there is one. The response is empty when the
+//! call carries no context.
+
+use std::fmt;
+
+use crate::errors::{self, ErrorKind};
+use crate::Result;
+
+/// The namespace of host calls answered by the host's trace context propagation
+pub const TRACE_NAMESPACE: &str = "wapc:trace";
+
+/// The operation a guest invokes in the `wapc:trace` namespace to read its trace context
+pub const CONTEXT_OPERATION: &str = "context";
+
+/// A W3C trace context: a `traceparent` header and an optional `tracestate` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    traceparent: String,
+    tracestate: Option<String>,
+}
+
+fn is_lower_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+impl TraceContext {
+    /// Parses a `traceparent` header, e.g.
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`. Fails with
+    /// `ErrorKind::InvalidTraceContext` if the header is malformed or its trace or parent ID is
+    /// all zeros.
+    pub fn parse(traceparent: &str) -> Result<TraceContext> {
+        let invalid = || errors::new(ErrorKind::InvalidTraceContext(traceparent.to_string()));
+        let fields: Vec<&str> = traceparent.trim().split('-').collect();
+        match fields.as_slice() {
+            [version, trace_id, parent_id, flags]
+                if is_lower_hex(version, 2)
+                    && *version != "ff"
+                    && is_lower_hex(trace_id, 32)
+                    && is_lower_hex(parent_id, 16)
+                    && is_lower_hex(flags, 2)
+                    && trace_id.bytes().any(|b| b != b'0')
+                    && parent_id.bytes().any(|b| b != b'0') =>
+            {
+                Ok(TraceContext {
+                    traceparent: traceparent.trim().to_string(),
+                    tracestate: None,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Attaches a vendor-specific `tracestate` header
+    pub fn with_tracestate(mut self, tracestate: &str) -> TraceContext {
+        self.tracestate = Some(tracestate.to_string());
+        self
+    }
+
+    /// The `traceparent` header
+    pub fn traceparent(&self) -> &str {
+        &self.traceparent
+    }
+
+    /// The `tracestate` header, if any
+    pub fn tracestate(&self) -> Option<&str> {
+        self.tracestate.as_deref()
+    }
+
+    /// The 32 hex digit ID of the trace
+    pub fn trace_id(&self) -> &str {
+        &self.traceparent[3..35]
+    }
+
+    /// The 16 hex digit ID of the caller's span
+    pub fn parent_id(&self) -> &str {
+        &self.traceparent[36..52]
+    }
+
+    /// Whether the caller recorded its span
+    pub fn sampled(&self) -> bool {
+        u8::from_str_radix(&self.traceparent[53..55], 16).unwrap_or(0) & 1 == 1
+    }
+
+    /// Encodes the context as the response to a `wapc:trace` `context` host call
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        match self.tracestate {
+            Some(ref state) => format!("{}\n{}", self.traceparent, state).into_bytes(),
+            None => self.traceparent.clone().into_bytes(),
+        }
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.traceparent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_traceparent() {
+        let ctx = TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+            .unwrap()
+            .with_tracestate("congo=t61rcWkgMzE");
+        assert_eq!(ctx.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(ctx.parent_id(), "00f067aa0ba902b7");
+        assert!(ctx.sampled());
+        assert!(ctx.encode().ends_with(b"-01\ncongo=t61rcWkgMzE"));
+
+        let zero_trace = "00-00000000000000000000000000000000-00f067aa0ba902b7-01";
+        assert!(TraceContext::parse(zero_trace).is_err());
+        assert!(TraceContext::parse("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01")
+            .is_err());
+    }
+}
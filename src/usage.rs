@@ -0,0 +1,93 @@
+//! Per-module resource accounting, read through
+//! [WapcHost::resource_report](../struct.WapcHost.html#method.resource_report).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// The resources used by a module over the lifetime of its host, for billing and capacity
+/// planning
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceReport {
+    pub module_id: u64,
+    /// The size of the guest's linear memory in 64 KiB pages, if the engine exposes it
+    pub memory_pages: Option<u32>,
+    /// The number of elements in each of the guest's tables, if the engine exposes them
+    pub table_sizes: Option<Vec<u32>>,
+    /// The fuel consumed by calls made with
+    /// [call_with_fuel](../struct.WapcHost.html#method.call_with_fuel)
+    pub fuel_consumed: u64,
+    /// The number of guest calls, including failed ones
+    pub calls: u64,
+    /// The number of host calls made by the guest
+    pub host_calls: u64,
+    /// Total bytes passed into the guest, as call payloads and host call responses
+    pub bytes_in: u64,
+    /// Total bytes passed out of the guest, as call responses and host call payloads
+    pub bytes_out: u64,
+    /// How long ago the host was created
+    pub uptime: Duration,
+}
+
+/// The counters behind a [ResourceReport], kept in the module state so that host calls are
+/// counted wherever the engine makes them
+pub(crate) struct UsageCounters {
+    created: Instant,
+    calls: AtomicU64,
+    host_calls: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl Default for UsageCounters {
+    fn default() -> UsageCounters {
+        UsageCounters {
+            created: Instant::now(),
+            calls: AtomicU64::new(0),
+            host_calls: AtomicU64::new(0),
+            bytes_in: AtomicU64::new(0),
+            bytes_out: AtomicU64::new(0),
+        }
+    }
+}
+
+impl UsageCounters {
+    pub(crate) fn call(&self, payload: usize) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in.fetch_add(payload as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn host_call(&self, payload: usize) {
+        self.host_calls.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out.fetch_add(payload as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_in(&self, len: usize) {
+        self.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_out(&self, len: usize) {
+        self.bytes_out.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn report(
+        &self,
+        module_id: u64,
+        memory_pages: Option<u32>,
+        table_sizes: Option<Vec<u32>>,
+        fuel_consumed: u64,
+    ) -> ResourceReport {
+        ResourceReport {
+            module_id,
+            memory_pages,
+            table_sizes,
+            fuel_consumed,
+            calls: self.calls.load(Ordering::Relaxed),
+            host_calls: self.host_calls.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+            uptime: self.created.elapsed(),
+        }
+    }
+}
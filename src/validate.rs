@@ -0,0 +1,288 @@
+//! Inspection of a module's imports and exports before instantiation, so orchestrators can
+//! reject modules that are not waPC-compliant with an actionable message instead of paying the
+//! cost of instantiating them.
+
+use wasmparser::{ExternalKind, Parser, Payload, TypeRef, Validator, WasmFeatures};
+
+use crate::errors::{self, ErrorKind};
+use crate::{EngineSettings, Result, WapcFunctions, HOST_NAMESPACE};
+
+const WASI_MODULES: [&str; 2] = ["wasi_snapshot_preview1", "wasi_unstable"];
+
+const HOST_EXPORTS: [&str; 17] = [
+    WapcFunctions::HOST_CONSOLE_LOG,
+    WapcFunctions::HOST_CALL,
+    WapcFunctions::GUEST_REQUEST_FN,
+    WapcFunctions::HOST_RESPONSE_FN,
+    WapcFunctions::HOST_RESPONSE_LEN_FN,
+    WapcFunctions::GUEST_RESPONSE_FN,
+    WapcFunctions::GUEST_ERROR_FN,
+    WapcFunctions::HOST_ERROR_FN,
+    WapcFunctions::HOST_ERROR_LEN_FN,
+    WapcFunctions::GUEST_DEFER_FN,
+    WapcFunctions::HOST_STREAM_OPEN_FN,
+    WapcFunctions::HOST_STREAM_WRITE_FN,
+    WapcFunctions::HOST_STREAM_CLOSE_FN,
+    WapcFunctions::EMIT_EVENT_FN,
+    WapcFunctions::HOST_CALL_STATUS_FN,
+    WapcFunctions::HOST_TIME_MS_FN,
+    WapcFunctions::HOST_MONOTONIC_MS_FN,
+];
+
+/// Imports whose results vary between executions, which deterministic hosts must refuse
+const NONDETERMINISTIC_WASI: [&str; 4] =
+    ["clock_time_get", "clock_res_get", "random_get", "poll_oneoff"];
+const NONDETERMINISTIC_WAPC: [&str; 2] = [
+    WapcFunctions::HOST_TIME_MS_FN,
+    WapcFunctions::HOST_MONOTONIC_MS_FN,
+];
+
+/// A function imported by a module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+}
+
+/// The limits of a module's linear memory, in 64KiB pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimits {
+    pub initial_pages: u64,
+    pub maximum_pages: Option<u64>,
+    pub shared: bool,
+    /// Whether the memory is imported from the host rather than defined by the module
+    pub imported: bool,
+}
+
+/// What a module requires of and offers to a waPC host
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleReport {
+    /// Functions imported from the `wapc` module
+    pub wapc_imports: Vec<String>,
+    /// Functions imported from a WASI module
+    pub wasi_imports: Vec<String>,
+    /// Functions imported from any other module, which must be linked as additional host functions
+    pub other_imports: Vec<Import>,
+    /// Names of all exported functions
+    pub exports: Vec<String>,
+    /// The module's linear memory, if it has one
+    pub memory: Option<MemoryLimits>,
+    /// Whether the module declares a start section
+    pub start_section: bool,
+    /// The initialization functions the host will call (`_start`, `wapc_init`) that are exported
+    pub start_functions: Vec<String>,
+    /// Reasons the module cannot be hosted. Empty for compatible modules.
+    pub problems: Vec<String>,
+}
+
+impl ModuleReport {
+    /// Whether the module can be hosted as a waPC guest
+    pub fn is_compatible(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Whether the module must be run with WASI enabled
+    pub fn requires_wasi(&self) -> bool {
+        !self.wasi_imports.is_empty()
+    }
+
+    /// The WASI and waPC imports that would make execution nondeterministic. A host built in
+    /// [deterministic](../struct.WapcHostBuilder.html#method.deterministic) mode should reject
+    /// modules for which this is not empty. Imports linked as additional host functions are
+    /// the embedder's responsibility.
+    pub fn nondeterministic_imports(&self) -> Vec<String> {
+        let wasi = self
+            .wasi_imports
+            .iter()
+            .filter(|name| NONDETERMINISTIC_WASI.contains(&name.as_str()));
+        let wapc = self
+            .wapc_imports
+            .iter()
+            .filter(|name| NONDETERMINISTIC_WAPC.contains(&name.as_str()));
+        wasi.chain(wapc).cloned().collect()
+    }
+}
+
+/// Parses a WebAssembly module and reports which waPC imports and exports are present, its WASI
+/// requirements, memory limits and start functions. Returns an error only if the bytes are not a
+/// well-formed module; incompatibilities are listed in the report's `problems`.
+pub fn validate_module(bytes: &[u8]) -> Result<ModuleReport> {
+    let mut report = ModuleReport::default();
+    if crate::binary::binary_kind(bytes) == Some(crate::binary::BinaryKind::Component) {
+        report.problems.push(
+            "Binary is a component; it must be hosted by an engine provider with component support"
+                .to_string(),
+        );
+        return Ok(report);
+    }
+
+    for payload in Parser::new(0).parse_all(bytes) {
+        match payload.map_err(invalid)? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    let import = import.map_err(invalid)?;
+                    match import.ty {
+                        TypeRef::Func(_) => {
+                            if import.module == HOST_NAMESPACE {
+                                report.wapc_imports.push(import.name.to_string());
+                            } else if WASI_MODULES.contains(&import.module) {
+                                report.wasi_imports.push(import.name.to_string());
+                            } else {
+                                report.other_imports.push(Import {
+                                    module: import.module.to_string(),
+                                    name: import.name.to_string(),
+                                });
+                            }
+                        }
+                        TypeRef::Memory(ty) => report.memory = Some(limits(ty, true)),
+                        _ => {}
+                    }
+                }
+            }
+            Payload::MemorySection(reader) => {
+                for memory in reader {
+                    report.memory = Some(limits(memory.map_err(invalid)?, false));
+                }
+            }
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export.map_err(invalid)?;
+                    if export.kind == ExternalKind::Func {
+                        report.exports.push(export.name.to_string());
+                    }
+                }
+            }
+            Payload::StartSection { .. } => report.start_section = true,
+            _ => {}
+        }
+    }
+
+    report.start_functions = WapcFunctions::REQUIRED_STARTS
+        .iter()
+        .filter(|f| report.exports.iter().any(|e| e == *f))
+        .map(|f| f.to_string())
+        .collect();
+
+    if !report.exports.iter().any(|e| e == WapcFunctions::GUEST_CALL) {
+        report.problems.push(format!(
+            "Module does not export {}; it was not built with a waPC guest SDK",
+            WapcFunctions::GUEST_CALL
+        ));
+    }
+    for name in &report.wapc_imports {
+        if !HOST_EXPORTS.contains(&name.as_str()) {
+            report.problems.push(format!(
+                "Module imports {}.{}, which is not a waPC host function",
+                HOST_NAMESPACE, name
+            ));
+        }
+    }
+    if report.memory.is_none() {
+        report
+            .problems
+            .push("Module has no linear memory to exchange payloads through".to_string());
+    }
+
+    Ok(report)
+}
+
+/// Checks that `bytes` does not need a WebAssembly feature disabled in `settings`, returning a
+/// [FeatureNotEnabled](../errors/enum.ErrorKind.html#variant.FeatureNotEnabled) error naming
+/// the first one it needs. Engine providers call this before compiling the module so that the
+/// failure is explained, rather than surfacing as an opaque compilation error.
+pub fn check_features(bytes: &[u8], settings: &EngineSettings) -> Result<()> {
+    let all = WasmFeatures::default();
+    Validator::new_with_features(all)
+        .validate_all(bytes)
+        .map_err(invalid)?;
+    let disabled = [
+        ("threads", !settings.threads, WasmFeatures::THREADS),
+        ("simd", !settings.simd, WasmFeatures::SIMD | WasmFeatures::RELAXED_SIMD),
+        ("relaxed_simd", !settings.relaxed_simd, WasmFeatures::RELAXED_SIMD),
+        ("multi_memory", !settings.multi_memory, WasmFeatures::MULTI_MEMORY),
+    ];
+    for (name, _, feature) in disabled.iter().filter(|(_, off, _)| *off) {
+        if Validator::new_with_features(all.difference(*feature))
+            .validate_all(bytes)
+            .is_err()
+        {
+            return Err(errors::new(ErrorKind::FeatureNotEnabled(name.to_string())));
+        }
+    }
+    Ok(())
+}
+
+fn limits(ty: wasmparser::MemoryType, imported: bool) -> MemoryLimits {
+    MemoryLimits {
+        initial_pages: ty.initial,
+        maximum_pages: ty.maximum,
+        shared: ty.shared,
+        imported,
+    }
+}
+
+fn invalid(e: wasmparser::BinaryReaderError) -> errors::Error {
+    errors::new(ErrorKind::WasmMisc(format!("Invalid WebAssembly module: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_bytes_are_rejected() {
+        assert!(validate_module(b"not wasm").is_err());
+    }
+
+    #[test]
+    fn disabled_features_are_named() {
+        const HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        // A function returning a v128 constant
+        let mut simd = HEADER.to_vec();
+        simd.extend_from_slice(&[0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7b, 0x03, 0x02, 0x01, 0x00]);
+        simd.extend_from_slice(&[0x0a, 0x16, 0x01, 0x14, 0x00, 0xfd, 0x0c]);
+        simd.extend_from_slice(&[0; 16]);
+        simd.push(0x0b);
+        // A shared memory
+        let mut threads = HEADER.to_vec();
+        threads.extend_from_slice(&[0x05, 0x04, 0x01, 0x03, 0x01, 0x01]);
+        // Two memories
+        let mut memories = HEADER.to_vec();
+        memories.extend_from_slice(&[0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x01]);
+
+        let defaults = EngineSettings::default();
+        assert!(check_features(&HEADER, &defaults).is_ok());
+        assert!(check_features(&simd, &defaults).is_ok());
+        let no_simd = EngineSettings {
+            simd: false,
+            ..EngineSettings::default()
+        };
+        match check_features(&simd, &no_simd).unwrap_err().kind() {
+            ErrorKind::FeatureNotEnabled(feature) => assert_eq!(feature, "simd"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        match check_features(&threads, &defaults).unwrap_err().kind() {
+            ErrorKind::FeatureNotEnabled(feature) => assert_eq!(feature, "threads"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        match check_features(&memories, &defaults).unwrap_err().kind() {
+            ErrorKind::FeatureNotEnabled(feature) => assert_eq!(feature, "multi_memory"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        let multi_memory = EngineSettings {
+            multi_memory: true,
+            ..EngineSettings::default()
+        };
+        assert!(check_features(&memories, &multi_memory).is_ok());
+    }
+
+    #[cfg(feature = "echo-guest")]
+    #[test]
+    fn echo_guest_is_compatible() {
+        let report = validate_module(crate::guests::ECHO).unwrap();
+        assert!(report.is_compatible(), "{:?}", report.problems);
+        assert!(!report.requires_wasi());
+        assert!(report.exports.iter().any(|e| e == WapcFunctions::GUEST_CALL));
+        assert!(report.nondeterministic_imports().is_empty());
+    }
+}
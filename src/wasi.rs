@@ -0,0 +1,261 @@
+//! A builder for [WasiParams](../struct.WasiParams.html) that names each setting and checks
+//! the configuration before any engine sees it.
+//!
+//! ```
+//! # use wapc::WasiParams;
+//! # let dir = std::env::temp_dir();
+//! let params = WasiParams::builder()
+//!     .arg("guest")
+//!     .env("LOG_LEVEL", "debug")
+//!     .map_dir("/data", dir.to_str().unwrap())
+//!     .inherit_stderr()
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(params.map_dirs.len(), 1);
+//! ```
+//!
+//! A builder starts from [deny_all](struct.WasiParamsBuilder.html#method.deny_all): no
+//! arguments, no environment, no directories and no access to the host's stdio, so every
+//! capability the guest gets is granted explicitly.
+//!
+//! Most embedders need one of a few configurations, which [WasiProfile](enum.WasiProfile.html)
+//! names so they need not be assembled by hand.
+
+use std::path::Path;
+
+use crate::errors::{self, ErrorKind};
+use crate::{Result, WasiParams};
+
+/// WASI filesystem functions, which a guest can only use with directories granted to it
+#[cfg(feature = "validate")]
+const FILESYSTEM_FUNCTIONS: [&str; 3] = ["path_open", "path_filestat_get", "fd_readdir"];
+
+/// A named WASI configuration, from granting nothing to granting everything the host process
+/// has
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WasiProfile {
+    /// The guest is run without WASI and must not import it
+    None,
+    /// WASI is available for clocks and randomness, but the guest gets no arguments,
+    /// environment, directories or stdio
+    PureCompute,
+    /// Like `PureCompute`, plus read-only access to the given host directories at the same
+    /// paths
+    ReadOnlyData(Vec<String>),
+    /// The guest inherits the host process's stdio and environment and can read and write
+    /// the current directory, mapped to `.`
+    Full,
+}
+
+impl WasiProfile {
+    /// Returns the parameters the profile grants, or `None` for
+    /// [WasiProfile::None](#variant.None). Fails like
+    /// [WasiParamsBuilder::build](struct.WasiParamsBuilder.html#method.build) if a granted
+    /// directory does not exist.
+    pub fn params(&self) -> Result<Option<WasiParams>> {
+        let builder = match self {
+            WasiProfile::None => return Ok(None),
+            WasiProfile::PureCompute => WasiParamsBuilder::deny_all(),
+            WasiProfile::ReadOnlyData(dirs) => dirs
+                .iter()
+                .fold(WasiParamsBuilder::deny_all(), |b, dir| b.preopen_dir_read_only(dir)),
+            WasiProfile::Full => {
+                let cwd = std::env::current_dir()?;
+                let builder = WasiParamsBuilder::deny_all()
+                    .inherit_stdio()
+                    .map_dir(".", &cwd.to_string_lossy());
+                // Windows keeps per-drive state in variables such as `=C:`, which WASI cannot carry
+                std::env::vars()
+                    .filter(|(key, _)| !key.is_empty() && !key.contains('='))
+                    .fold(builder, |b, (key, value)| b.env(&key, &value))
+            }
+        };
+        builder.build().map(Some)
+    }
+
+    /// Checks a module's WASI imports against the profile before it is loaded, failing with
+    /// `ErrorKind::WasiConfiguration` and a suggested profile if the module needs WASI the
+    /// profile withholds: any WASI import under `None`, or the filesystem under `PureCompute`.
+    #[cfg(feature = "validate")]
+    pub fn check(&self, report: &crate::validate::ModuleReport) -> Result<()> {
+        let needed = |names: &[&str]| -> Vec<&str> {
+            report
+                .wasi_imports
+                .iter()
+                .map(String::as_str)
+                .filter(|import| names.is_empty() || names.contains(import))
+                .collect()
+        };
+        let reason = match self {
+            WasiProfile::None if report.requires_wasi() => format!(
+                "module imports WASI ({}) but the profile is None; use PureCompute or a profile \
+                 that grants what it needs",
+                needed(&[]).join(", ")
+            ),
+            WasiProfile::PureCompute if !needed(&FILESYSTEM_FUNCTIONS).is_empty() => format!(
+                "module uses the WASI filesystem ({}) but PureCompute grants no directories; use \
+                 ReadOnlyData with the directories it reads",
+                needed(&FILESYSTEM_FUNCTIONS).join(", ")
+            ),
+            _ => return Ok(()),
+        };
+        Err(errors::new(ErrorKind::WasiConfiguration(reason)))
+    }
+}
+
+/// Builds [WasiParams](../struct.WasiParams.html), validating them in
+/// [build](#method.build)
+#[derive(Debug, Default)]
+pub struct WasiParamsBuilder {
+    params: WasiParams,
+}
+
+impl WasiParamsBuilder {
+    /// Creates a builder that grants the guest nothing
+    pub fn deny_all() -> WasiParamsBuilder {
+        WasiParamsBuilder::default()
+    }
+
+    /// Appends a command-line argument. The first argument is conventionally the program name.
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.params.argv.push(arg.to_string());
+        self
+    }
+
+    /// Appends several command-line arguments
+    pub fn args<S: AsRef<str>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.params
+            .argv
+            .extend(args.into_iter().map(|a| a.as_ref().to_string()));
+        self
+    }
+
+    /// Sets an environment variable
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.params.env_vars.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Gives the guest access to a host directory at the same path
+    pub fn preopen_dir(mut self, path: &str) -> Self {
+        self.params.preopened_dirs.push(path.to_string());
+        self
+    }
+
+    /// Gives the guest read-only access to a host directory at the same path
+    pub fn preopen_dir_read_only(mut self, path: &str) -> Self {
+        self.params.read_only_dirs.push(path.to_string());
+        self
+    }
+
+    /// Gives the guest access to the host directory `host_path` at `guest_path`
+    pub fn map_dir(mut self, guest_path: &str, host_path: &str) -> Self {
+        self.params
+            .map_dirs
+            .push((guest_path.to_string(), host_path.to_string()));
+        self
+    }
+
+    /// Lets the guest read the host process's stdin
+    pub fn inherit_stdin(mut self) -> Self {
+        self.params.inherit_stdin = true;
+        self
+    }
+
+    /// Lets the guest write to the host process's stdout
+    pub fn inherit_stdout(mut self) -> Self {
+        self.params.inherit_stdout = true;
+        self
+    }
+
+    /// Lets the guest write to the host process's stderr
+    pub fn inherit_stderr(mut self) -> Self {
+        self.params.inherit_stderr = true;
+        self
+    }
+
+    /// Lets the guest use all of the host process's stdio
+    pub fn inherit_stdio(self) -> Self {
+        self.inherit_stdin().inherit_stdout().inherit_stderr()
+    }
+
+    /// Checks the configuration and returns the parameters. Fails with
+    /// `ErrorKind::WasiConfiguration` listing every problem found: host directories that do
+    /// not exist, empty guest paths, and environment variable names that are empty or contain
+    /// `=` or NUL.
+    pub fn build(self) -> Result<WasiParams> {
+        let params = self.params;
+        let mut problems = Vec::new();
+        let host_dirs = params
+            .preopened_dirs
+            .iter()
+            .chain(params.read_only_dirs.iter())
+            .chain(params.map_dirs.iter().map(|(_, host)| host));
+        for dir in host_dirs {
+            if !Path::new(dir).is_dir() {
+                problems.push(format!("{} is not a directory", dir));
+            }
+        }
+        for (guest, _) in params.map_dirs.iter().filter(|(guest, _)| guest.is_empty()) {
+            problems.push(format!("mapped directory {:?} has an empty guest path", guest));
+        }
+        for (key, _) in params.env_vars.iter() {
+            if key.is_empty() || key.contains('=') || key.contains('\0') {
+                problems.push(format!("{:?} is not a valid environment variable name", key));
+            }
+        }
+        if problems.is_empty() {
+            Ok(params)
+        } else {
+            Err(errors::new(ErrorKind::WasiConfiguration(problems.join("; "))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_problem_is_reported() {
+        let missing = std::env::temp_dir().join("wapc-no-such-dir");
+        let err = WasiParams::builder()
+            .preopen_dir(missing.to_str().unwrap())
+            .env("A=B", "c")
+            .build()
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("wapc-no-such-dir is not a directory"));
+        assert!(message.contains("\"A=B\" is not a valid environment variable name"));
+
+        let params = WasiParamsBuilder::deny_all().build().unwrap();
+        assert!(params.argv.is_empty() && !params.inherit_stdout);
+    }
+
+    #[test]
+    fn profiles_grant_what_they_name() {
+        assert!(WasiProfile::None.params().unwrap().is_none());
+        let dir = std::env::temp_dir().to_string_lossy().into_owned();
+        let params = WasiProfile::ReadOnlyData(vec![dir.clone()])
+            .params()
+            .unwrap()
+            .unwrap();
+        assert_eq!(params.read_only_dirs, vec![dir]);
+        assert!(params.preopened_dirs.is_empty() && params.env_vars.is_empty());
+        assert!(WasiProfile::Full.params().unwrap().unwrap().inherit_stdin);
+    }
+
+    #[cfg(feature = "validate")]
+    #[test]
+    fn profiles_reject_modules_needing_more() {
+        let report = crate::validate::ModuleReport {
+            wasi_imports: vec!["fd_write".to_string(), "path_open".to_string()],
+            ..Default::default()
+        };
+        let none = WasiProfile::None.check(&report).unwrap_err();
+        assert!(none.to_string().contains("fd_write, path_open"));
+        let compute = WasiProfile::PureCompute.check(&report).unwrap_err();
+        assert!(compute.to_string().contains("use ReadOnlyData"));
+        assert!(WasiProfile::ReadOnlyData(vec![]).check(&report).is_ok());
+    }
+}
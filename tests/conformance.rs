@@ -0,0 +1,245 @@
+//! Protocol conformance tests for `WapcHost`, driven through its public API.
+//!
+//! No WebAssembly engine ships with this crate, so the guests here are scripted: the engine
+//! provider below treats the module bytes as the name of a guest behavior and performs the
+//! same sequence of host imports that a compiled guest of that kind would. Engine providers
+//! can run these scenarios against real guests by substituting their own provider.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use wapc::errors::{self, ErrorKind};
+use wapc::guest_error::GuestErrorClass;
+use wapc::{
+    InterruptHandle, ModuleState, WapcFunctions, WapcHost, WapcHostBuilder,
+    WebAssemblyEngineProvider,
+};
+
+type EngineResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// An engine provider whose "module" is the name of a scripted guest
+struct ScriptedEngine {
+    state: Option<Arc<ModuleState>>,
+    interrupted: Arc<AtomicBool>,
+    guest: Vec<u8>,
+    /// Calls handled since the guest was instantiated
+    calls: u32,
+    /// Fuel remaining, consumed at one unit per payload byte
+    fuel: Option<u64>,
+}
+
+impl ScriptedEngine {
+    fn boxed(guest: &[u8]) -> Box<dyn WebAssemblyEngineProvider> {
+        Box::new(ScriptedEngine {
+            state: None,
+            interrupted: Arc::new(AtomicBool::new(false)),
+            guest: guest.to_vec(),
+            calls: 0,
+            fuel: None,
+        })
+    }
+}
+
+impl WebAssemblyEngineProvider for ScriptedEngine {
+    fn init(&mut self, host: Arc<ModuleState>) -> EngineResult<()> {
+        if self.guest == b"not-wapc" {
+            return Err(Box::new(errors::new(ErrorKind::MissingRequiredExport {
+                required: WapcFunctions::GUEST_CALL.to_string(),
+                found: vec!["_start".to_string(), "memory".to_string()],
+            })));
+        }
+        if self.guest == b"hang" {
+            // A start function that loops until the engine interrupts it
+            while !self.interrupted.load(Ordering::SeqCst) {
+                std::thread::yield_now();
+            }
+            return Err("interrupted".into());
+        }
+        self.state = Some(host);
+        Ok(())
+    }
+
+    fn call(&mut self, op_length: i32, msg_length: i32) -> EngineResult<i32> {
+        let state = self.state.as_ref().unwrap();
+        let inv = state.get_guest_request().unwrap();
+        assert_eq!(op_length as usize, inv.operation.len());
+        assert_eq!(msg_length as usize, inv.msg.len());
+        self.calls += 1;
+        if let Some(ref mut fuel) = self.fuel {
+            *fuel = fuel
+                .checked_sub(inv.msg.len() as u64)
+                .ok_or("all fuel consumed")?;
+        }
+
+        Ok(match &self.guest[..] {
+            b"echo" => {
+                state.set_guest_response(inv.msg.to_vec());
+                1
+            }
+            b"reverse" => {
+                state.set_guest_response(inv.msg.iter().rev().cloned().collect());
+                1
+            }
+            b"reject" => {
+                state.set_guest_error(format!("[bad_request] cannot handle {}", inv.operation));
+                0
+            }
+            b"relay" => match state.do_host_call("default", "test", &inv.operation, &inv.msg) {
+                Ok(1) => {
+                    state.set_guest_response(state.get_host_response().unwrap());
+                    1
+                }
+                _ => {
+                    state.set_guest_error(state.get_host_error().unwrap());
+                    0
+                }
+            },
+            b"counter" => {
+                state.set_guest_response(self.calls.to_string().into_bytes());
+                1
+            }
+            b"trap" => return Err("unreachable executed".into()),
+            other => panic!("unknown scripted guest {:?}", other),
+        })
+    }
+
+    fn replace(&mut self, bytes: &[u8]) -> EngineResult<()> {
+        self.guest = bytes.to_vec();
+        self.calls = 0;
+        Ok(())
+    }
+
+    fn reset(&mut self) -> EngineResult<()> {
+        self.calls = 0;
+        Ok(())
+    }
+
+    fn set_fuel(&mut self, fuel: Option<u64>) -> EngineResult<()> {
+        self.fuel = fuel;
+        Ok(())
+    }
+
+    fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    fn interrupt_handle(&self) -> Option<Arc<dyn InterruptHandle>> {
+        Some(Arc::new(Interrupter(self.interrupted.clone())))
+    }
+}
+
+struct Interrupter(Arc<AtomicBool>);
+
+impl InterruptHandle for Interrupter {
+    fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+fn host(guest: &[u8]) -> WapcHost {
+    WapcHost::new(ScriptedEngine::boxed(guest), |_, _, _, op, payload| match op {
+        "fail" => Err("host refused the call".into()),
+        _ => Ok(payload.to_vec()),
+    })
+    .unwrap()
+}
+
+#[test]
+fn successful_call_returns_guest_response() {
+    assert_eq!(host(b"echo").call("Echo", b"hello").unwrap(), b"hello");
+}
+
+#[test]
+fn guest_error_is_reported_with_its_class() {
+    let err = host(b"reject").call("Greet", b"").unwrap_err();
+    let guest = err.guest_error().unwrap();
+    assert_eq!(guest.class, GuestErrorClass::BadRequest);
+    assert_eq!(guest.message, "cannot handle Greet");
+}
+
+#[test]
+fn host_error_propagates_through_guest() {
+    let host = host(b"relay");
+    assert_eq!(host.call("ok", b"payload").unwrap(), b"payload");
+
+    let err = host.call("fail", b"payload").unwrap_err();
+    match err.kind() {
+        ErrorKind::GuestCallFailure(reason) => assert!(reason.contains("host refused the call")),
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert_eq!(err.module_id(), Some(host.id()));
+}
+
+#[test]
+fn trap_fails_the_call() {
+    let err = host(b"trap").call("Anything", b"").unwrap_err();
+    assert!(err.to_string().contains("unreachable executed"));
+}
+
+#[test]
+fn large_payloads_round_trip() {
+    let payload: Vec<u8> = (0..8 * 1024 * 1024).map(|i| i as u8).collect();
+    assert_eq!(host(b"relay").call("ok", &payload).unwrap(), payload);
+}
+
+#[test]
+fn hot_swap_changes_behavior_and_keeps_id() {
+    let host = host(b"echo");
+    let id = host.id();
+    assert_eq!(host.call("Echo", b"abc").unwrap(), b"abc");
+
+    host.replace_module(b"reverse").unwrap();
+    assert_eq!(host.call("Echo", b"abc").unwrap(), b"cba");
+    assert_eq!(host.id(), id);
+}
+
+#[test]
+fn missing_guest_call_lists_found_exports() {
+    let err = WapcHost::new(ScriptedEngine::boxed(b"not-wapc"), |_, _, _, _, _| Ok(vec![]))
+        .err()
+        .unwrap();
+    match err.kind() {
+        ErrorKind::MissingRequiredExport { required, found } => {
+            assert_eq!(required, "__guest_call");
+            assert_eq!(found, &["_start", "memory"]);
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert!(err.module_id().is_some());
+}
+
+#[test]
+fn recycles_after_configured_calls() {
+    let host = WapcHostBuilder::new()
+        .recycle_after_calls(2)
+        .build(ScriptedEngine::boxed(b"counter"))
+        .unwrap();
+    let counts: Vec<Vec<u8>> = (0..5).map(|_| host.call("Count", b"").unwrap()).collect();
+    assert_eq!(counts, [b"1", b"2", b"1", b"2", b"1"]);
+}
+
+#[test]
+fn hung_start_function_times_out() {
+    let err = WapcHostBuilder::new()
+        .init_timeout(Duration::from_millis(50))
+        .build(ScriptedEngine::boxed(b"hang"))
+        .err()
+        .unwrap();
+    match err.kind() {
+        ErrorKind::GuestInitTimeout(timeout) => assert_eq!(*timeout, Duration::from_millis(50)),
+        other => panic!("unexpected error {:?}", other),
+    }
+}
+
+#[test]
+fn fuel_consumption_is_metered() {
+    let host = host(b"echo");
+    let (result, consumed) = host.call_with_fuel("Echo", b"12345", 100);
+    assert_eq!(result.unwrap(), b"12345");
+    assert_eq!(consumed, 5);
+
+    let (result, _) = host.call_with_fuel("Echo", b"12345", 3);
+    assert!(result.is_err());
+    assert_eq!(host.fuel_consumed(), 5);
+}
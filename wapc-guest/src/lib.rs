@@ -0,0 +1,245 @@
+//! # wapc-guest
+//!
+//! The guest-side SDK for waPC. It implements the `__guest_call` export required by the waPC
+//! protocol, dispatches incoming calls to the handlers registered with
+//! [register_function](fn.register_function.html) (or the
+//! [register_functions!](macro.register_functions.html) macro), and wraps the host imports in
+//! safe functions such as [host_call](fn.host_call.html) and [console_log](fn.console_log.html).
+//!
+//! Handler errors are passed to the host through `__guest_error`, prefixed with the class tag
+//! that the host's `wapc::guest_error` module decodes, e.g. `[bad_request] missing field`.
+//! Build an [Error](struct.Error.html) with a class to opt in; plain errors are sent untagged.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::RwLock;
+
+/// The result returned by an operation handler
+pub type CallResult = std::result::Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The signature of an operation handler
+pub type HandlerSignature = fn(&[u8]) -> CallResult;
+
+pub mod prelude {
+    pub use crate::{
+        console_log, host_call, register_function, register_functions, CallResult, Error,
+        ErrorClass, HandlerSignature,
+    };
+}
+
+static REGISTRY: RwLock<Option<HashMap<String, HandlerSignature>>> = RwLock::new(None);
+
+/// Registers the handler invoked when the host calls the given operation
+pub fn register_function(operation: &str, handler: HandlerSignature) {
+    REGISTRY
+        .write()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(operation.to_string(), handler);
+}
+
+/// Registers several operation handlers at once. Expands to a `wapc_init` export, which the
+/// host invokes during initialization, so handlers are in place before the first call.
+///
+/// ```ignore
+/// register_functions! {
+///     "Hello" => hello,
+///     "Goodbye" => goodbye,
+/// }
+/// ```
+#[macro_export]
+macro_rules! register_functions {
+    ($($operation:expr => $handler:expr),* $(,)?) => {
+        #[no_mangle]
+        pub extern "C" fn wapc_init() {
+            $($crate::register_function($operation, $handler);)*
+        }
+    };
+}
+
+/// The class of failure reported to the host, matching the host's `GuestErrorClass`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    UserError,
+    InternalError,
+    UnsupportedOperation,
+    BadRequest,
+}
+
+impl ErrorClass {
+    fn tag(self) -> &'static str {
+        match self {
+            ErrorClass::UserError => "user_error",
+            ErrorClass::InternalError => "internal_error",
+            ErrorClass::UnsupportedOperation => "unsupported_operation",
+            ErrorClass::BadRequest => "bad_request",
+        }
+    }
+}
+
+/// An error carrying the class the host uses to categorize guest failures
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub class: ErrorClass,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new(class: ErrorClass, message: impl Into<String>) -> Error {
+        Error {
+            class,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.class.tag(), self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Dispatches an operation to its registered handler, returning the response or the error
+/// message to report to the host
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+fn dispatch(operation: &str, msg: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let handler = REGISTRY
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|r| r.get(operation).copied());
+    match handler {
+        Some(handler) => handler(msg).map_err(|e| e.to_string()),
+        None => Err(Error::new(
+            ErrorClass::UnsupportedOperation,
+            format!("No handler registered for function \"{}\"", operation),
+        )
+        .to_string()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod imports {
+    #[link(wasm_import_module = "wapc")]
+    extern "C" {
+        pub fn __console_log(ptr: *const u8, len: usize);
+        pub fn __host_call(
+            bd_ptr: *const u8,
+            bd_len: usize,
+            ns_ptr: *const u8,
+            ns_len: usize,
+            op_ptr: *const u8,
+            op_len: usize,
+            ptr: *const u8,
+            len: usize,
+        ) -> usize;
+        pub fn __host_response(ptr: *mut u8);
+        pub fn __host_response_len() -> usize;
+        pub fn __host_error_len() -> usize;
+        pub fn __host_error(ptr: *mut u8);
+        pub fn __guest_response(ptr: *const u8, len: usize);
+        pub fn __guest_error(ptr: *const u8, len: usize);
+        pub fn __guest_request(op_ptr: *mut u8, ptr: *mut u8);
+    }
+}
+
+/// The entry point the host invokes for every call
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn __guest_call(op_len: i32, req_len: i32) -> i32 {
+    use imports::*;
+
+    let mut op = vec![0u8; op_len as usize];
+    let mut msg = vec![0u8; req_len as usize];
+    unsafe { __guest_request(op.as_mut_ptr(), msg.as_mut_ptr()) };
+    let op = String::from_utf8_lossy(&op);
+
+    match dispatch(&op, &msg) {
+        Ok(response) => {
+            unsafe { __guest_response(response.as_ptr(), response.len()) };
+            1
+        }
+        Err(error) => {
+            unsafe { __guest_error(error.as_ptr(), error.len()) };
+            0
+        }
+    }
+}
+
+/// Performs a call to the host, returning the host's response or its error
+#[cfg(target_arch = "wasm32")]
+pub fn host_call(binding: &str, namespace: &str, operation: &str, msg: &[u8]) -> CallResult {
+    use imports::*;
+
+    let success = unsafe {
+        __host_call(
+            binding.as_ptr(),
+            binding.len(),
+            namespace.as_ptr(),
+            namespace.len(),
+            operation.as_ptr(),
+            operation.len(),
+            msg.as_ptr(),
+            msg.len(),
+        )
+    };
+    if success == 0 {
+        let mut error = vec![0u8; unsafe { __host_error_len() }];
+        unsafe { __host_error(error.as_mut_ptr()) };
+        Err(String::from_utf8_lossy(&error).into_owned().into())
+    } else {
+        let mut response = vec![0u8; unsafe { __host_response_len() }];
+        unsafe { __host_response(response.as_mut_ptr()) };
+        Ok(response)
+    }
+}
+
+/// Performs a call to the host. Outside of WebAssembly there is no host, so this always fails.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn host_call(_binding: &str, _namespace: &str, operation: &str, _msg: &[u8]) -> CallResult {
+    Err(format!(
+        "Cannot call host operation \"{}\" outside of a waPC host",
+        operation
+    )
+    .into())
+}
+
+/// Writes a message to the host's log
+#[cfg(target_arch = "wasm32")]
+pub fn console_log(msg: &str) {
+    unsafe { imports::__console_log(msg.as_ptr(), msg.len()) };
+}
+
+/// Writes a message to the host's log. Outside of WebAssembly this prints to standard error.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn console_log(msg: &str) {
+    eprintln!("{}", msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shout(msg: &[u8]) -> CallResult {
+        if msg.is_empty() {
+            return Err(Error::new(ErrorClass::BadRequest, "empty message").into());
+        }
+        Ok(msg.to_ascii_uppercase())
+    }
+
+    #[test]
+    fn dispatches_registered_operations() {
+        register_function("shout", shout);
+
+        assert_eq!(dispatch("shout", b"hi").unwrap(), b"HI");
+        assert_eq!(
+            dispatch("shout", b"").unwrap_err(),
+            "[bad_request] empty message"
+        );
+        assert!(dispatch("whisper", b"hi")
+            .unwrap_err()
+            .starts_with("[unsupported_operation]"));
+    }
+}